@@ -0,0 +1,10 @@
+fn main() {
+    // Generate the gRPC service from the proto definitions, using the bundled protoc unless PROTOC is set
+    #[cfg(feature = "grpc")]
+    {
+        if std::env::var_os("PROTOC").is_none() {
+            std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        }
+        tonic_build::compile_protos("proto/phi.proto").unwrap();
+    }
+}
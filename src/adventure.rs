@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::crafter::Crafter;
+use crate::generation::GenerationConfig;
+use crate::scene::Scene;
+
+/// Default maximum number of tokens of narration after each command
+pub const DEFAULT_NARRATION_TOKENS: usize = 64;
+/// Maximum number of tokens in the name of an item the player takes
+pub const MAX_TAKEN_ITEM_TOKENS: usize = 8;
+/// The world state key the player's inventory is kept under
+pub const INVENTORY_KEY: &str = "inventory";
+
+/// What kind of command the player typed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CommandKind {
+    Look,
+    Go,
+    Take,
+    Drop,
+    Combine,
+    Talk,
+    Inventory,
+    Other,
+}
+
+impl CommandKind {
+    pub const ALL: [CommandKind; 8] = [
+        CommandKind::Look,
+        CommandKind::Go,
+        CommandKind::Take,
+        CommandKind::Drop,
+        CommandKind::Combine,
+        CommandKind::Talk,
+        CommandKind::Inventory,
+        CommandKind::Other,
+    ];
+}
+
+impl Display for CommandKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandKind::Look => write!(f, "look"),
+            CommandKind::Go => write!(f, "go"),
+            CommandKind::Take => write!(f, "take"),
+            CommandKind::Drop => write!(f, "drop"),
+            CommandKind::Combine => write!(f, "combine"),
+            CommandKind::Talk => write!(f, "talk"),
+            CommandKind::Inventory => write!(f, "inventory"),
+            CommandKind::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// What happened after a command
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdventureTurn {
+    pub command: CommandKind,
+    /// The text shown to the player
+    pub narration: String,
+    /// Items added to the inventory
+    pub gained: Vec<String>,
+    /// Items removed from the inventory
+    pub lost: Vec<String>,
+}
+
+/// A text adventure: player commands are classified and routed to the inventory,
+/// a `Crafter` for combining items, and a `Scene` for narrating everything else
+pub struct Adventure {
+    scene: Scene,
+    crafter: Crafter,
+    inventory: Vec<String>,
+    pub narration_tokens: usize,
+    seed: u64,
+}
+
+impl Adventure {
+    pub fn new(scene: Scene, crafter: Crafter) -> Self {
+        let mut adventure = Self {
+            scene,
+            crafter,
+            inventory: Vec::new(),
+            narration_tokens: DEFAULT_NARRATION_TOKENS,
+            seed: 0,
+        };
+        adventure.update_inventory_state();
+        adventure
+    }
+
+    pub fn with_inventory(mut self, items: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.inventory = items.into_iter().map(Into::into).collect();
+        self.update_inventory_state();
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_narration_tokens(mut self, narration_tokens: usize) -> Self {
+        self.narration_tokens = narration_tokens;
+        self
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    pub fn crafter(&self) -> &Crafter {
+        &self.crafter
+    }
+
+    pub fn inventory(&self) -> &[String] {
+        &self.inventory
+    }
+
+    /// Run a command typed by the player, such as "combine the rope and the hook".
+    /// Returns an error if the command is empty.
+    pub fn command(&mut self, input: impl AsRef<str>) -> Result<AdventureTurn> {
+        let input = input.as_ref().trim();
+        if input.is_empty() {
+            bail!("cannot run an empty command")
+        }
+        let seed = self.next_seed();
+
+        // Route the command
+        let command = self
+            .scene
+            .model()
+            .classify(input, CommandKind::ALL, &GenerationConfig::new(seed))
+            .unwrap_or(CommandKind::Other);
+        let mut turn = AdventureTurn {
+            command,
+            narration: String::new(),
+            gained: Vec::new(),
+            lost: Vec::new(),
+        };
+        match command {
+            CommandKind::Inventory => {
+                turn.narration = if self.inventory.is_empty() {
+                    "You are carrying nothing.".to_string()
+                } else {
+                    format!("You are carrying: {}.", self.inventory.join(", "))
+                };
+                return Ok(turn);
+            }
+            CommandKind::Combine => {
+                let items = mentioned_items(&self.inventory, input);
+                if items.len() < 2 {
+                    turn.narration =
+                        "You need at least two items you're carrying to combine.".to_string();
+                    return Ok(turn);
+                }
+
+                // Craft the result and swap it for the ingredients
+                let result = self.crafter.craft_item(&items, seed)?;
+                self.inventory.retain(|item| !items.contains(item));
+                self.inventory.push(result.clone());
+                turn.lost = items;
+                turn.gained = vec![result];
+            }
+            CommandKind::Take => {
+                let item = self.taken_item(input, seed)?;
+                if !item.is_empty() {
+                    self.inventory.push(item.clone());
+                    turn.gained.push(item);
+                }
+            }
+            CommandKind::Drop => {
+                let items = mentioned_items(&self.inventory, input);
+                self.inventory.retain(|item| !items.contains(item));
+                turn.lost = items;
+            }
+            CommandKind::Look | CommandKind::Go | CommandKind::Talk | CommandKind::Other => {}
+        }
+        self.update_inventory_state();
+
+        // Narrate what happens
+        let mut action = input.to_string();
+        if !turn.lost.is_empty() || !turn.gained.is_empty() {
+            action.push_str(&format!(
+                " (the player loses {} and gains {})",
+                none_if_empty(&turn.lost),
+                none_if_empty(&turn.gained)
+            ));
+        }
+        self.scene.push_player_action(action);
+        turn.narration = self
+            .scene
+            .respond_to_player(self.narration_tokens)?
+            .text()
+            .to_string();
+
+        Ok(turn)
+    }
+
+    /// Ask the model which item the player picks up
+    fn taken_item(&self, input: &str, seed: u64) -> Result<String> {
+        let mut extra = HashMap::new();
+        extra.insert("Setting", self.scene.setting());
+        extra.insert("Command", input);
+        extra.insert("Response", "Item:");
+        let config = GenerationConfig::new(seed)
+            .with_max_tokens(MAX_TAKEN_ITEM_TOKENS)
+            .with_stop("\n");
+        let item = self.scene.model().instruct_with(
+            "Name the item the player picks up. Answer with only its name.",
+            Some(&extra),
+            &config,
+        )?;
+        Ok(item
+            .trim()
+            .trim_matches(|c: char| c.is_ascii_punctuation())
+            .trim()
+            .to_string())
+    }
+
+    /// Keep the inventory in the scene's world state so narration knows about it
+    fn update_inventory_state(&mut self) {
+        self.scene
+            .state_mut()
+            .set(INVENTORY_KEY, none_if_empty(&self.inventory));
+    }
+
+    fn next_seed(&mut self) -> u64 {
+        let seed = self.seed;
+        self.seed = self.seed.wrapping_add(1);
+        seed
+    }
+}
+
+/// Get the items of the inventory mentioned in the text, ignoring case
+pub(crate) fn mentioned_items(inventory: &[String], text: &str) -> Vec<String> {
+    let text = text.to_lowercase();
+    inventory
+        .iter()
+        .filter(|item| text.contains(&item.to_lowercase()))
+        .unique()
+        .cloned()
+        .collect()
+}
+
+fn none_if_empty(items: &[String]) -> String {
+    if items.is_empty() {
+        "nothing".to_string()
+    } else {
+        items.join(", ")
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::{Model, MAX_TOKENS};
+
+/// The action an agent takes to stop and give its final answer
+pub const FINISH_ACTION: &str = "finish";
+/// Default maximum number of think, act and observe steps before the agent must answer
+pub const DEFAULT_MAX_STEPS: usize = 8;
+/// Default maximum number of tokens the agent may generate over a whole run
+pub const DEFAULT_TOKEN_BUDGET: usize = 1024;
+/// Maximum number of tokens in a single thought
+pub const MAX_THOUGHT_TOKENS: usize = 96;
+/// Maximum number of tokens in the input to a tool or the final answer
+pub const MAX_INPUT_TOKENS: usize = 128;
+/// Maximum number of characters of a tool's output shown to the agent
+pub const MAX_OBSERVATION_CHARS: usize = 1000;
+/// Room left in the prompt for the labels and action name of a step, besides its thought and input
+const STEP_LABEL_TOKENS: usize = 32;
+/// Shown instead of observations that were left out to keep the prompt within the context window
+const OMITTED_OBSERVATION: &str = "(left out)";
+
+/// Something an `Agent` can use to act on the world or look things up
+pub trait Tool: Send + Sync {
+    /// The name the agent calls the tool by, without spaces
+    fn name(&self) -> &str;
+
+    /// What the tool does and what input it expects
+    fn description(&self) -> &str;
+
+    /// Run the tool on the agent's input, returning what the agent observes
+    fn call(&self, input: &str) -> Result<String>;
+}
+
+/// A `Tool` that calls a function
+pub struct FnTool<F> {
+    pub name: String,
+    pub description: String,
+    pub function: F,
+}
+
+impl<F: Fn(&str) -> Result<String> + Send + Sync> Tool for FnTool<F> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn call(&self, input: &str) -> Result<String> {
+        (self.function)(input)
+    }
+}
+
+/// One think, act and observe step of an agent's run
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentStep {
+    pub thought: String,
+    /// The name of the tool used, or `FINISH_ACTION`
+    pub action: String,
+    pub input: String,
+    /// What the tool returned, or None for the final step
+    pub observation: Option<String>,
+}
+
+/// The result of `Agent::run`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentRun {
+    /// Every step the agent took, in order
+    pub transcript: Vec<AgentStep>,
+    pub answer: String,
+    /// Number of tokens generated over the run
+    pub tokens_used: usize,
+    /// Whether the agent was forced to answer because it ran out of steps, tokens or room in the context window
+    pub exhausted: bool,
+}
+
+/// Solves tasks by thinking, using tools and observing their results in a loop until it can answer
+pub struct Agent {
+    model: Model,
+    /// Who the agent is and how it behaves
+    pub persona: String,
+    tools: Vec<Box<dyn Tool>>,
+    pub max_steps: usize,
+    pub token_budget: usize,
+}
+
+impl Agent {
+    pub fn new(model: Model, persona: impl Into<String>) -> Self {
+        Self {
+            model,
+            persona: persona.into(),
+            tools: Vec::new(),
+            max_steps: DEFAULT_MAX_STEPS,
+            token_budget: DEFAULT_TOKEN_BUDGET,
+        }
+    }
+
+    /// Add a tool the agent can use. Tools with the same name as an earlier tool replace it.
+    pub fn with_tool(mut self, tool: impl Tool + 'static) -> Self {
+        self.tools.retain(|other| other.name() != tool.name());
+        self.tools.push(Box::new(tool));
+        self
+    }
+
+    /// Add a tool that calls a function
+    pub fn with_fn_tool(
+        self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        function: impl Fn(&str) -> Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.with_tool(FnTool {
+            name: name.into(),
+            description: description.into(),
+            function,
+        })
+    }
+
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn with_token_budget(mut self, token_budget: usize) -> Self {
+        self.token_budget = token_budget;
+        self
+    }
+
+    /// Get the names of the tools the agent can use
+    pub fn tools(&self) -> impl Iterator<Item = &str> {
+        self.tools.iter().map(|tool| tool.name())
+    }
+
+    /// Work on the task until the agent finishes or runs out of steps or tokens,
+    /// in which case it must answer with what it has observed so far.
+    /// The oldest observations are left out of the prompt once another step wouldn't fit in the context window,
+    /// and the agent must answer if it still wouldn't.
+    /// Tool errors are shown to the agent as observations.
+    /// Returns an error if the task is empty or a tool name contains whitespace.
+    pub fn run(&self, task: impl AsRef<str>, seed: u64) -> Result<AgentRun> {
+        let task = task.as_ref().trim();
+        if task.is_empty() {
+            bail!("cannot run an agent without a task")
+        }
+        if let Some(tool) = self
+            .tools
+            .iter()
+            .find(|tool| tool.name().is_empty() || tool.name().contains(char::is_whitespace))
+        {
+            bail!("tool name \"{}\" must be one word", tool.name())
+        }
+
+        // Describe the tools and how to use them
+        let tools = self
+            .tools
+            .iter()
+            .map(|tool| format!("- {}: {}", tool.name(), tool.description()))
+            .chain([format!(
+                "- {}: Give the final answer as the input.",
+                FINISH_ACTION
+            )])
+            .join("\n");
+        let instruction = "Solve the task one step at a time. For every step write a thought, \
+            then choose an action from the tools and write its input on one line. \
+            The result of the action is shown as an observation.";
+        let mut extra = HashMap::new();
+        extra.insert("Persona", self.persona.clone());
+        extra.insert("Tools", tools);
+        extra.insert("Task", task.to_string());
+
+        let mut actions = self
+            .tools
+            .iter()
+            .map(|tool| format!(" {}", tool.name()))
+            .collect_vec();
+        actions.push(format!(" {}", FINISH_ACTION));
+        let mut transcript = Vec::new();
+        let mut omitted = 0;
+        let mut tokens_used = 0;
+        for step in 0.. {
+            let seed = seed.wrapping_add(step as u64);
+
+            // Leave out the oldest observations until there is room for a whole step
+            let step_tokens = MAX_THOUGHT_TOKENS + MAX_INPUT_TOKENS + STEP_LABEL_TOKENS;
+            let (mut scratchpad, prompt_tokens) = loop {
+                let scratchpad = write_scratchpad(&transcript, omitted);
+                extra.insert("Response", scratchpad.clone());
+                let prompt_tokens = self
+                    .model
+                    .create_instruct_prompt(instruction, Some(&extra))
+                    .len();
+                if prompt_tokens + step_tokens <= MAX_TOKENS || omitted >= transcript.len() {
+                    break (scratchpad, prompt_tokens);
+                }
+                omitted += 1;
+            };
+
+            let exhausted = step >= self.max_steps
+                || tokens_used >= self.token_budget
+                || prompt_tokens + step_tokens > MAX_TOKENS;
+            let remaining = self.token_budget.saturating_sub(tokens_used);
+
+            // Think, unless the agent must answer now
+            let thought = if exhausted {
+                "I must give my final answer now.".to_string()
+            } else {
+                extra.insert("Response", format!("{}Thought:", scratchpad));
+                let config = GenerationConfig::new(seed)
+                    .with_temp(0.3)
+                    .with_max_tokens(MAX_THOUGHT_TOKENS.min(remaining))
+                    .with_stop("\n");
+                let thought = self
+                    .model
+                    .instruct_with(instruction, Some(&extra), &config)?;
+                tokens_used += self.model.tokenize_str(&thought).len();
+                thought.trim().to_string()
+            };
+            scratchpad.push_str(&format!("Thought: {}\nAction:", thought));
+
+            // Choose an action, only allowing the names of the tools
+            let action = if exhausted {
+                FINISH_ACTION.to_string()
+            } else {
+                extra.insert("Response", scratchpad.clone());
+                let prompt = self.model.create_instruct_prompt(instruction, Some(&extra));
+                tokens_used += 1;
+                self.model
+                    .choose_by_trie(prompt, &actions, "\n", seed, 0.0)
+                    .map(|action| action.trim().to_string())
+                    .unwrap_or_else(|| FINISH_ACTION.to_string())
+            };
+            scratchpad.push_str(&format!(" {}\nInput:", action));
+
+            // Write the input to the action
+            extra.insert("Response", scratchpad.clone());
+            let max_tokens = if exhausted {
+                // Answer in whatever room the context window has left
+                let room = MAX_TOKENS.saturating_sub(prompt_tokens + STEP_LABEL_TOKENS);
+                MAX_INPUT_TOKENS.min(room.max(1))
+            } else {
+                MAX_INPUT_TOKENS.min(remaining.max(1))
+            };
+            let config = GenerationConfig::new(seed)
+                .with_temp(0.3)
+                .with_max_tokens(max_tokens)
+                .with_stop("\n");
+            let input = self
+                .model
+                .instruct_with(instruction, Some(&extra), &config)?;
+            tokens_used += self.model.tokenize_str(&input).len();
+            let input = input.trim().to_string();
+
+            // Finish with the input as the answer
+            if action == FINISH_ACTION {
+                transcript.push(AgentStep {
+                    thought,
+                    action,
+                    input: input.clone(),
+                    observation: None,
+                });
+                return Ok(AgentRun {
+                    transcript,
+                    answer: input,
+                    tokens_used,
+                    exhausted,
+                });
+            }
+
+            // Use the tool and show the agent what happened
+            let tool = self
+                .tools
+                .iter()
+                .find(|tool| tool.name() == action)
+                .unwrap();
+            let observation = match tool.call(&input) {
+                Ok(output) => output.trim().chars().take(MAX_OBSERVATION_CHARS).collect(),
+                Err(error) => format!("Error: {}", error),
+            };
+            transcript.push(AgentStep {
+                thought,
+                action,
+                input,
+                observation: Some(observation),
+            });
+        }
+
+        unreachable!()
+    }
+}
+
+/// Write the steps the way the agent sees them, leaving out the first `omitted` observations
+fn write_scratchpad(transcript: &[AgentStep], omitted: usize) -> String {
+    let mut scratchpad = String::new();
+    for (index, step) in transcript.iter().enumerate() {
+        scratchpad.push_str(&format!(
+            "Thought: {}\nAction: {}\nInput: {}\n",
+            step.thought, step.action, step.input
+        ));
+        if let Some(observation) = &step.observation {
+            let observation = if index < omitted {
+                OMITTED_OBSERVATION.to_string()
+            } else {
+                observation.replace('\n', " ")
+            };
+            scratchpad.push_str(&format!("Observation: {}\n", observation));
+        }
+    }
+    scratchpad
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Default maximum number of tokens in an answer, used when the config doesn't set one
+pub const MAX_ANSWER_TOKENS: usize = 128;
+/// Tokens kept free for the instruction and the prompt's headings
+const PROMPT_OVERHEAD_TOKENS: usize = 96;
+/// What the model is told to answer when the documents don't contain the answer
+pub const UNSUPPORTED_ANSWER: &str = "The documents don't say.";
+
+/// The answer to a question about some documents
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Answer {
+    pub text: String,
+    /// Whether the answer comes from the documents rather than the model's own knowledge
+    pub supported: bool,
+}
+
+impl Model {
+    /// Answer the question using only the documents.
+    /// Documents are included in order until the prompt is full, and the last one that fits is cut short.
+    /// If the documents don't contain the answer, the model refuses with `UNSUPPORTED_ANSWER`
+    /// and the answer is marked as unsupported.
+    /// Returns an error if the question is empty.
+    pub fn answer(
+        &self,
+        context_docs: impl IntoIterator<Item = impl AsRef<str>>,
+        question: impl AsRef<str>,
+        config: &GenerationConfig,
+    ) -> Result<Answer> {
+        let question = question.as_ref().trim();
+        if question.is_empty() {
+            bail!("cannot answer an empty question")
+        }
+        let config = self.answer_config(config);
+
+        // Fit the documents into the space left over
+        let budget = self
+            .max_tokens()
+            .saturating_sub(config.max_tokens.unwrap_or(MAX_ANSWER_TOKENS))
+            .saturating_sub(self.tokenize_str(question).len())
+            .saturating_sub(PROMPT_OVERHEAD_TOKENS);
+        let documents = self.fit_documents(context_docs, budget);
+
+        // Ask for an answer from the documents only
+        let instruction = format!(
+            "Answer the question using only the documents. If the documents don't contain the answer, reply exactly: {}",
+            UNSUPPORTED_ANSWER
+        );
+        let mut extra = HashMap::new();
+        extra.insert("Documents", documents.as_str());
+        extra.insert("Question", question);
+        let text = self.instruct_with(instruction, Some(&extra), &config)?;
+        let text = text.trim().to_string();
+
+        // Check whether the model refused
+        let supported = !text.is_empty() && !is_unsupported(&text);
+        Ok(Answer { text, supported })
+    }
+
+    /// Answer the question like `answer`, but if the documents don't contain the answer,
+    /// let the model answer from its own knowledge instead of refusing. Such answers are marked as unsupported.
+    pub fn answer_anyway(
+        &self,
+        context_docs: impl IntoIterator<Item = impl AsRef<str>>,
+        question: impl AsRef<str>,
+        config: &GenerationConfig,
+    ) -> Result<Answer> {
+        let answer = self.answer(context_docs, question.as_ref(), config)?;
+        if answer.supported {
+            return Ok(answer);
+        }
+
+        // Ask again without the documents
+        let mut extra = HashMap::new();
+        extra.insert("Question", question.as_ref().trim());
+        let text = self.instruct_with(
+            "Answer the question as well as you can.",
+            Some(&extra),
+            &self.answer_config(config),
+        )?;
+
+        Ok(Answer {
+            text: text.trim().to_string(),
+            supported: false,
+        })
+    }
+
+    /// Copy the config, limiting the answer to `MAX_ANSWER_TOKENS` and the current section if it doesn't already
+    fn answer_config(&self, config: &GenerationConfig) -> GenerationConfig {
+        let mut config = config.clone();
+        config.max_tokens = Some(config.max_tokens.unwrap_or(MAX_ANSWER_TOKENS));
+        if !config.stop.iter().any(|stop| stop == "###") {
+            config.stop.push("###".to_string());
+        }
+        config
+    }
+
+    /// Number the documents like "[1] document", keeping to `budget` tokens
+    fn fit_documents(
+        &self,
+        context_docs: impl IntoIterator<Item = impl AsRef<str>>,
+        budget: usize,
+    ) -> String {
+        let mut documents = String::new();
+        let mut used = 0;
+        for (index, document) in context_docs.into_iter().enumerate() {
+            let document = format!("[{}] {}\n", index + 1, document.as_ref().trim());
+            let tokens = self.tokenize_str(&document);
+
+            // Cut the document short if it doesn't fit
+            let remaining = budget.saturating_sub(used);
+            if tokens.len() > remaining {
+                if remaining > 0 {
+                    documents.push_str(&self.detokenize(&tokens.as_slice()[..remaining]));
+                    documents.push('\n');
+                }
+                break;
+            }
+
+            documents.push_str(&document);
+            used += tokens.len();
+        }
+
+        documents
+    }
+}
+
+/// Whether the text is the model's refusal to answer
+fn is_unsupported(text: &str) -> bool {
+    let normalize = |text: &str| {
+        text.to_lowercase()
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect::<String>()
+    };
+    normalize(text).starts_with(&normalize(UNSUPPORTED_ANSWER))
+}
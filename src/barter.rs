@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Default number of player offers an NPC responds to before it must accept or reject
+pub const DEFAULT_MAX_ROUNDS: usize = 4;
+/// Maximum number of tokens in a line the NPC says while bartering
+pub const MAX_BARTER_LINE_TOKENS: usize = 48;
+
+/// Who made an offer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Party {
+    Player,
+    Npc,
+}
+
+/// A price offered for the item
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Offer {
+    pub from: Party,
+    pub price: i64,
+}
+
+/// What the NPC decided to do about the player's offer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BarterDecision {
+    /// The NPC sells at this price, ending the exchange
+    Accept(i64),
+    /// The NPC asks for this price instead
+    Counter(i64),
+    /// The NPC refuses to sell, ending the exchange
+    Reject,
+}
+
+impl BarterDecision {
+    /// Whether the exchange is over
+    pub fn is_final(&self) -> bool {
+        !matches!(self, BarterDecision::Counter(_))
+    }
+}
+
+/// The NPC's response to an offer
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BarterTurn {
+    pub decision: BarterDecision,
+    /// What the NPC says
+    pub line: String,
+}
+
+/// An offer and counteroffer exchange between the player and a merchant NPC selling an item.
+/// The NPC never asks for more than the top of its price range or sells below the bottom.
+pub struct Barter {
+    model: Model,
+    item: String,
+    npc_profile: String,
+    /// The lowest price the NPC will sell for to the price it first asks for
+    bounds: RangeInclusive<i64>,
+    offers: Vec<Offer>,
+    outcome: Option<BarterDecision>,
+    pub max_rounds: usize,
+    seed: u64,
+}
+
+impl Barter {
+    /// Start bartering for the item. Returns an error if the price bounds are empty or negative.
+    pub fn new(
+        model: Model,
+        item: impl Into<String>,
+        npc_profile: impl Into<String>,
+        bounds: RangeInclusive<i64>,
+    ) -> Result<Self> {
+        if bounds.is_empty() || *bounds.start() < 0 {
+            bail!("the price bounds {:?} are empty or negative", bounds)
+        }
+        Ok(Self {
+            model,
+            item: item.into(),
+            npc_profile: npc_profile.into(),
+            bounds,
+            offers: Vec::new(),
+            outcome: None,
+            max_rounds: DEFAULT_MAX_ROUNDS,
+            seed: 0,
+        })
+    }
+
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Get every offer made so far, oldest first
+    pub fn offers(&self) -> &[Offer] {
+        &self.offers
+    }
+
+    /// Get the final decision, if the exchange is over
+    pub fn outcome(&self) -> Option<BarterDecision> {
+        self.outcome
+    }
+
+    /// Get the price the NPC is currently asking, which is the top of its range before it speaks
+    pub fn asking_price(&self) -> i64 {
+        self.offers
+            .iter()
+            .rev()
+            .find(|offer| offer.from == Party::Npc)
+            .map_or(*self.bounds.end(), |offer| offer.price)
+    }
+
+    /// Have the NPC name its opening price, chosen by the model within the bounds
+    pub fn open(&mut self) -> Result<BarterTurn> {
+        let context = self.context();
+        let config = GenerationConfig::new(self.next_seed());
+        let price = self
+            .model
+            .choose_number(
+                context,
+                format!(
+                    "What price does the merchant first ask for the {}?",
+                    self.item
+                ),
+                self.bounds.clone(),
+                &config,
+            )
+            .unwrap_or(*self.bounds.end());
+        self.offers.push(Offer {
+            from: Party::Npc,
+            price,
+        });
+        let decision = BarterDecision::Counter(price);
+        let line = self.line(decision)?;
+        Ok(BarterTurn { decision, line })
+    }
+
+    /// Have the NPC respond to the player's offer, optionally with what the player said.
+    /// Offers at or above the asking price are always accepted, and counteroffers are always between
+    /// the player's offer and the asking price. Returns an error if the exchange is already over.
+    pub fn respond(&mut self, price: i64, player_line: Option<&str>) -> Result<BarterTurn> {
+        if let Some(outcome) = self.outcome {
+            bail!("the exchange is already over: {:?}", outcome)
+        }
+        let asking_price = self.asking_price();
+        self.offers.push(Offer {
+            from: Party::Player,
+            price,
+        });
+        let rounds = self
+            .offers
+            .iter()
+            .filter(|offer| offer.from == Party::Player)
+            .count();
+
+        // Decide, only asking the model when there is a real choice
+        let counter_range = price.saturating_add(1).max(*self.bounds.start())..=asking_price - 1;
+        let decision = if price >= asking_price {
+            BarterDecision::Accept(price)
+        } else if rounds >= self.max_rounds || counter_range.is_empty() {
+            if price >= *self.bounds.start() {
+                BarterDecision::Accept(price)
+            } else {
+                BarterDecision::Reject
+            }
+        } else {
+            let mut context = self.context();
+            if let Some(player_line) = player_line {
+                context.push_str(&format!("\nThe customer says: \"{}\"", player_line.trim()));
+            }
+            let config = GenerationConfig::new(self.next_seed());
+            let options = if price >= *self.bounds.start() {
+                vec!["accept", "counter"]
+            } else {
+                vec!["counter", "reject"]
+            };
+            let choice = self
+                .model
+                .classify(&context, options.iter().copied(), &config)
+                .unwrap_or("counter");
+            match choice {
+                "accept" => BarterDecision::Accept(price),
+                "reject" => BarterDecision::Reject,
+                _ => {
+                    let counter = self
+                        .model
+                        .choose_number(
+                            context,
+                            "What lower price does the merchant ask for now?",
+                            counter_range.clone(),
+                            &config,
+                        )
+                        .unwrap_or(*counter_range.end());
+                    BarterDecision::Counter(counter)
+                }
+            }
+        };
+
+        // Remember the decision and say it
+        match decision {
+            BarterDecision::Counter(price) => self.offers.push(Offer {
+                from: Party::Npc,
+                price,
+            }),
+            _ => self.outcome = Some(decision),
+        }
+        let line = self.line(decision)?;
+        Ok(BarterTurn { decision, line })
+    }
+
+    /// Describe the merchant, the item and the offers so far
+    fn context(&self) -> String {
+        let offers = self
+            .offers
+            .iter()
+            .map(|offer| match offer.from {
+                Party::Player => format!("- The customer offers {}.", offer.price),
+                Party::Npc => format!("- The merchant asks for {}.", offer.price),
+            })
+            .join("\n");
+        format!(
+            "Merchant: {}\nItem for sale: {}\nOffers so far:\n{}",
+            self.npc_profile,
+            self.item,
+            if offers.is_empty() { "- none" } else { &offers }
+        )
+    }
+
+    /// Write what the NPC says when making the decision, in character
+    fn line(&mut self, decision: BarterDecision) -> Result<String> {
+        let action = match decision {
+            BarterDecision::Accept(price) => {
+                format!("agrees to sell the {} for {}", self.item, price)
+            }
+            BarterDecision::Counter(price) => format!("asks for {} for the {}", price, self.item),
+            BarterDecision::Reject => format!("refuses to sell the {} at that price", self.item),
+        };
+        let mut extra = HashMap::new();
+        extra.insert("Situation", self.context());
+        extra.insert("Response", "\"".to_string());
+        let config = GenerationConfig::new(self.next_seed())
+            .with_temp(0.7)
+            .with_max_tokens(MAX_BARTER_LINE_TOKENS)
+            .with_stop("\"")
+            .with_stop("\n");
+        let line = self.model.instruct_with(
+            format!(
+                "Write what the merchant says as they {}, in character.",
+                action
+            ),
+            Some(&extra),
+            &config,
+        )?;
+        Ok(line.trim().to_string())
+    }
+
+    fn next_seed(&mut self) -> u64 {
+        let seed = self.seed;
+        self.seed = self.seed.wrapping_add(1);
+        seed
+    }
+}
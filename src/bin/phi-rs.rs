@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use clap::{Args, Parser, Subcommand};
+use hf_hub::api::sync::Api;
+
+use phi_rs::crafter::{Crafter, Theme};
+use phi_rs::generation::GenerationConfig;
+use phi_rs::model::{Model, MODEL_FILE, MODEL_REPO, TOKENIZER_FILE};
+use phi_rs::scene::Scene;
+
+/// Run phi-rs from the command line
+#[derive(Parser)]
+#[command(name = "phi-rs", version, about)]
+struct Cli {
+    /// Seed of the model
+    #[arg(long, global = true, default_value_t = 0)]
+    model_seed: u64,
+    /// Run the model on the GPU if CUDA is available
+    #[arg(long, global = true)]
+    cuda: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Continue a prompt
+    Infer {
+        prompt: String,
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    /// Follow an instruction, with optional `key=value` sections of extra information
+    Instruct {
+        instruction: String,
+        /// Extra information as `key=value`, such as `--extra "Text=Once upon a time"`
+        #[arg(long, value_parser = parse_key_value)]
+        extra: Vec<(String, String)>,
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    /// Craft a new item from ingredients
+    Craft {
+        #[arg(required = true)]
+        items: Vec<String>,
+        /// Theme of the crafter, such as general, alchemy, cooking, tinkering or wordblending
+        #[arg(long, default_value = "general", value_parser = Theme::from_str)]
+        theme: Theme,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Choose the item that best fits the context and traits
+    Choose {
+        context: String,
+        traits: String,
+        #[arg(required = true)]
+        items: Vec<String>,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        #[arg(long, default_value_t = 3)]
+        attempts: usize,
+    },
+    /// Run scenes
+    Scene {
+        #[command(subcommand)]
+        command: SceneCommand,
+    },
+    /// Print the token ids of a text
+    Tokenize { text: String },
+    /// Download the model and tokenizer into the local cache and print where they are
+    Cache,
+}
+
+#[derive(Subcommand)]
+enum SceneCommand {
+    /// Play out a scene for a number of turns
+    Run {
+        setting: String,
+        /// Characters in the scene
+        #[arg(long = "character", short)]
+        characters: Vec<String>,
+        /// Narration that starts the scene
+        #[arg(long)]
+        story: Option<String>,
+        #[arg(long, default_value_t = 5)]
+        turns: usize,
+        /// Maximum number of tokens in each turn
+        #[arg(long, default_value_t = 48)]
+        turn_tokens: usize,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+}
+
+/// Flags for every option of `GenerationConfig`
+#[derive(Args)]
+struct GenerationArgs {
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Sampling temperature; greedy sampling if left out
+    #[arg(long)]
+    temp: Option<f64>,
+    #[arg(long)]
+    top_p: Option<f64>,
+    #[arg(long, default_value_t = 1.0)]
+    repeat_penalty: f32,
+    #[arg(long, default_value_t = 0)]
+    repeat_last_n: usize,
+    #[arg(long)]
+    max_tokens: Option<usize>,
+    /// Stop generating at this string; can be given more than once
+    #[arg(long)]
+    stop: Vec<String>,
+    /// Never generate this token id; can be given more than once
+    #[arg(long = "ban-token")]
+    banned_tokens: Vec<u32>,
+}
+
+impl GenerationArgs {
+    fn config(&self) -> GenerationConfig {
+        GenerationConfig {
+            seed: self.seed,
+            temp: self.temp,
+            top_p: self.top_p,
+            repeat_penalty: self.repeat_penalty,
+            repeat_last_n: self.repeat_last_n,
+            max_tokens: self.max_tokens,
+            stop: self.stop.clone(),
+            banned_tokens: self.banned_tokens.clone(),
+        }
+    }
+}
+
+fn parse_key_value(text: &str) -> Result<(String, String)> {
+    match text.split_once('=') {
+        Some((key, value)) => Ok((key.trim().to_string(), value.to_string())),
+        None => bail!("expected key=value, got {:?}", text),
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    // The cache command doesn't need the model loaded
+    if let Command::Cache = cli.command {
+        let repo = Api::new()?.model(MODEL_REPO.to_string());
+        println!("{}", repo.get(MODEL_FILE)?.display());
+        println!("{}", repo.get(TOKENIZER_FILE)?.display());
+        return Ok(());
+    }
+    let model = Model::new(cli.model_seed, cli.cuda)?;
+
+    match cli.command {
+        Command::Infer { prompt, generation } => {
+            println!(
+                "{}{}",
+                prompt,
+                model.generate(prompt.as_str(), &generation.config())?
+            );
+        }
+        Command::Instruct {
+            instruction,
+            extra,
+            generation,
+        } => {
+            let extra: HashMap<&str, &str> = extra
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect();
+            println!(
+                "{}",
+                model.instruct_with(instruction, Some(&extra), &generation.config())?
+            );
+        }
+        Command::Craft { items, theme, seed } => {
+            let crafter = Crafter::themed(model, theme);
+            println!("{}", crafter.craft_item(&items, seed)?);
+        }
+        Command::Choose {
+            context,
+            traits,
+            items,
+            seed,
+            attempts,
+        } => match model.try_choose_item(context, traits, &items, seed, attempts) {
+            Some(item) => println!("{}", item),
+            None => bail!("no item was chosen"),
+        },
+        Command::Scene {
+            command:
+                SceneCommand::Run {
+                    setting,
+                    characters,
+                    story,
+                    turns,
+                    turn_tokens,
+                    seed,
+                },
+        } => {
+            let mut scene = Scene::builder(model, setting)
+                .characters(characters)
+                .seed(seed)
+                .build()?;
+            if let Some(story) = story {
+                print!("{}", scene.push_story(story));
+            }
+            for _ in 0..turns {
+                print!("{}", scene.infer_any(turn_tokens)?);
+                std::io::stdout().flush()?;
+            }
+        }
+        Command::Tokenize { text } => {
+            let tokens = model.tokenize_str(&text);
+            println!(
+                "{}",
+                tokens
+                    .iter()
+                    .map(|token| token.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+            eprintln!("{} tokens", tokens.len());
+        }
+        Command::Cache => unreachable!(),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Default maximum number of tokens in a free-text field of a character sheet
+pub const DEFAULT_TEXT_FIELD_TOKENS: usize = 128;
+
+/// What kind of value a field of a `CharacterSchema` holds
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldKind {
+    /// A whole number in the range, such as a stat
+    Number(RangeInclusive<i64>),
+    /// One of the options, such as a class or alignment
+    Choice(Vec<String>),
+    /// Free text of at most this many tokens, such as a backstory
+    Text(usize),
+}
+
+/// A field of a `CharacterSchema`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CharacterField {
+    pub name: String,
+    pub kind: FieldKind,
+}
+
+impl CharacterField {
+    pub fn number(name: impl Into<String>, range: RangeInclusive<i64>) -> Self {
+        Self {
+            name: name.into(),
+            kind: FieldKind::Number(range),
+        }
+    }
+
+    pub fn choice(
+        name: impl Into<String>,
+        options: impl IntoIterator<Item = impl Display>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            kind: FieldKind::Choice(
+                options
+                    .into_iter()
+                    .map(|option| option.to_string())
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn text(name: impl Into<String>, max_tokens: usize) -> Self {
+        Self {
+            name: name.into(),
+            kind: FieldKind::Text(max_tokens),
+        }
+    }
+}
+
+/// The fields of a character sheet, filled in order by `Model::generate_character`.
+/// Later fields are written knowing the values of earlier ones.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CharacterSchema {
+    pub fields: Vec<CharacterField>,
+}
+
+impl CharacterSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_field(mut self, name: impl Into<String>, kind: FieldKind) -> Self {
+        self.fields.push(CharacterField {
+            name: name.into(),
+            kind,
+        });
+        self
+    }
+
+    pub fn with_number(self, name: impl Into<String>, range: RangeInclusive<i64>) -> Self {
+        self.with_field(name, FieldKind::Number(range))
+    }
+
+    pub fn with_choice(
+        self,
+        name: impl Into<String>,
+        options: impl IntoIterator<Item = impl Display>,
+    ) -> Self {
+        let options = options
+            .into_iter()
+            .map(|option| option.to_string())
+            .collect();
+        self.with_field(name, FieldKind::Choice(options))
+    }
+
+    pub fn with_text(self, name: impl Into<String>) -> Self {
+        self.with_field(name, FieldKind::Text(DEFAULT_TEXT_FIELD_TOKENS))
+    }
+}
+
+/// The value of a field of a `CharacterSheet`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldValue {
+    Number(i64),
+    Choice(String),
+    Text(String),
+}
+
+impl FieldValue {
+    pub fn as_number(&self) -> Option<i64> {
+        match self {
+            FieldValue::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FieldValue::Choice(text) | FieldValue::Text(text) => Some(text),
+            FieldValue::Number(_) => None,
+        }
+    }
+}
+
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Number(number) => write!(f, "{}", number),
+            FieldValue::Choice(text) | FieldValue::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// A character generated from a `CharacterSchema`, with a value for every field in order
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CharacterSheet {
+    pub fields: Vec<(String, FieldValue)>,
+}
+
+impl CharacterSheet {
+    /// Get the value of a field by name, ignoring case
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&FieldValue> {
+        let name = name.as_ref();
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+}
+
+impl Display for CharacterSheet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, value) in &self.fields {
+            writeln!(f, "{}: {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Model {
+    /// Generate a character described by the prompt, filling every field of the schema.
+    /// Numbers are constrained to their ranges and choices to their options while decoding.
+    /// Returns an error if a field has an empty range or no options, or the model doesn't answer a field with
+    /// a number in its range or one of its options.
+    pub fn generate_character(
+        &self,
+        prompt: impl AsRef<str>,
+        schema: &CharacterSchema,
+    ) -> Result<CharacterSheet> {
+        self.generate_character_with(prompt, schema, &GenerationConfig::new(0).with_temp(0.7))
+    }
+
+    /// Generate a character like `generate_character`, sampling with the config
+    pub fn generate_character_with(
+        &self,
+        prompt: impl AsRef<str>,
+        schema: &CharacterSchema,
+        config: &GenerationConfig,
+    ) -> Result<CharacterSheet> {
+        let prompt = prompt.as_ref().trim();
+        check_fields(&schema.fields)?;
+
+        let mut sheet = CharacterSheet::default();
+        for (index, field) in schema.fields.iter().enumerate() {
+            // Describe the character so far
+            let mut character = format!("Description: {}\n", prompt);
+            for (name, value) in &sheet.fields {
+                character.push_str(&format!("{}: {}\n", name, value));
+            }
+
+            let seed = config.seed.wrapping_add(index as u64);
+            let value = self.fill_field("character", &character, field, seed, config)?;
+            sheet.fields.push((field.name.clone(), value));
+        }
+
+        Ok(sheet)
+    }
+
+    /// Fill in a field of the subject, such as a character, described by `known`.
+    /// Numbers are constrained to their ranges and choices to their options while decoding.
+    /// Returns an error if the model doesn't answer with a number in the range or one of the options.
+    pub(crate) fn fill_field(
+        &self,
+        subject: &str,
+        known: &str,
+        field: &CharacterField,
+        seed: u64,
+        config: &GenerationConfig,
+    ) -> Result<FieldValue> {
+        let section = subject
+            .chars()
+            .take(1)
+            .flat_map(char::to_uppercase)
+            .chain(subject.chars().skip(1))
+            .collect::<String>();
+
+        Ok(match &field.kind {
+            FieldKind::Number(range) => {
+                let question = format!("What is the {}'s {}?", subject, field.name);
+                let number = self
+                    .choose_number(
+                        known,
+                        question,
+                        range.clone(),
+                        &config.clone().with_seed(seed),
+                    )
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "the model did not answer the {}'s {} with a number from {} to {}",
+                            subject,
+                            field.name,
+                            range.start(),
+                            range.end()
+                        )
+                    })?;
+                FieldValue::Number(number)
+            }
+            FieldKind::Choice(options) => {
+                // Only let the model write one of the options, followed by the end of the line
+                let instruction = format!(
+                    "Choose the {}'s {} from: {}.",
+                    subject,
+                    field.name,
+                    options.join(", ")
+                );
+                let response = format!("{}:", field.name);
+                let mut extra = HashMap::new();
+                extra.insert(section.as_str(), known);
+                extra.insert("Response", response.as_str());
+                let prompt = self.create_instruct_prompt(instruction, Some(&extra));
+                let items: Vec<String> = options
+                    .iter()
+                    .map(|option| format!(" {}", option))
+                    .collect();
+                let choice = self
+                    .choose_by_trie(prompt, &items, "\n", seed, config.temp.unwrap_or(0.0))
+                    .ok_or_else(|| {
+                        anyhow!("the model did not choose the {}'s {}", subject, field.name)
+                    })?;
+                FieldValue::Choice(choice.trim().to_string())
+            }
+            FieldKind::Text(max_tokens) => {
+                let instruction = format!("Write the {}'s {}.", subject, field.name);
+                let mut extra = HashMap::new();
+                extra.insert(section.as_str(), known);
+                let text_config = GenerationConfig {
+                    seed,
+                    max_tokens: Some(*max_tokens),
+                    stop: vec!["###".to_string()],
+                    ..config.clone()
+                };
+                let text = self.instruct_with(instruction, Some(&extra), &text_config)?;
+                FieldValue::Text(text.trim().to_string())
+            }
+        })
+    }
+}
+
+/// Make sure every number field has a range and every choice field has options
+pub(crate) fn check_fields(fields: &[CharacterField]) -> Result<()> {
+    for field in fields {
+        match &field.kind {
+            FieldKind::Number(range) if range.is_empty() => {
+                bail!("the range of field \"{}\" is empty", field.name)
+            }
+            FieldKind::Choice(options) if options.is_empty() => {
+                bail!("field \"{}\" has no options", field.name)
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,552 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+
+use anyhow::Result;
+use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::generation::GenerationConfig;
+use crate::model::{softmax, Model};
+
+/// Maximum number of tokens in the explanation of a choice
+pub const MAX_EXPLANATION_TOKENS: usize = 48;
+/// Lowest probability `ask_bool` accepts an answer with
+pub const MIN_BOOL_CONFIDENCE: f32 = 0.6;
+/// Largest number of values `rate` will consider
+pub const MAX_RATING_VALUES: usize = 101;
+/// Largest range `choose_number` constrains to the numbers in the range, rather than checking a free answer
+pub const MAX_CONSTRAINED_NUMBERS: usize = 1000;
+/// Maximum number of tokens in a free answer to `choose_number`
+const MAX_NUMBER_TOKENS: usize = 8;
+/// Labels given to the options of a multiple choice question
+const OPTION_LABELS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+/// Ways the model might write "yes" as the answer to a question
+const YES_ANSWERS: &[&str] = &[" yes", " Yes", " YES"];
+/// Ways the model might write "no" as the answer to a question
+const NO_ANSWERS: &[&str] = &[" no", " No", " NO"];
+
+/// The result of `Model::choose_item_with_margin`
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarginChoice {
+    /// The chosen item (lowercased and trimmed), or None if the choice was ambiguous
+    pub choice: Option<String>,
+    /// Every item with its probability, most likely first
+    pub scores: Vec<(String, f32)>,
+}
+
+impl MarginChoice {
+    pub fn is_ambiguous(&self) -> bool {
+        self.choice.is_none()
+    }
+}
+
+impl Model {
+    /// Like `try_choose_item`, but after choosing, continue the same response with a one-sentence
+    /// explanation of why the item was chosen, so the explanation always agrees with the choice.
+    /// Returns the chosen item (lowercased and trimmed) and the explanation if successful, otherwise None.
+    pub fn try_choose_item_explained(
+        &self,
+        context: impl AsRef<str>,
+        desired_traits: impl AsRef<str>,
+        items: impl IntoIterator<Item = impl AsRef<str>>,
+        seed: u64,
+        attempts: usize,
+    ) -> Option<(String, String)> {
+        // Trim and lowercase all the items
+        let items: Vec<String> = items
+            .into_iter()
+            .map(|item| item.as_ref().trim().to_lowercase())
+            .collect();
+
+        // Choose the item
+        let item = self.try_choose_item(
+            context.as_ref(),
+            desired_traits.as_ref(),
+            &items,
+            seed,
+            attempts,
+        )?;
+
+        // Continue the same response with the reason
+        let mut prompt = self.create_choice_prompt(context, desired_traits, &items);
+        prompt.push_str(format!("{}]\nReason: I chose [{}] because", item, item));
+        let config = GenerationConfig::new(seed)
+            .with_max_tokens(MAX_EXPLANATION_TOKENS)
+            .with_stop("\n");
+        let reason = self.generate(prompt, &config).ok()?;
+
+        // Keep only the first sentence
+        let reason = reason.trim();
+        let reason = match reason.find(['.', '!', '?']) {
+            Some(end) => &reason[..=end],
+            None => reason,
+        };
+        let explanation = format!("I chose {} because {}", item, reason);
+
+        Some((item, explanation))
+    }
+
+    /// Ask the model a yes or no question about the context.
+    /// Only the answers "yes" and "no" are considered, weighed by how likely the model finds each
+    /// (sharpened or flattened by the config's temperature).
+    /// Returns None if neither answer reaches `MIN_BOOL_CONFIDENCE` or scoring fails.
+    pub fn ask_bool(
+        &self,
+        context: impl AsRef<str>,
+        question: impl AsRef<str>,
+        config: &GenerationConfig,
+    ) -> Option<bool> {
+        let yes = self.yes_probability(context, question).ok()?;
+
+        // Apply the temperature to the odds of each answer
+        let temp = config.temp.filter(|temp| *temp > 0.0).unwrap_or(1.0) as f32;
+        let probabilities = softmax([yes.ln() / temp, (1.0 - yes).ln() / temp]);
+
+        if probabilities[0] >= MIN_BOOL_CONFIDENCE {
+            Some(true)
+        } else if probabilities[1] >= MIN_BOOL_CONFIDENCE {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Ask the model a multiple choice question, with the options labeled A, B, C and so on.
+    /// Only the labels are considered as answers. With no temperature in the config the most likely
+    /// option is chosen, otherwise one is sampled using the config's seed and temperature.
+    /// Returns the index of the chosen option, or None if there are no options, more than 26, or scoring fails.
+    pub fn answer_multiple_choice(
+        &self,
+        question: impl AsRef<str>,
+        options: impl IntoIterator<Item = impl AsRef<str>>,
+        config: &GenerationConfig,
+    ) -> Option<usize> {
+        let options: Vec<String> = options
+            .into_iter()
+            .map(|option| option.as_ref().trim().to_string())
+            .collect();
+        if options.is_empty() || options.len() > OPTION_LABELS.len() {
+            return None;
+        }
+
+        // List the options like so: "A) option\nB) option"
+        let labels: Vec<char> = OPTION_LABELS.chars().take(options.len()).collect();
+        let options_string = labels
+            .iter()
+            .zip(&options)
+            .map(|(label, option)| format!("{}) {}", label, option))
+            .join("\n");
+
+        // Create the prompt
+        let mut extra = HashMap::new();
+        extra.insert("Question", question.as_ref());
+        extra.insert("Options", options_string.as_str());
+        extra.insert("Response", "Answer:");
+        let prompt = self.create_instruct_prompt(
+            "Answer the multiple choice question with the letter of the correct option.",
+            Some(&extra),
+        );
+
+        // Score each label
+        let log_probs = self
+            .continuation_log_probs(prompt, labels.iter().map(|label| format!(" {}", label)))
+            .ok()?;
+        let scores: Vec<f32> = log_probs
+            .iter()
+            .map(|log_probs| log_probs.iter().sum())
+            .collect();
+
+        self.pick(&scores, config)
+    }
+
+    /// Classify the text with one of the labels, such as to route a player's message to an intent.
+    /// Only the labels are considered as answers. With no temperature in the config the most likely
+    /// label is chosen, otherwise one is sampled using the config's seed and temperature.
+    /// Returns None if there are no labels or scoring fails.
+    pub fn classify<L: Clone + Display>(
+        &self,
+        text: impl AsRef<str>,
+        labels: impl IntoIterator<Item = L>,
+        config: &GenerationConfig,
+    ) -> Option<L> {
+        let labels: Vec<L> = labels.into_iter().collect();
+        let scores = self.label_log_likelihoods(text, &labels)?;
+        let index = self.pick(&scores, config)?;
+        Some(labels[index].clone())
+    }
+
+    /// Classify the text like `classify`, returning every label with its probability, most likely first.
+    /// The probabilities sum to 1, sharpened or flattened by the config's temperature
+    /// (the model's own probabilities are used if it has none).
+    /// Returns an empty list if there are no labels or scoring fails.
+    pub fn classify_scored<L: Clone + Display>(
+        &self,
+        text: impl AsRef<str>,
+        labels: impl IntoIterator<Item = L>,
+        config: &GenerationConfig,
+    ) -> Vec<(L, f32)> {
+        let labels: Vec<L> = labels.into_iter().collect();
+        let Some(scores) = self.label_log_likelihoods(text, &labels) else {
+            return Vec::new();
+        };
+
+        // Turn the log-likelihoods into probabilities
+        let temp = config.temp.filter(|temp| *temp > 0.0).unwrap_or(1.0) as f32;
+        let probabilities = softmax(scores.iter().map(|score| score / temp));
+
+        // Most likely first
+        let mut scored: Vec<(L, f32)> = labels.into_iter().zip(probabilities).collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+    }
+
+    /// Get the total log-probability of each label (lowercased and trimmed) followed by the closing bracket
+    /// as the label of the text. Returns None if there are no labels or scoring fails.
+    fn label_log_likelihoods<L: Display>(
+        &self,
+        text: impl AsRef<str>,
+        labels: &[L],
+    ) -> Option<Vec<f32>> {
+        if labels.is_empty() {
+            return None;
+        }
+        let names: Vec<String> = labels
+            .iter()
+            .map(|label| label.to_string().trim().to_lowercase())
+            .collect();
+
+        // Create the prompt, listing the labels like so: "[label1][label2][label3]"
+        let labels_string = format!("[{}]", names.join("]["));
+        let mut extra = HashMap::new();
+        extra.insert("Text", text.as_ref());
+        extra.insert("Labels", labels_string.as_str());
+        extra.insert("Response", "Label: [");
+        let prompt = self.create_instruct_prompt(
+            "Classify the text with the most fitting of the labels.",
+            Some(&extra),
+        );
+
+        // Score each label followed by the closing bracket, so the end of the label counts too
+        let log_probs = self
+            .continuation_log_probs(prompt, names.iter().map(|name| format!("{}]", name)))
+            .ok()?;
+        Some(
+            log_probs
+                .iter()
+                .map(|log_probs| log_probs.iter().sum())
+                .collect(),
+        )
+    }
+
+    /// Choose the index of the highest score, or sample one by the scores if the config has a temperature
+    fn pick(&self, scores: &[f32], config: &GenerationConfig) -> Option<usize> {
+        match config.temp.filter(|temp| *temp > 0.0) {
+            // Sample an index
+            Some(temp) => {
+                let probabilities = softmax(scores.iter().map(|score| score / temp as f32));
+                let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(self.seed()));
+                let mut remaining: f32 = rng.gen();
+                for (index, probability) in probabilities.iter().enumerate() {
+                    remaining -= probability;
+                    if remaining <= 0.0 {
+                        return Some(index);
+                    }
+                }
+                probabilities.len().checked_sub(1)
+            }
+            // Choose the highest score
+            None => scores
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(index, _)| index),
+        }
+    }
+
+    /// Ask the model a question about the context that is answered with a whole number in `range`,
+    /// such as how many goblins attack. For ranges of up to `MAX_CONSTRAINED_NUMBERS` numbers the model can only
+    /// write numbers in the range; for larger ranges its answer is parsed and checked against the range.
+    /// Returns None if the range is empty or the answer isn't a number in the range.
+    pub fn choose_number(
+        &self,
+        context: impl AsRef<str>,
+        question: impl AsRef<str>,
+        range: RangeInclusive<i64>,
+        config: &GenerationConfig,
+    ) -> Option<i64> {
+        if range.is_empty() {
+            return None;
+        }
+
+        // Create the prompt
+        let instruction = format!(
+            "Answer the question about the context with a whole number from {} to {}. Answer with only the number.",
+            range.start(),
+            range.end()
+        );
+        let mut extra = HashMap::new();
+        extra.insert("Context", context.as_ref());
+        extra.insert("Question", question.as_ref());
+        extra.insert("Response", "Answer:");
+        let prompt = self.create_instruct_prompt(instruction, Some(&extra));
+
+        // Compare the span before converting it, since it may not fit in a usize
+        let span = range.end().abs_diff(*range.start());
+        let constrained = usize::try_from(span).is_ok_and(|span| span < MAX_CONSTRAINED_NUMBERS);
+        let answer = if constrained {
+            // Only let the model write the numbers in the range, each followed by the end of the line
+            let numbers: Vec<String> = range.clone().map(|number| format!(" {}", number)).collect();
+            self.choose_by_trie(
+                prompt,
+                &numbers,
+                "\n",
+                config.seed,
+                config.temp.unwrap_or(0.0),
+            )?
+        } else {
+            // Let the model write anything, then read the number
+            let config = GenerationConfig {
+                max_tokens: Some(MAX_NUMBER_TOKENS),
+                stop: vec!["\n".to_string()],
+                ..config.clone()
+            };
+            self.generate(prompt, &config).ok()?
+        };
+
+        // Check the answer is in the range
+        let number = answer.trim().parse::<i64>().ok()?;
+        range.contains(&number).then_some(number)
+    }
+
+    /// Ask the model to rate the context by the criteria with a whole number from `scale_min` to `scale_max`.
+    /// Only numbers in the range are considered. With no temperature in the config the most likely rating
+    /// is returned, otherwise the average of the ratings weighed by their probability
+    /// (sharpened or flattened by the temperature), like averaging many samples.
+    /// Returns None if the range is empty or larger than `MAX_RATING_VALUES`, or scoring fails.
+    pub fn rate(
+        &self,
+        context: impl AsRef<str>,
+        criteria: impl AsRef<str>,
+        scale_min: i32,
+        scale_max: i32,
+        config: &GenerationConfig,
+    ) -> Option<f32> {
+        if scale_min > scale_max
+            || (scale_max as i64 - scale_min as i64) as usize >= MAX_RATING_VALUES
+        {
+            return None;
+        }
+        let ratings: Vec<i32> = (scale_min..=scale_max).collect();
+
+        // Create the prompt
+        let instruction = format!(
+            "Rate the context by the criteria with a whole number from {} to {}. Answer with only the number.",
+            scale_min, scale_max
+        );
+        let mut extra = HashMap::new();
+        extra.insert("Context", context.as_ref());
+        extra.insert("Criteria", criteria.as_ref());
+        extra.insert("Response", "Rating:");
+        let prompt = self.create_instruct_prompt(instruction, Some(&extra));
+
+        // Score each rating followed by the end of the line, so "1" isn't confused with the start of "10"
+        let log_probs = self
+            .continuation_log_probs(
+                prompt,
+                ratings.iter().map(|rating| format!(" {}\n", rating)),
+            )
+            .ok()?;
+        let scores: Vec<f32> = log_probs
+            .iter()
+            .map(|log_probs| log_probs.iter().sum())
+            .collect();
+
+        match config.temp.filter(|temp| *temp > 0.0) {
+            // Average the ratings by their probability
+            Some(temp) => {
+                let probabilities = softmax(scores.iter().map(|score| score / temp as f32));
+                Some(
+                    ratings
+                        .iter()
+                        .zip(probabilities)
+                        .map(|(rating, probability)| *rating as f32 * probability)
+                        .sum(),
+                )
+            }
+            // Choose the most likely rating
+            None => ratings
+                .iter()
+                .zip(scores)
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(rating, _)| *rating as f32),
+        }
+    }
+
+    /// Choose an item like `choose_item_scored`, but only if the model is clearly more sure of it than of any other:
+    /// if the probabilities of the two most likely items (see `rank_items`) are less than `margin` apart,
+    /// no item is chosen. The probabilities of every item are returned either way,
+    /// such as to ask a clarifying question between the closest items.
+    pub fn choose_item_with_margin(
+        &self,
+        context: impl AsRef<str>,
+        desired_traits: impl AsRef<str>,
+        items: impl IntoIterator<Item = impl AsRef<str>>,
+        margin: f32,
+    ) -> MarginChoice {
+        let scores = self.rank_items(context, desired_traits, items, &GenerationConfig::default());
+
+        // Compare the two most likely items
+        let choice = match scores.as_slice() {
+            [] => None,
+            [(only, _)] => Some(only.clone()),
+            [(best, best_score), (_, second_score), ..] => {
+                (best_score - second_score >= margin).then(|| best.clone())
+            }
+        };
+
+        MarginChoice { choice, scores }
+    }
+
+    /// Choose an item like `choose_item_scored`, blending the model's judgement with a prior weight for each item,
+    /// such as its drop rate. Each item's probability is proportional to its weight times the model's probability for it.
+    /// With no temperature in the config the most probable item is chosen, otherwise one is sampled
+    /// using the config's seed and temperature. Weights of items that are the same once lowercased and trimmed are added together.
+    /// Returns the chosen item (lowercased and trimmed), or None if no item has a positive weight or scoring fails.
+    pub fn choose_item_weighted(
+        &self,
+        context: impl AsRef<str>,
+        desired_traits: impl AsRef<str>,
+        weighted_items: impl IntoIterator<Item = (impl AsRef<str>, f32)>,
+        config: &GenerationConfig,
+    ) -> Option<String> {
+        // Add up the weights of each item, leaving out items that can never be chosen
+        let mut weights: HashMap<String, f32> = HashMap::new();
+        let mut items: Vec<String> = Vec::new();
+        for (item, weight) in weighted_items {
+            let item = item.as_ref().trim().to_lowercase();
+            if item.is_empty() || weight <= 0.0 {
+                continue;
+            }
+            if !weights.contains_key(&item) {
+                items.push(item.clone());
+            }
+            *weights.entry(item).or_default() += weight;
+        }
+
+        // Add the log of each weight to the model's log-likelihood of the item
+        let scored = self.item_log_likelihoods(context, desired_traits, &items);
+        let scores: Vec<f32> = scored
+            .iter()
+            .map(|(item, log_likelihood)| log_likelihood + weights[item].ln())
+            .collect();
+
+        let index = self.pick(&scores, config)?;
+        Some(scored[index].0.clone())
+    }
+
+    /// Rank the items by the criteria by comparing every pair of items, best first.
+    /// Each pair is compared in both orders so the model's preference for the first or second option cancels out,
+    /// and items are ranked by how likely they are to win their comparisons in total.
+    /// The config's temperature sharpens or flattens each comparison (the model's own probabilities are used if it has none).
+    /// This takes two prompts per pair of items, so it is much slower than `rank_items` but more reliable for long lists.
+    pub fn rank_by_tournament(
+        &self,
+        items: impl IntoIterator<Item = impl AsRef<str>>,
+        criteria: impl AsRef<str>,
+        config: &GenerationConfig,
+    ) -> Vec<String> {
+        let items: Vec<String> = items
+            .into_iter()
+            .map(|item| item.as_ref().trim().to_string())
+            .unique()
+            .collect();
+        let criteria = criteria.as_ref();
+
+        // Compare every pair in both orders, giving each item its chance of winning
+        let mut wins = vec![0.0; items.len()];
+        for (i, j) in (0..items.len()).tuple_combinations() {
+            let first = self.first_preferred(&items[i], &items[j], criteria, config);
+            let second = self.first_preferred(&items[j], &items[i], criteria, config);
+            let (Ok(first), Ok(second)) = (first, second) else {
+                continue;
+            };
+            let i_wins = (first + (1.0 - second)) / 2.0;
+            wins[i] += i_wins;
+            wins[j] += 1.0 - i_wins;
+        }
+
+        // Most wins first
+        items
+            .into_iter()
+            .zip(wins)
+            .sorted_by(|a, b| b.1.total_cmp(&a.1))
+            .map(|(item, _)| item)
+            .collect()
+    }
+
+    /// Get the probability the model prefers option A over option B by the criteria
+    fn first_preferred(
+        &self,
+        a: &str,
+        b: &str,
+        criteria: &str,
+        config: &GenerationConfig,
+    ) -> Result<f32> {
+        // Create the prompt
+        let options = format!("A) {}\nB) {}", a, b);
+        let mut extra = HashMap::new();
+        extra.insert("Criteria", criteria);
+        extra.insert("Options", options.as_str());
+        extra.insert("Response", "Answer:");
+        let prompt = self.create_instruct_prompt(
+            "Which option is better by the criteria? Answer with the letter of the better option.",
+            Some(&extra),
+        );
+
+        // Compare the two answers
+        let log_probs = self.continuation_log_probs(prompt, [" A", " B"])?;
+        let temp = config.temp.filter(|temp| *temp > 0.0).unwrap_or(1.0) as f32;
+        let probabilities = softmax(
+            log_probs
+                .iter()
+                .map(|log_probs| log_probs.iter().sum::<f32>() / temp),
+        );
+
+        Ok(probabilities[0])
+    }
+
+    /// Get the probability the model answers "yes" rather than "no" to a question about the context
+    pub fn yes_probability(
+        &self,
+        context: impl AsRef<str>,
+        question: impl AsRef<str>,
+    ) -> Result<f32> {
+        // Create the prompt
+        let mut extra = HashMap::new();
+        extra.insert("Context", context.as_ref());
+        extra.insert("Question", question.as_ref());
+        extra.insert("Response", "Answer:");
+        let prompt = self.create_instruct_prompt(
+            "Answer the question about the context with yes or no.",
+            Some(&extra),
+        );
+
+        // Add up the probabilities of every way of writing each answer
+        let log_probs =
+            self.continuation_log_probs(prompt, YES_ANSWERS.iter().chain(NO_ANSWERS).copied())?;
+        let probabilities: Vec<f32> = log_probs
+            .iter()
+            .map(|log_probs| log_probs.iter().sum::<f32>().exp())
+            .collect();
+        let (yes, no) = probabilities.split_at(YES_ANSWERS.len());
+        let (yes, no) = (yes.iter().sum::<f32>(), no.iter().sum::<f32>());
+
+        // Only compare the two answers
+        if yes + no == 0.0 {
+            Ok(0.5)
+        } else {
+            Ok(yes / (yes + no))
+        }
+    }
+}
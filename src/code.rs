@@ -0,0 +1,188 @@
+use anyhow::{bail, Result};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Default maximum number of tokens in a code completion
+pub const DEFAULT_CODE_TOKENS: usize = 256;
+/// Number of times `complete_code` tries to write a completion with balanced brackets
+pub const CODE_ATTEMPTS: u64 = 3;
+/// Marks the start and end of a fenced code block
+const CODE_FENCE: &str = "```";
+
+impl Model {
+    /// Complete the code in `prefix`, such as the body of a function, in the language (such as "rust" or "python").
+    /// The completion stops at the end of a fenced code block, or once a line is indented less than the
+    /// code being completed, keeping a line of closing brackets that ends the block. Completions that
+    /// close brackets that were never opened are written again with the next seed.
+    /// Returns an error if every attempt had unbalanced brackets.
+    pub fn complete_code(
+        &self,
+        prefix: impl AsRef<str>,
+        language: impl AsRef<str>,
+        config: &GenerationConfig,
+    ) -> Result<String> {
+        let prefix = prefix.as_ref();
+        let prompt = format!("{}{}\n{}", CODE_FENCE, language.as_ref().trim(), prefix);
+        let base_indent = indentation(prefix.rsplit('\n').next().unwrap_or_default());
+        let max_tokens = config.max_tokens.unwrap_or(DEFAULT_CODE_TOKENS);
+
+        let mut error = None;
+        for seed in (0..CODE_ATTEMPTS).map(|i| config.seed.wrapping_add(i)) {
+            let inference = self.infer_with(prompt.as_str(), &config.clone().with_seed(seed))?;
+            let mut generated = Vec::new();
+            let mut completion = String::new();
+            for token in inference {
+                generated.push(token);
+                completion = self.detokenize(&generated);
+
+                // Stop at the end of the code block
+                if let Some(end) = completion.find(CODE_FENCE) {
+                    completion.truncate(end);
+                    break;
+                }
+
+                // Stop once a finished line leaves the block being completed
+                if let Some(end) = block_end(prefix, &completion, base_indent) {
+                    completion.truncate(end);
+                    break;
+                }
+
+                if generated.len() >= max_tokens {
+                    break;
+                }
+            }
+
+            match check_brackets(prefix, &completion) {
+                Ok(()) => return Ok(completion.trim_end().to_string()),
+                Err(e) => error = Some(e),
+            }
+        }
+
+        match error {
+            Some(error) => bail!("every completion had unbalanced brackets: {}", error),
+            None => bail!("no completion was generated for the code"),
+        }
+    }
+}
+
+/// Get the content of the first fenced code block in the text, or the whole text if there is none
+pub fn strip_code_fences(text: impl AsRef<str>) -> String {
+    let text = text.as_ref();
+    let Some(start) = text.find(CODE_FENCE) else {
+        return text.to_string();
+    };
+
+    // Skip the language after the opening fence
+    let content = &text[start + CODE_FENCE.len()..];
+    let content = content.split_once('\n').map_or("", |(_, content)| content);
+    match content.find(CODE_FENCE) {
+        Some(end) => content[..end].to_string(),
+        None => content.to_string(),
+    }
+}
+
+/// Get the number of columns a line is indented by, counting tabs as 4
+fn indentation(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Find where the completion should end because a finished line is indented less than `base_indent`.
+/// A line of closing brackets that closes a bracket left open is kept.
+fn block_end(prefix: &str, completion: &str, base_indent: usize) -> Option<usize> {
+    // The first line continues the last line of the prefix and the last line isn't finished yet
+    let mut start = completion.find('\n')? + 1;
+    while let Some(length) = completion[start..].find('\n') {
+        let line = &completion[start..start + length];
+        if !line.trim().is_empty() && indentation(line) < base_indent {
+            // Keep a line of closing brackets if brackets are still open before it
+            let closing = line
+                .trim()
+                .chars()
+                .all(|c| matches!(c, ')' | ']' | '}' | ';' | ','));
+            let open = bracket_depth(&format!("{}{}", prefix, &completion[..start])) > 0;
+            return Some(if closing && open {
+                start + length
+            } else {
+                start
+            });
+        }
+        start += length + 1;
+    }
+    None
+}
+
+/// Get how many brackets are open at the end of the code, ignoring brackets in strings and comments.
+/// Mismatched brackets aren't detected here; use `check_brackets` for that.
+fn bracket_depth(code: &str) -> i64 {
+    let mut depth = 0;
+    scan_brackets(code, |bracket| {
+        depth += if matches!(bracket, '(' | '[' | '{') {
+            1
+        } else {
+            -1
+        };
+    });
+    depth
+}
+
+/// Call `f` with every bracket in the code, skipping string literals and line comments
+fn scan_brackets(code: &str, mut f: impl FnMut(char)) {
+    for line in code.lines() {
+        let mut chars = line.chars().peekable();
+        let mut quote = None;
+        while let Some(c) = chars.next() {
+            match quote {
+                Some(q) => {
+                    if c == '\\' {
+                        chars.next();
+                    } else if c == q {
+                        quote = None;
+                    }
+                }
+                None => match c {
+                    '"' => quote = Some(c),
+                    '#' => break,
+                    '/' if chars.peek() == Some(&'/') => break,
+                    '(' | ')' | '[' | ']' | '{' | '}' => f(c),
+                    _ => {}
+                },
+            }
+        }
+    }
+}
+
+/// Make sure the completion only closes brackets that are open and of the same kind.
+/// Brackets left open are fine, since the completion may stop partway through the code.
+pub fn check_brackets(prefix: impl AsRef<str>, completion: impl AsRef<str>) -> Result<()> {
+    let mut open = Vec::new();
+    let mut error = None;
+    scan_brackets(prefix.as_ref(), |bracket| match bracket {
+        '(' | '[' | '{' => open.push(bracket),
+        _ => {
+            open.pop();
+        }
+    });
+    scan_brackets(completion.as_ref(), |bracket| {
+        let expected = match bracket {
+            '(' | '[' | '{' => {
+                open.push(bracket);
+                return;
+            }
+            ')' => '(',
+            ']' => '[',
+            _ => '{',
+        };
+        if error.is_none() && open.pop() != Some(expected) {
+            error = Some(bracket);
+        }
+    });
+
+    match error {
+        Some(bracket) => bail!("the completion closes an unopened {:?}", bracket),
+        None => Ok(()),
+    }
+}
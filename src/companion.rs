@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::VectorMemory;
+use crate::generation::GenerationConfig;
+use crate::list::parse_list_item;
+use crate::model::Model;
+use crate::summarize::{SummaryLength, SummaryOptions};
+
+/// The user `Companion::chat` talks to
+pub const DEFAULT_USER: &str = "User";
+/// Default number of tokens of recent messages kept verbatim before older ones are summarized
+pub const DEFAULT_MAX_HISTORY_TOKENS: usize = 512;
+/// Maximum number of tokens in a reply
+pub const MAX_REPLY_TOKENS: usize = 128;
+/// Maximum number of tokens of facts learned from a single message
+pub const MAX_USER_FACT_TOKENS: usize = 64;
+/// Number of facts about the user recalled for every reply
+pub const RECALLED_FACTS: usize = 5;
+
+/// Who a companion is and how it talks
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersonaCard {
+    pub name: String,
+    /// Who the companion is, such as "a retired sea captain who loves telling stories"
+    pub description: String,
+    /// How the companion talks, such as "warm, a little gruff, uses sailing slang"
+    pub style: Option<String>,
+    /// The first thing the companion says to a new user
+    pub greeting: Option<String>,
+}
+
+impl PersonaCard {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            style: None,
+            greeting: None,
+        }
+    }
+
+    pub fn with_style(mut self, style: impl Into<String>) -> Self {
+        self.style = Some(style.into());
+        self
+    }
+
+    pub fn with_greeting(mut self, greeting: impl Into<String>) -> Self {
+        self.greeting = Some(greeting.into());
+        self
+    }
+}
+
+/// Who sent a `ChatMessage`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatRole {
+    User,
+    Companion,
+}
+
+/// A message in a conversation with a `Companion`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub text: String,
+}
+
+/// Everything a companion remembers about its conversation with one user
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Conversation {
+    /// A summary of the messages that no longer fit in the recent history
+    pub summary: String,
+    /// The most recent messages, oldest first
+    pub recent: Vec<ChatMessage>,
+    /// Facts the user has told the companion about themselves
+    pub facts: VectorMemory,
+}
+
+/// The part of a `Companion` that is saved and loaded
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct CompanionState {
+    persona: PersonaCard,
+    conversations: BTreeMap<String, Conversation>,
+    max_history_tokens: usize,
+    seed: u64,
+}
+
+/// A 1:1 chat partner with a fixed persona that remembers each user it talks to.
+/// Older messages are summarized once the recent history grows too long, and facts users
+/// state about themselves are kept and recalled when relevant.
+pub struct Companion {
+    model: Model,
+    state: CompanionState,
+}
+
+impl Companion {
+    pub fn new(model: Model, persona: PersonaCard) -> Self {
+        Self {
+            model,
+            state: CompanionState {
+                persona,
+                conversations: BTreeMap::new(),
+                max_history_tokens: DEFAULT_MAX_HISTORY_TOKENS,
+                seed: 0,
+            },
+        }
+    }
+
+    pub fn with_max_history_tokens(mut self, max_history_tokens: usize) -> Self {
+        self.state.max_history_tokens = max_history_tokens;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.state.seed = seed;
+        self
+    }
+
+    pub fn persona(&self) -> &PersonaCard {
+        &self.state.persona
+    }
+
+    /// Get everything the companion remembers about a user, if it has talked to them
+    pub fn conversation(&self, user: impl AsRef<str>) -> Option<&Conversation> {
+        self.state.conversations.get(user.as_ref())
+    }
+
+    /// Get the facts the companion knows about a user, in the order it learned them
+    pub fn facts(&self, user: impl AsRef<str>) -> Vec<&str> {
+        self.conversation(user)
+            .map(|conversation| {
+                conversation
+                    .facts
+                    .entries()
+                    .iter()
+                    .map(|entry| entry.text.as_str())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Teach the companion a fact about a user, such as "their cat is called Pepper"
+    pub fn remember(&mut self, user: impl AsRef<str>, fact: impl AsRef<str>) -> Result<()> {
+        let fact = fact.as_ref().trim();
+        let conversation = self
+            .state
+            .conversations
+            .entry(user.as_ref().to_string())
+            .or_default();
+        if !fact.is_empty()
+            && !conversation
+                .facts
+                .entries()
+                .iter()
+                .any(|entry| entry.text == fact)
+        {
+            conversation.facts.insert(&self.model, fact)?;
+        }
+        Ok(())
+    }
+
+    /// Reply to a message from `DEFAULT_USER`
+    pub fn chat(&mut self, user_msg: impl AsRef<str>) -> Result<String> {
+        self.chat_as(DEFAULT_USER, user_msg)
+    }
+
+    /// Reply to a message from the user, remembering the message, the reply,
+    /// and any facts the user stated about themselves.
+    /// Returns an error if the message is empty.
+    pub fn chat_as(&mut self, user: impl AsRef<str>, user_msg: impl AsRef<str>) -> Result<String> {
+        let user = user.as_ref().trim();
+        let user_msg = user_msg.as_ref().trim();
+        if user_msg.is_empty() {
+            bail!("cannot reply to an empty message")
+        }
+
+        // Learn what the user says about themselves
+        for fact in self.facts_in(user, user_msg)? {
+            self.remember(user, fact)?;
+        }
+
+        // Describe the persona, what the companion remembers, and the conversation so far
+        let conversation = self
+            .state
+            .conversations
+            .entry(user.to_string())
+            .or_default();
+        let persona = &self.state.persona;
+        let mut extra = HashMap::new();
+        let mut description = persona.description.clone();
+        if let Some(style) = &persona.style {
+            description.push_str(&format!("\nSpeaking style: {}", style));
+        }
+        extra.insert("Persona", description);
+        if !conversation.summary.is_empty() {
+            extra.insert("Earlier Conversation", conversation.summary.clone());
+        }
+        let facts = if conversation.facts.is_empty() {
+            String::new()
+        } else {
+            conversation
+                .facts
+                .search(&self.model, user_msg, RECALLED_FACTS)?
+                .into_iter()
+                .map(|(entry, _)| format!("- {}", entry.text))
+                .join("\n")
+        };
+        if !facts.is_empty() {
+            extra.insert("Facts About The User", facts);
+        }
+        let mut history = String::new();
+        if conversation.recent.is_empty() {
+            if let Some(greeting) = &persona.greeting {
+                history.push_str(&format!("{}: {}\n", persona.name, greeting));
+            }
+        }
+        for message in &conversation.recent {
+            history.push_str(&format_message(message, user, &persona.name));
+        }
+        history.push_str(&format!("{}: {}\n", user, user_msg));
+        extra.insert("Conversation", history);
+        extra.insert("Response", format!("{}:", persona.name));
+
+        // Reply in character until the user's next turn
+        let instruction = format!(
+            "Write {}'s next reply to {} in the conversation, staying in character.",
+            persona.name, user
+        );
+        let config = GenerationConfig::new(self.next_seed())
+            .with_temp(0.7)
+            .with_max_tokens(MAX_REPLY_TOKENS)
+            .with_stop(format!("\n{}:", user))
+            .with_stop("###");
+        let reply = self
+            .model
+            .instruct_with(instruction, Some(&extra), &config)?
+            .trim()
+            .to_string();
+
+        // Remember the exchange, summarizing older messages once there are too many
+        let conversation = self.state.conversations.get_mut(user).unwrap();
+        conversation.recent.push(ChatMessage {
+            role: ChatRole::User,
+            text: user_msg.to_string(),
+        });
+        conversation.recent.push(ChatMessage {
+            role: ChatRole::Companion,
+            text: reply.clone(),
+        });
+        self.compress_history(user)?;
+
+        Ok(reply)
+    }
+
+    /// Write the companion's persona and memories to a JSON file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(&self.state)?)?;
+        Ok(())
+    }
+
+    /// Read a companion from a JSON file written by `Companion::save`
+    pub fn load(model: Model, path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            model,
+            state: serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        })
+    }
+
+    /// Ask the model for the facts the user states about themselves in the message
+    fn facts_in(&mut self, user: &str, user_msg: &str) -> Result<Vec<String>> {
+        let mut extra = HashMap::new();
+        extra.insert("Message", format!("{}: {}", user, user_msg));
+        extra.insert("Response", "- ".to_string());
+        let config = GenerationConfig::new(self.next_seed())
+            .with_temp(0.1)
+            .with_max_tokens(MAX_USER_FACT_TOKENS)
+            .with_stop("###");
+        let list = self.model.instruct_with(
+            "List the lasting facts the person states about themselves, such as their name, likes or plans, \
+            one per line. Write \"none\" if there are none.",
+            Some(&extra),
+            &config,
+        )?;
+
+        Ok(format!("- {}", list)
+            .lines()
+            .filter_map(parse_list_item)
+            .filter(|fact| !fact.trim_end_matches('.').eq_ignore_ascii_case("none"))
+            .collect())
+    }
+
+    /// Summarize the oldest half of the user's recent messages while they use too many tokens
+    fn compress_history(&mut self, user: &str) -> Result<()> {
+        let persona = self.state.persona.name.clone();
+        loop {
+            let conversation = &self.state.conversations[user];
+            let history = conversation
+                .recent
+                .iter()
+                .map(|message| format_message(message, user, &persona))
+                .join("");
+            if conversation.recent.len() <= 2
+                || self.model.tokenize_str(&history).len() <= self.state.max_history_tokens
+            {
+                return Ok(());
+            }
+
+            // Fold the oldest messages into the summary
+            let oldest = conversation.recent.len() / 2;
+            let mut text = conversation.summary.clone();
+            for message in &conversation.recent[..oldest] {
+                text.push('\n');
+                text.push_str(&format_message(message, user, &persona));
+            }
+            let options = SummaryOptions::new()
+                .with_length(SummaryLength::Paragraph)
+                .with_seed(self.next_seed());
+            let summary = self.model.summarize(text, options)?;
+
+            let conversation = self.state.conversations.get_mut(user).unwrap();
+            conversation.summary = summary;
+            conversation.recent.drain(..oldest);
+        }
+    }
+
+    fn next_seed(&mut self) -> u64 {
+        let seed = self.state.seed;
+        self.state.seed = self.state.seed.wrapping_add(1);
+        seed
+    }
+}
+
+/// Format a message as a line of the conversation
+fn format_message(message: &ChatMessage, user: impl Display, companion: impl Display) -> String {
+    match message.role {
+        ChatRole::User => format!("{}: {}\n", user, message.text),
+        ChatRole::Companion => format!("{}: {}\n", companion, message.text),
+    }
+}
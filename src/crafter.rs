@@ -1,59 +1,744 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{collections::{HashMap, VecDeque}, fmt::Display, path::Path, sync::Mutex};
 
+use anyhow::{anyhow, bail, Result};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 
-use crate::model::Model;
+use crate::embedding::VectorMemory;
+use crate::generation::GenerationConfig;
+use crate::model::{softmax, Model};
+use crate::token_string::TokenString;
+
+pub mod recipe_book;
+pub mod theme;
+pub mod validation;
+
+pub use recipe_book::{RecipeBook, SeedPolicy};
+pub use theme::Theme;
+pub use validation::ResultValidation;
+
+/// Maximum number of tokens in the name of a crafted item
+pub const MAX_ITEM_TOKENS: usize = 32;
+/// Number of times `craft_from` tries to settle on an allowed result
+pub const CRAFT_FROM_ATTEMPTS: u64 = 3;
+/// Default number of times a crafted item's name is generated before giving up
+pub const DEFAULT_CRAFT_ATTEMPTS: usize = 3;
+/// Maximum number of tokens in the description of a crafted item
+pub const MAX_DESCRIPTION_TOKENS: usize = 48;
+/// Maximum number of tokens in the quantity inferred by `craft_with_metadata`
+pub const MAX_QUANTITY_TOKENS: usize = 4;
+/// Lowest average token log-probability `try_craft` accepts a result with
+pub const MIN_TRY_CRAFT_SCORE: f32 = -2.5;
+/// Results `try_craft` treats as nothing coming of the combination
+const NOTHING_RESULTS: &[&str] = &["nothing", "none", "nothing happens", "n/a"];
+/// Maximum number of tokens in the ingredients inferred by `decompose`
+pub const MAX_INGREDIENT_TOKENS: usize = 64;
+/// How many times more attempts than requested candidates `craft_candidates` may make
+pub const CANDIDATE_ATTEMPTS_PER_RESULT: usize = 3;
+/// Default maximum number of tokens the examples may take up once learned examples are added
+pub const DEFAULT_MAX_EXAMPLE_TOKENS: usize = 512;
 
 /// Use a Model to infer the results of crafting
 /// two or more items together.
 pub struct Crafter {
     model: Model,
     temp: Option<f64>,
+    theme: Theme,
+    base_examples: Vec<CrafterExample>,
+    learned_examples: VecDeque<CrafterExample>,
+    max_example_tokens: usize,
     examples: String,
+    reverse_examples: String,
+    recipe_book: Option<Mutex<RecipeBook>>,
+    validation: Option<ResultValidation>,
+    max_attempts: usize,
+    synonym_threshold: Option<f32>,
+    known_results: Mutex<VectorMemory>,
 }
 
 impl Crafter {
     pub fn new<'a>(model: Model, temp: Option<f64>, examples: impl IntoIterator<Item = &'a CrafterExample>) -> Self {
-        // Create a string with the examples separated by newlines
-        let examples = examples
-            .into_iter()
-            .map(|example| {
-                format!(
-                    "Combining {} results in {}",
-                    example.items, example.result
-                )
-            })
-            .join("\n");
+        let mut crafter = Self {
+            model,
+            temp,
+            theme: Theme::General,
+            base_examples: examples.into_iter().cloned().collect(),
+            learned_examples: VecDeque::new(),
+            max_example_tokens: DEFAULT_MAX_EXAMPLE_TOKENS,
+            examples: String::new(),
+            reverse_examples: String::new(),
+            recipe_book: None,
+            validation: None,
+            max_attempts: DEFAULT_CRAFT_ATTEMPTS,
+            synonym_threshold: None,
+            known_results: Mutex::new(VectorMemory::new()),
+        };
+        crafter.update_examples();
+        crafter
+    }
+
+    /// Create a crafter with the preset examples and prompt phrasing of `theme`
+    pub fn themed(model: Model, theme: Theme) -> Self {
+        Self::new(model, None, &theme.examples()).with_theme(theme)
+    }
+
+    /// Use the prompt phrasing of `theme`, keeping the current examples
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
 
-        Self { model, temp, examples }
+    pub fn theme(&self) -> Theme {
+        self.theme
+    }
+
+    /// Set the maximum number of tokens the examples may take up before the oldest learned examples are forgotten
+    pub fn with_max_example_tokens(mut self, max_example_tokens: usize) -> Self {
+        self.max_example_tokens = max_example_tokens;
+        self.update_examples();
+        self
+    }
+
+    /// Add the combination as an example for future crafting, such as once the player accepts a result.
+    /// Learning the same items again replaces the old result and counts as the newest example.
+    /// If the examples grow past the token budget, the oldest learned examples are forgotten;
+    /// the examples the crafter was created with are always kept.
+    pub fn learn(&mut self, items: impl IntoIterator<Item = impl Display>, result: impl Display) {
+        let example = CrafterExample::new(items, result);
+
+        // Move the combination to the back if it was already learned
+        self.learned_examples.retain(|learned| !learned.items.eq_ignore_ascii_case(&example.items));
+        self.learned_examples.push_back(example);
+
+        self.update_examples();
+    }
+
+    /// Get the examples learned so far, oldest first
+    pub fn learned_examples(&self) -> impl Iterator<Item = &CrafterExample> {
+        self.learned_examples.iter()
+    }
+
+    /// Rebuild the example strings, forgetting the oldest learned examples until they fit the token budget
+    fn update_examples(&mut self) {
+        loop {
+            let examples = self.base_examples.iter().chain(&self.learned_examples);
+
+            // Create a string with the examples separated by newlines
+            self.examples = examples
+                .clone()
+                .map(|example| {
+                    format!(
+                        "Combining {} results in {}",
+                        example.items, example.result
+                    )
+                })
+                .join("\n");
+
+            // Create the same string with the examples reversed, for decomposing
+            self.reverse_examples = examples
+                .map(|example| format!("{} is made by combining {}", example.result, example.items))
+                .join("\n");
+
+            // Stop once the examples fit or there is nothing left to forget
+            if self.learned_examples.is_empty() || self.model.tokenize_str(&self.examples).len() <= self.max_example_tokens {
+                break;
+            }
+            self.learned_examples.pop_front();
+        }
+    }
+
+    /// Create a crafter from a JSON or TOML file (chosen by the extension) written by `CrafterFile::save`
+    pub fn from_file(model: Model, path: impl AsRef<Path>) -> Result<Self> {
+        let file = CrafterFile::load(path)?;
+        Ok(Self::new(model, file.temp, &file.examples))
+    }
+
+    /// Remember the results of `craft_item` in a recipe book, so the same ingredients always give the same item
+    pub fn with_recipe_book(mut self, recipe_book: RecipeBook) -> Self {
+        self.recipe_book = Some(Mutex::new(recipe_book));
+        self
+    }
+
+    /// Check the names of crafted items against `validation`, regenerating them if they break its rules
+    pub fn with_validation(mut self, validation: ResultValidation) -> Self {
+        self.validation = Some(validation);
+        self
+    }
+
+    /// Replace new results of `craft_item` with an existing result from the recipe book
+    /// if their embeddings have at least `threshold` cosine similarity, so synonyms don't become new items.
+    /// Has no effect without a recipe book.
+    ///
+    /// The embeddings average the model's token embeddings, so they match names with similar wording
+    /// rather than meaning: "Fire Potion" and "Potion of Fire" are likely to merge, "Blaze Tonic" is not,
+    /// and a threshold that's too low will merge different items that share a word.
+    pub fn with_synonym_threshold(mut self, threshold: f32) -> Self {
+        self.synonym_threshold = Some(threshold);
+        self
+    }
+
+    /// Set how many times a crafted item's name is generated before settling for the best failed attempt
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Get a copy of the recipe book, such as to save it, if the crafter has one
+    pub fn recipe_book(&self) -> Option<RecipeBook> {
+        self.recipe_book.as_ref().map(|recipe_book| recipe_book.lock().unwrap().clone())
     }
 
     pub fn craft(&self, items: impl IntoIterator<Item = impl Display>, seed: u64) -> String {
+        // Create the prompt
+        let prompt = self.prompt(items, None, false);
+
+        // Gather the results until a ] is found
+        self.model.infer_iter(prompt, seed, Some(self.temp.unwrap_or(0.0)), None, 1.0, 0)
+            .unwrap()
+            .complete_until("]")
+    }
+
+    /// Craft the items together and return the name of the resulting item, trimmed and without brackets or quotes.
+    /// Returns an error if the model doesn't name an item.
+    /// If the crafter has a recipe book, known recipes are looked up instead of inferred.
+    pub fn craft_item(&self, items: impl IntoIterator<Item = impl Display>, seed: u64) -> Result<String> {
+        let items: Vec<String> = items.into_iter().map(|item| item.to_string()).collect();
+
+        // Look the recipe up first
+        if let Some(recipe_book) = &self.recipe_book {
+            if let Some(result) = recipe_book.lock().unwrap().get(&items, seed) {
+                return Ok(result.to_string());
+            }
+        }
+
+        // Create the prompt and infer the item
+        let prompt = self.prompt(&items, None, false);
+        let item = self.infer_item(&items, prompt, seed, self.temp.unwrap_or(0.0))?;
+        let item = self.canonical_result(item);
+
+        // Remember the recipe for next time
+        if let Some(recipe_book) = &self.recipe_book {
+            recipe_book.lock().unwrap().insert(&items, seed, item.clone());
+        }
+
+        Ok(item)
+    }
+
+    /// Craft iteratively for `steps` steps, starting by crafting `start_items` together.
+    /// Every following step combines the previous result with the next of the start items in turn,
+    /// so [fire, water] might give steam, then [steam, fire], then [engine, water] and so on.
+    /// Returns every step crafted, stopping early with an error if a step fails.
+    pub fn craft_chain(&self, start_items: impl IntoIterator<Item = impl Display>, steps: usize, seed: u64) -> Result<Vec<CraftStep>> {
+        let start_items: Vec<String> = start_items.into_iter().map(|item| item.to_string()).collect();
+        if start_items.is_empty() {
+            bail!("cannot craft a chain without any items")
+        }
+
+        let mut chain: Vec<CraftStep> = Vec::with_capacity(steps);
+        for step in 0..steps {
+            // Combine the previous result with the next start item
+            let items = match chain.last() {
+                Some(previous) => vec![previous.result.clone(), start_items[(step - 1) % start_items.len()].clone()],
+                None => start_items.clone(),
+            };
+
+            // Craft them like craft_item, so the recipe book is used
+            let result = self.craft_item(&items, seed.wrapping_add(step as u64))?;
+            chain.push(CraftStep { items, result });
+        }
+
+        Ok(chain)
+    }
+
+    /// Craft the items together several times with varied seeds and temperatures, returning up to `n` distinct results
+    /// with how confident the model is in each (summing to 1), most likely first.
+    /// The recipe book is not used, since it would only ever give one result.
+    pub fn craft_candidates(&self, items: impl IntoIterator<Item = impl Display>, n: usize, seed: u64) -> Vec<(String, f32)> {
+        let items: Vec<String> = items.into_iter().map(|item| item.to_string()).collect();
+        let prompt = self.prompt(&items, None, false);
+
+        // Generate results, raising the temperature after each attempt so the results vary
+        let mut candidates: Vec<String> = Vec::new();
+        let mut temperature = self.temp.unwrap_or(0.0);
+        for attempt in 0..n.saturating_mul(CANDIDATE_ATTEMPTS_PER_RESULT) {
+            if candidates.len() >= n {
+                break;
+            }
+            let item = self.infer_item(&items, prompt.clone(), seed.wrapping_add(attempt as u64), temperature);
+            temperature += 0.3;
+
+            // Skip failed attempts and duplicates
+            let Ok(item) = item else {
+                continue;
+            };
+            if !candidates.iter().any(|candidate| candidate.eq_ignore_ascii_case(&item)) {
+                candidates.push(item);
+            }
+        }
+
+        // Score each result by how likely the model finds it
+        let scores: Vec<f32> = candidates
+            .iter()
+            .map(|item| self.model.score_continuation(prompt.clone(), format!("{}]", item)).unwrap_or(f32::MIN))
+            .collect();
+
+        // Turn the scores into confidences that sum to 1
+        let mut candidates: Vec<(String, f32)> = candidates.into_iter().zip(softmax(scores)).collect();
+
+        // Most likely first
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        candidates
+    }
+
+    /// Craft the items together, allowing the model to decide nothing comes of the combination.
+    /// Returns None if the model says nothing, repeats one of the items, or isn't confident enough in its result
+    /// (see `MIN_TRY_CRAFT_SCORE`). Accepted results are remembered in the recipe book like `craft_item`.
+    pub fn try_craft(&self, items: impl IntoIterator<Item = impl Display>, seed: u64) -> Option<String> {
+        let items: Vec<String> = items.into_iter().map(|item| item.to_string()).collect();
+
+        // Look the recipe up first
+        if let Some(recipe_book) = &self.recipe_book {
+            if let Some(result) = recipe_book.lock().unwrap().get(&items, seed) {
+                return Some(result.to_string());
+            }
+        }
+
+        // Create the prompt and infer the item
+        let prompt = self.prompt(&items, None, true);
+        let item = self.infer_item(&items, prompt.clone(), seed, self.temp.unwrap_or(0.0)).ok()?;
+
+        // Reject "nothing"
+        let lowercase = item.to_lowercase();
+        if NOTHING_RESULTS.contains(&lowercase.as_str()) {
+            return None;
+        }
+
+        // Reject results that just repeat one of the items
+        if echoes_item(&items, &item) {
+            return None;
+        }
+
+        // Reject results the model finds unlikely
+        let score = self.model.score_continuation(prompt, format!("{}]", item)).ok()?;
+        if score < MIN_TRY_CRAFT_SCORE {
+            return None;
+        }
+
+        // Remember the recipe for next time
+        if let Some(recipe_book) = &self.recipe_book {
+            recipe_book.lock().unwrap().insert(&items, seed, item.clone());
+        }
+
+        Some(item)
+    }
+
+    /// Craft the items together like `craft_item`, then infer a short description of the result
+    pub fn craft_described(&self, items: impl IntoIterator<Item = impl Display>, seed: u64) -> Result<CraftedItem> {
+        let items: Vec<String> = items.into_iter().map(|item| item.to_string()).collect();
+        let name = self.craft_item(&items, seed)?;
+
+        // Continue the response after the result's name
+        let mut prompt = self.prompt(&items, None, false);
+        prompt.push_str(format!("{}]\nDescription:", name));
+
+        // Generate a single line of flavor text
+        let config = GenerationConfig::new(seed)
+            .with_temp(self.temp.unwrap_or(0.0))
+            .with_max_tokens(MAX_DESCRIPTION_TOKENS)
+            .with_stop("\n");
+        let description = self.model.generate(prompt, &config)?.trim().to_string();
+
+        Ok(CraftedItem {
+            name,
+            description: if description.is_empty() { None } else { Some(description) },
+            rarity: None,
+            quantity: 1,
+        })
+    }
+
+    /// Craft the items together like `craft_item`, then infer the result's rarity and how many are made.
+    /// The rarity and quantity are both written by one inference that continues the response after the name.
+    pub fn craft_with_metadata(&self, items: impl IntoIterator<Item = impl Display>, seed: u64) -> Result<CraftedItem> {
+        let items: Vec<String> = items.into_iter().map(|item| item.to_string()).collect();
+        let name = self.craft_item(&items, seed)?;
+        let temp = self.temp.unwrap_or(0.0);
+
+        // Continue the response after the result's name, only letting the model write a known rarity
+        let mut prompt = self.prompt(&items, None, false);
+        prompt.push_str(format!("{}]\nRarity:", name));
+        let mut inference = self.model.infer_with(prompt, &GenerationConfig::new(seed).with_temp(temp))?;
+        let rarities: Vec<String> = Rarity::ALL.iter().map(|rarity| format!(" {}", rarity)).collect();
+        let rarity = self.model.write_by_trie(&mut inference, &rarities, "\n", true).map(|index| Rarity::ALL[index]);
+
+        // Then ask for the quantity in the same inference, ending the rarity line if the model didn't settle on one
+        let label = if rarity.is_some() { "Quantity:" } else { "\nQuantity:" };
+        inference.push_tokens(self.model.tokenize_str(label).as_slice());
+        let quantity = inference.complete_until_any(Some(MAX_QUANTITY_TOKENS), &["\n"]);
+
+        // Read the leading digits, making at least one
+        let quantity = quantity
+            .trim()
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>()
+            .parse::<u32>()
+            .unwrap_or(1)
+            .max(1);
+
+        Ok(CraftedItem { name, description: None, rarity, quantity })
+    }
+
+    /// Craft the items together, restricting the result to one of `allowed_results`.
+    /// Returns the allowed result the model settled on, or None if it didn't settle on any.
+    pub fn craft_from(
+        &self,
+        items: impl IntoIterator<Item = impl Display>,
+        allowed_results: impl IntoIterator<Item = impl AsRef<str>>,
+        seed: u64,
+    ) -> Option<String> {
+        let allowed_results: Vec<String> = allowed_results.into_iter().map(|result| result.as_ref().trim().to_string()).collect();
+        if allowed_results.is_empty() {
+            return None;
+        }
+
+        // Show the model the possible results like so: "[result1][result2][result3]"
+        let possible_results = format!("[{}]", allowed_results.iter().join("]["));
+        let prompt = self.prompt(items, Some(&possible_results), false);
+
+        // Only let the model write one of the allowed results as they are listed, followed by the closing bracket.
+        // The trie always settles on a result, so another attempt is only made if inference ends early.
+        let mut temperature = self.temp.unwrap_or(0.0);
+        for seed in (0..CRAFT_FROM_ATTEMPTS).map(|attempt| seed.wrapping_add(attempt)) {
+            if let Some(chosen) = self.model.choose_by_trie(prompt.clone(), &allowed_results, "]", seed, temperature) {
+                return Some(chosen);
+            }
+            temperature += 0.2;
+        }
+
+        None
+    }
+
+    /// Infer plausible items that could be combined to make `item`, the reverse of crafting.
+    /// Returns an empty list if the model doesn't name any.
+    pub fn decompose(&self, item: impl Display, seed: u64) -> Vec<String> {
+        let item = item.to_string();
+
+        // Generate the instruction
+        let instruction = self.theme.reverse_instruction(&item);
+
+        // Put the reversed examples in extra information
+        let mut extra: HashMap<&str, &str> = HashMap::new();
+        extra.insert("Known Decompositions", &self.reverse_examples);
+
+        // Start the response off to help the model
+        let response_prefix = format!("[{}] is made by combining [", item);
+        extra.insert("Response", &response_prefix);
+        let prompt = self.model.create_instruct_prompt(&instruction, Some(&extra));
+
+        // Generate until the end of the line
+        let config = GenerationConfig::new(seed)
+            .with_temp(self.temp.unwrap_or(0.0))
+            .with_max_tokens(MAX_INGREDIENT_TOKENS)
+            .with_stop("\n");
+        let Ok(result) = self.model.generate(prompt, &config) else {
+            return Vec::new();
+        };
+
+        // Split the ingredients on the "+" separators, ignoring anything after the last bracket
+        let result = match result.rfind(']') {
+            Some(end) => &result[..end],
+            None => &result,
+        };
+        result
+            .split('+')
+            .map(|ingredient| {
+                ingredient
+                    .trim_matches(|c: char| c.is_whitespace() || matches!(c, '[' | ']' | '"' | '\'' | '.'))
+                    .split_whitespace()
+                    .join(" ")
+            })
+            .filter(|ingredient| !ingredient.is_empty())
+            .unique_by(|ingredient| ingredient.to_lowercase())
+            .collect()
+    }
+
+    /// Find the result in the recipe book that `item` is a synonym of, if synonyms are being merged.
+    /// Returns `item` itself if there is none.
+    fn canonical_result(&self, item: String) -> String {
+        let (Some(threshold), Some(recipe_book)) = (self.synonym_threshold, &self.recipe_book) else {
+            return item;
+        };
+        let results: Vec<String> = recipe_book.lock().unwrap().results().map(str::to_string).collect();
+
+        // Use the existing spelling if the result is already known
+        if let Some(result) = results.iter().find(|result| result.eq_ignore_ascii_case(&item)) {
+            return result.clone();
+        }
+
+        // Embed any results that haven't been embedded yet, leaving out any that can't be embedded
+        let mut known_results = self.known_results.lock().unwrap();
+        for result in &results {
+            if !known_results.entries().iter().any(|entry| entry.text == *result) {
+                let _ = known_results.insert(&self.model, result);
+            }
+        }
+
+        // Use the most similar known result if it is similar enough
+        match known_results.search(&self.model, &item, 1) {
+            Ok(found) => match found.first() {
+                Some((entry, similarity)) if *similarity >= threshold => entry.text.clone(),
+                _ => item,
+            },
+            Err(_) => item,
+        }
+    }
+
+    /// Generate the name of an item after a crafting prompt, trimmed and without brackets or quotes.
+    /// Names that are empty, repeat one of the items or break the crafter's validation are regenerated
+    /// with the next seed and a higher temperature, up to the crafter's maximum number of attempts.
+    /// If every attempt fails, the earliest name that broke the fewest of those rules is returned instead.
+    /// Returns an error if the model never named an item.
+    fn infer_item(&self, items: &[String], prompt: TokenString, seed: u64, temp: f64) -> Result<String> {
+        let mut temp = temp;
+        let mut best: Option<(usize, String)> = None;
+        for seed in (0..self.max_attempts.max(1) as u64).map(|attempt| seed.wrapping_add(attempt)) {
+            // Generate until the closing bracket or the end of the line
+            let config = GenerationConfig::new(seed)
+                .with_temp(temp)
+                .with_max_tokens(MAX_ITEM_TOKENS)
+                .with_stop("]")
+                .with_stop("\n");
+            let result = self.model.generate(prompt.clone(), &config)?;
+            temp += 0.2;
+
+            // Strip stray punctuation and collapse whitespace
+            let item = result
+                .trim_matches(|c: char| c.is_whitespace() || matches!(c, '[' | ']' | '"' | '\'' | '.'))
+                .split_whitespace()
+                .join(" ");
+            if item.is_empty() {
+                continue;
+            }
+
+            // Check the name against the validation and the items
+            let valid = self.validation.as_ref().is_none_or(|validation| validation.validate(&item).is_ok());
+            let echoes = echoes_item(items, &item);
+            if valid && !echoes {
+                return Ok(item);
+            }
+
+            // Keep the name that broke the fewest rules in case every attempt fails
+            let broken = usize::from(!valid) + usize::from(echoes);
+            if best.as_ref().is_none_or(|(fewest, _)| broken < *fewest) {
+                best = Some((broken, item));
+            }
+        }
+
+        best.map(|(_, item)| item)
+            .ok_or_else(|| anyhow!("the model did not name an item for the combination"))
+    }
+
+    /// Create the prompt that asks what combining the items results in,
+    /// optionally listing the results the model may choose from or allowing it to answer [nothing]
+    fn prompt(&self, items: impl IntoIterator<Item = impl Display>, possible_results: Option<&str>, allow_nothing: bool) -> TokenString {
         // Join the items with a "] + [" separator
         let joined_items = items.into_iter().join("] + [");
 
         // Generate the instruction
-        let instruction = format!("What might you get by combining [{}]? Be creative and use the examples.", joined_items);
+        let mut instruction = self.theme.instruction(&joined_items);
+        if allow_nothing {
+            instruction.push_str(" If combining them makes no sense, answer [nothing].");
+        }
 
         // Put examples in extra information
         let mut extra: HashMap<&str, &str> = HashMap::new();
         extra.insert("Known Combinations", &self.examples);
+        if let Some(possible_results) = possible_results {
+            extra.insert("Possible Results", possible_results);
+        }
 
         // Start the response off to help the model
         let response_prefix = format!("If you combine [{}] you get: [", joined_items);
         extra.insert("Response", &response_prefix);
 
-        // Gather the results until a ] is found
-        self.model.instruct(&instruction, Some(&extra), seed, Some(self.temp.unwrap_or(0.0)), None, 1.0, 0)
-            .complete_until("]")
+        self.model.create_instruct_prompt(&instruction, Some(&extra))
+    }
+}
+
+/// Whether `result` just repeats one of the items, ignoring case and any details in parentheses
+fn echoes_item(items: &[String], result: &str) -> bool {
+    items.iter().any(|item| {
+        let name = item.split('(').next().unwrap().trim();
+        name.eq_ignore_ascii_case(result)
+    })
+}
+
+/// An item to craft with, which may have a description and tags that change what it crafts into.
+/// Displays as "name (description, tag, tag)", such as "sword (rusty)".
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ingredient {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Ingredient {
+    pub fn new(name: impl Display) -> Self {
+        Self { name: name.to_string(), description: None, tags: Vec::new() }
+    }
+
+    pub fn with_description(mut self, description: impl Display) -> Self {
+        self.description = Some(description.to_string());
+        self
     }
+
+    pub fn with_tag(mut self, tag: impl Display) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+}
+
+impl Display for Ingredient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        // Put the description and tags in parentheses after the name
+        let details = self.description.iter().chain(&self.tags).join(", ");
+        if !details.is_empty() {
+            write!(f, " ({})", details)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// One step of `Crafter::craft_chain`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CraftStep {
+    pub items: Vec<String>,
+    pub result: String,
+}
+
+/// How rare a crafted item is
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Rarity {
+    #[default]
+    Common,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary,
+}
+
+impl Rarity {
+    /// Every rarity, from most to least common
+    pub const ALL: [Rarity; 5] = [Rarity::Common, Rarity::Uncommon, Rarity::Rare, Rarity::Epic, Rarity::Legendary];
+}
+
+impl Display for Rarity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rarity::Common => write!(f, "common"),
+            Rarity::Uncommon => write!(f, "uncommon"),
+            Rarity::Rare => write!(f, "rare"),
+            Rarity::Epic => write!(f, "epic"),
+            Rarity::Legendary => write!(f, "legendary"),
+        }
+    }
+}
+
+/// The result of `Crafter::craft_described` or `Crafter::craft_with_metadata`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CraftedItem {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub rarity: Option<Rarity>,
+    #[serde(default = "default_quantity")]
+    pub quantity: u32,
+}
+
+fn default_quantity() -> u32 {
+    1
 }
 
+/// A crafter's temperature and examples, as stored in a JSON or TOML file
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CrafterFile {
+    #[serde(default)]
+    pub temp: Option<f64>,
+    #[serde(default)]
+    pub examples: Vec<CrafterExample>,
+}
+
+impl CrafterFile {
+    pub fn new(temp: Option<f64>, examples: impl IntoIterator<Item = CrafterExample>) -> Self {
+        Self { temp, examples: examples.into_iter().collect() }
+    }
+
+    /// Read the file as TOML if it ends in `.toml`, and as JSON otherwise
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        if is_toml(path) {
+            Ok(toml::from_str(&text)?)
+        } else {
+            Ok(serde_json::from_str(&text)?)
+        }
+    }
+
+    /// Write the file as TOML if it ends in `.toml`, and as JSON otherwise
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = if is_toml(path) {
+            toml::to_string_pretty(self)?
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("toml"))
+}
+
+/// An example combination shown to the model.
+/// In files it is written as a list of item names and a result name, without brackets.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "CrafterExampleData", into = "CrafterExampleData")]
 pub struct CrafterExample {
     pub items: String,
     pub result: String,
 }
 
+#[derive(Serialize, Deserialize)]
+struct CrafterExampleData {
+    items: Vec<String>,
+    result: String,
+}
+
+impl From<CrafterExampleData> for CrafterExample {
+    fn from(data: CrafterExampleData) -> Self {
+        Self::new(data.items, data.result)
+    }
+}
+
+impl From<CrafterExample> for CrafterExampleData {
+    fn from(example: CrafterExample) -> Self {
+        // Strip the brackets added by CrafterExample::new
+        let unbracket = |s: &str| s.trim().trim_start_matches('[').trim_end_matches(']').to_string();
+        Self {
+            items: example.items.split("] + [").map(unbracket).collect(),
+            result: unbracket(&example.result),
+        }
+    }
+}
+
 impl CrafterExample {
     pub fn new(items: impl IntoIterator<Item = impl Display>, result: impl Display) -> Self {
         // Format the items as a string with a "+" separator
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+
+use anyhow::Result;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// Whether the results in a `RecipeBook` depend on the seed they were crafted with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SeedPolicy {
+    /// The same ingredients always give the same result
+    #[default]
+    Ignore,
+    /// Results are remembered separately for every seed
+    PerSeed,
+}
+
+/// Remembers the results of crafting so the same ingredients always give the same item.
+/// Ingredients are matched regardless of order, case and surrounding whitespace.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecipeBook {
+    pub seed_policy: SeedPolicy,
+    recipes: BTreeMap<String, String>,
+}
+
+impl RecipeBook {
+    pub fn new(seed_policy: SeedPolicy) -> Self {
+        Self {
+            seed_policy,
+            recipes: BTreeMap::new(),
+        }
+    }
+
+    /// Create the key the result of crafting `items` with `seed` is stored under
+    fn key(&self, items: impl IntoIterator<Item = impl Display>, seed: u64) -> String {
+        let items = items
+            .into_iter()
+            .map(|item| item.to_string().trim().to_lowercase())
+            .sorted()
+            .join(" + ");
+        match self.seed_policy {
+            SeedPolicy::Ignore => items,
+            SeedPolicy::PerSeed => format!("{} #{}", items, seed),
+        }
+    }
+
+    /// Get the remembered result of crafting the items, if there is one
+    pub fn get(&self, items: impl IntoIterator<Item = impl Display>, seed: u64) -> Option<&str> {
+        self.recipes.get(&self.key(items, seed)).map(String::as_str)
+    }
+
+    /// Remember the result of crafting the items, returning the previous result if there was one
+    pub fn insert(
+        &mut self,
+        items: impl IntoIterator<Item = impl Display>,
+        seed: u64,
+        result: impl Into<String>,
+    ) -> Option<String> {
+        let key = self.key(items, seed);
+        self.recipes.insert(key, result.into())
+    }
+
+    /// Get an iterator over the ingredients and results of every recipe, sorted by ingredients
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.recipes
+            .iter()
+            .map(|(items, result)| (items.as_str(), result.as_str()))
+    }
+
+    /// Get the distinct results of every recipe, in the order of their first recipe
+    pub fn results(&self) -> impl Iterator<Item = &str> {
+        self.recipes.values().map(String::as_str).unique()
+    }
+
+    pub fn len(&self) -> usize {
+        self.recipes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recipes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.recipes.clear();
+    }
+
+    /// Write the recipe book to a JSON file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Read a recipe book from a JSON file written by `RecipeBook::save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
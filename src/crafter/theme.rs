@@ -0,0 +1,119 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use super::CrafterExample;
+
+/// A preset set of examples and prompt phrasing for a common kind of crafting
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Theme {
+    /// Anything goes, like the examples the crafter is given
+    #[default]
+    General,
+    /// Combining substances and reagents into potions and artifacts
+    Alchemy,
+    /// Cooking ingredients into dishes
+    Cooking,
+    /// Building gadgets out of parts
+    Tinkering,
+    /// Blending the meanings of words into new words or concepts
+    WordBlending,
+}
+
+impl Theme {
+    /// Every theme, in declaration order
+    pub const ALL: [Theme; 5] = [
+        Theme::General,
+        Theme::Alchemy,
+        Theme::Cooking,
+        Theme::Tinkering,
+        Theme::WordBlending,
+    ];
+
+    /// The preset examples for this theme
+    pub fn examples(&self) -> Vec<CrafterExample> {
+        let examples: &[(&[&str], &str)] = match self {
+            Theme::General => &[
+                (&["water", "fire"], "steam"),
+                (&["earth", "water"], "mud"),
+                (&["human", "hammer"], "construction worker"),
+                (&["clown", "tent"], "circus"),
+                (&["sugar", "water", "bee"], "honey"),
+                (&["hope", "despair"], "life"),
+            ],
+            Theme::Alchemy => &[
+                (&["salt", "sulfur", "mercury"], "philosopher's stone"),
+                (&["mandrake root", "moonwater"], "sleeping draught"),
+                (&["iron", "dragon's blood"], "fireproof ingot"),
+                (&["feather", "quicksilver"], "levitation potion"),
+                (&["nightshade", "wine"], "poisoned chalice"),
+                (&["lead", "sunlight"], "gold"),
+            ],
+            Theme::Cooking => &[
+                (&["flour", "water", "yeast"], "bread"),
+                (&["egg", "milk", "sugar"], "custard"),
+                (&["tomato", "basil", "dough"], "pizza"),
+                (&["rice", "fish", "seaweed"], "sushi"),
+                (&["potato", "oil"], "french fries"),
+                (&["bread", "cheese", "fire"], "grilled cheese"),
+            ],
+            Theme::Tinkering => &[
+                (&["gear", "spring"], "clockwork"),
+                (&["wire", "battery", "bulb"], "flashlight"),
+                (&["lens", "tube"], "telescope"),
+                (&["motor", "propeller"], "fan"),
+                (&["clockwork", "bird"], "mechanical songbird"),
+                (&["magnet", "needle", "water"], "compass"),
+            ],
+            Theme::WordBlending => &[
+                (&["breakfast", "lunch"], "brunch"),
+                (&["smoke", "fog"], "smog"),
+                (&["motor", "hotel"], "motel"),
+                (&["space", "sailor"], "astronaut"),
+                (&["time", "money"], "wages"),
+                (&["snow", "man"], "snowman"),
+            ],
+        };
+
+        examples
+            .iter()
+            .map(|(items, result)| CrafterExample::new(items.iter(), result))
+            .collect()
+    }
+
+    /// The instruction asking what combining the items (already joined with "] + [") results in
+    pub(crate) fn instruction(&self, joined_items: impl Display) -> String {
+        match self {
+            Theme::General => format!("What might you get by combining [{}]? Be creative and use the examples.", joined_items),
+            Theme::Alchemy => format!("You are an alchemist. What substance or artifact might you create by combining [{}] in your laboratory? Use the examples.", joined_items),
+            Theme::Cooking => format!("You are a chef. What dish might you make by cooking [{}] together? Use the examples.", joined_items),
+            Theme::Tinkering => format!("You are an inventor. What gadget might you build out of [{}]? Use the examples.", joined_items),
+            Theme::WordBlending => format!("What word or concept might you get by blending the meanings of [{}]? Use the examples.", joined_items),
+        }
+    }
+
+    /// The instruction asking what items might be combined to make the item
+    pub(crate) fn reverse_instruction(&self, item: impl Display) -> String {
+        match self {
+            Theme::General => format!("What items might be combined to make [{}]? Be creative and use the examples.", item),
+            Theme::Alchemy => format!("You are an alchemist. What substances might be combined to create [{}]? Use the examples.", item),
+            Theme::Cooking => format!("You are a chef. What ingredients might be cooked together to make [{}]? Use the examples.", item),
+            Theme::Tinkering => format!("You are an inventor. What parts might be used to build [{}]? Use the examples.", item),
+            Theme::WordBlending => format!("What words might be blended together to get [{}]? Use the examples.", item),
+        }
+    }
+}
+
+impl FromStr for Theme {
+    type Err = anyhow::Error;
+
+    /// Parse a theme from its name, ignoring case, such as "alchemy" or "WordBlending"
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match Theme::ALL.into_iter().find(|theme| format!("{:?}", theme).eq_ignore_ascii_case(s.trim())) {
+            Some(theme) => Ok(theme),
+            None => bail!("unknown theme {:?}", s),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Rules a crafted item's name must follow.
+/// Results that break them are regenerated up to the crafter's maximum number of attempts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResultValidation {
+    /// Words and phrases the name may not contain, matched as whole words regardless of case
+    pub banned_words: Vec<String>,
+    /// Maximum number of characters in the name
+    pub max_chars: Option<usize>,
+    /// Maximum number of words in the name
+    pub max_words: Option<usize>,
+    /// Characters besides letters, digits and spaces the name may contain, or None to allow anything
+    pub allowed_punctuation: Option<String>,
+}
+
+impl ResultValidation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_banned_word(mut self, word: impl Into<String>) -> Self {
+        self.banned_words.push(word.into());
+        self
+    }
+
+    pub fn with_max_chars(mut self, max_chars: impl Into<Option<usize>>) -> Self {
+        self.max_chars = max_chars.into();
+        self
+    }
+
+    pub fn with_max_words(mut self, max_words: impl Into<Option<usize>>) -> Self {
+        self.max_words = max_words.into();
+        self
+    }
+
+    pub fn with_allowed_punctuation(mut self, allowed_punctuation: impl Into<Option<String>>) -> Self {
+        self.allowed_punctuation = allowed_punctuation.into();
+        self
+    }
+
+    /// Returns an error describing the first rule the name breaks
+    pub fn validate(&self, name: &str) -> Result<()> {
+        // Check the length
+        if let Some(max_chars) = self.max_chars {
+            if name.chars().count() > max_chars {
+                bail!("{:?} is longer than {} characters", name, max_chars)
+            }
+        }
+        let words: Vec<String> = name
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        if let Some(max_words) = self.max_words {
+            if words.len() > max_words {
+                bail!("{:?} is longer than {} words", name, max_words)
+            }
+        }
+
+        // Check the characters
+        if let Some(allowed_punctuation) = &self.allowed_punctuation {
+            let disallowed = name
+                .chars()
+                .find(|&c| !c.is_alphanumeric() && c != ' ' && !allowed_punctuation.contains(c));
+            if let Some(c) = disallowed {
+                bail!("{:?} contains the character {:?}", name, c)
+            }
+        }
+
+        // Check for banned words, matching phrases of several words as a whole
+        for banned in &self.banned_words {
+            let banned_words: Vec<String> = banned.split_whitespace().map(str::to_lowercase).collect();
+            let found = !banned_words.is_empty()
+                && words
+                    .windows(banned_words.len())
+                    .any(|window| window == banned_words.as_slice());
+            if found {
+                bail!("{:?} contains the banned word {:?}", name, banned)
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ResultValidation {
+    /// Short names made of words, rejecting prompt headings the model sometimes repeats
+    fn default() -> Self {
+        Self {
+            banned_words: vec!["response".to_string(), "instruction".to_string()],
+            max_chars: Some(40),
+            max_words: Some(5),
+            allowed_punctuation: Some("'-&".to_string()),
+        }
+    }
+}
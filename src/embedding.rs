@@ -0,0 +1,125 @@
+use anyhow::Result;
+use candle_core::{DType, Tensor};
+use serde::{Deserialize, Serialize};
+
+use crate::model::Model;
+use crate::token_string::IntoTokenString;
+
+impl Model {
+    /// Embed the text into a normalized vector by averaging the model's token embeddings.
+    /// Texts with similar wording and topics end up close together.
+    /// Returns an error if the text is empty.
+    pub fn embed(&self, text: impl IntoTokenString) -> Result<Vec<f32>> {
+        // Tokenize the text
+        let tokens = self.tokenize(text);
+        if tokens.is_empty() {
+            anyhow::bail!("cannot embed empty text")
+        }
+
+        // Average the embeddings of the tokens
+        let ids = Tensor::new(tokens.as_slice(), self.device())?;
+        let embedding = self
+            .token_embeddings()?
+            .index_select(&ids, 0)?
+            .mean(0)?
+            .to_dtype(DType::F32)?
+            .to_vec1::<f32>()?;
+
+        Ok(normalize(embedding))
+    }
+}
+
+/// Scale a vector to unit length
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let length = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if length > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= length);
+    }
+    vector
+}
+
+/// Get the cosine similarity of two vectors, from -1.0 to 1.0
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let length_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let length_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if length_a == 0.0 || length_b == 0.0 {
+        0.0
+    } else {
+        dot / (length_a * length_b)
+    }
+}
+
+/// A text stored in a `VectorMemory` along with its embedding
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+/// A store of texts that can be searched by similarity to a query
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct VectorMemory {
+    entries: Vec<MemoryEntry>,
+}
+
+impl VectorMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Embed a text and add it to the memory
+    pub fn insert(&mut self, model: &Model, text: impl AsRef<str>) -> Result<()> {
+        let text = text.as_ref().trim();
+        let embedding = model.embed(text)?;
+        self.insert_embedded(text, embedding);
+        Ok(())
+    }
+
+    /// Add a text that was already embedded to the memory
+    pub fn insert_embedded(&mut self, text: impl Into<String>, embedding: Vec<f32>) {
+        self.entries.push(MemoryEntry {
+            text: text.into(),
+            embedding,
+        });
+    }
+
+    /// Find the `k` entries most similar to the query, most similar first
+    pub fn search(
+        &self,
+        model: &Model,
+        query: impl AsRef<str>,
+        k: usize,
+    ) -> Result<Vec<(&MemoryEntry, f32)>> {
+        let query = model.embed(query.as_ref())?;
+        Ok(self.search_embedded(&query, k))
+    }
+
+    /// Find the `k` entries most similar to an already embedded query, most similar first
+    pub fn search_embedded(&self, query: &[f32], k: usize) -> Vec<(&MemoryEntry, f32)> {
+        let mut scored: Vec<(&MemoryEntry, f32)> = self
+            .entries
+            .iter()
+            .map(|entry| (entry, cosine_similarity(query, &entry.embedding)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(k);
+        scored
+    }
+
+    pub fn entries(&self) -> &[MemoryEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
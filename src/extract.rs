@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Maximum number of entities `extract_entities` will find in one text
+pub const MAX_ENTITIES: usize = 16;
+/// Maximum number of tokens in the text of an entity
+const MAX_ENTITY_TOKENS: usize = 16;
+/// What the model writes to end the list of entities
+const END_OF_ENTITIES: &str = "done";
+
+/// Maximum number of tokens per keyword `keywords` allows the model
+const TOKENS_PER_KEYWORD: usize = 8;
+
+/// Something mentioned in a text, like a character, place or item
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Entity {
+    /// The type of the entity, as passed to `extract_entities`
+    pub kind: String,
+    /// The entity as it is written in the text
+    pub text: String,
+    /// Where the entity is first mentioned in the text, in bytes
+    pub span: Range<usize>,
+}
+
+impl Model {
+    /// Find the entities of the given types mentioned in the text, such as "character", "place" and "item".
+    /// The model writes one entity per line, and is only allowed to start each line with one of the types.
+    /// Entities that don't appear in the text are left out, and each entity is only returned once.
+    pub fn extract_entities(
+        &self,
+        text: impl AsRef<str>,
+        entity_types: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Vec<Entity> {
+        let text = text.as_ref();
+        let entity_types: Vec<String> = entity_types
+            .into_iter()
+            .map(|entity_type| entity_type.as_ref().trim().to_string())
+            .filter(|entity_type| !entity_type.is_empty())
+            .collect();
+        if text.trim().is_empty() || entity_types.is_empty() {
+            return Vec::new();
+        }
+
+        // Create the prompt
+        let instruction = format!(
+            "List every {} mentioned in the text, one per line as \"type: name\", using the exact words from the text. Write \"{}\" after the last one.",
+            entity_types.join(", "),
+            END_OF_ENTITIES
+        );
+        let types_string = entity_types.join(", ");
+        let mut extra = HashMap::new();
+        extra.insert("Text", text);
+        extra.insert("Types", types_string.as_str());
+        extra.insert("Response", "");
+        let mut prompt = self.create_instruct_prompt(instruction, Some(&extra));
+
+        // Each line may only start with one of the types, or end the list
+        let mut line_starts: Vec<String> = entity_types
+            .iter()
+            .map(|entity_type| format!("{}:", entity_type.to_lowercase()))
+            .collect();
+        line_starts.push(END_OF_ENTITIES.to_string());
+
+        let config = GenerationConfig::new(0)
+            .with_max_tokens(MAX_ENTITY_TOKENS)
+            .with_stop("\n");
+        let mut entities: Vec<Entity> = Vec::new();
+        for _ in 0..MAX_ENTITIES {
+            // Choose the type of the next entity
+            let Some(line_start) = self.choose_by_trie(prompt.clone(), &line_starts, "", 0, 0.0)
+            else {
+                break;
+            };
+            let Some(index) = line_starts.iter().position(|start| *start == line_start) else {
+                break;
+            };
+            if index == entity_types.len() {
+                break;
+            }
+
+            // Generate the entity itself
+            prompt.push_str(&line_start);
+            let Ok(name) = self.generate(prompt.clone(), &config) else {
+                break;
+            };
+            prompt.push_str(format!("{}\n", name));
+
+            // Only keep entities that are really in the text
+            let name = name
+                .trim()
+                .trim_matches(|c: char| matches!(c, '"' | '\'' | '.'));
+            let Some(span) = find_span(text, name) else {
+                continue;
+            };
+            let entity = Entity {
+                kind: entity_types[index].clone(),
+                text: text[span.clone()].to_string(),
+                span,
+            };
+            if !entities.iter().any(|existing| {
+                existing.kind == entity.kind && existing.text.eq_ignore_ascii_case(&entity.text)
+            }) {
+                entities.push(entity);
+            }
+        }
+
+        entities
+    }
+}
+
+impl Model {
+    /// Find up to `n` salient keywords or topics of the text, lowercased and without duplicates,
+    /// such as to index it for retrieval
+    pub fn keywords(&self, text: impl AsRef<str>, n: usize) -> Vec<String> {
+        let text = text.as_ref();
+        if text.trim().is_empty() || n == 0 {
+            return Vec::new();
+        }
+
+        // Ask for a comma separated list on one line
+        let instruction = format!(
+            "List the {} most important keywords or topics of the text, separated by commas.",
+            n
+        );
+        let mut extra = HashMap::new();
+        extra.insert("Text", text);
+        extra.insert("Response", "Keywords:");
+        let config = GenerationConfig::new(0)
+            .with_max_tokens(n * TOKENS_PER_KEYWORD)
+            .with_stop("\n");
+        let Ok(keywords) = self.instruct_with(instruction, Some(&extra), &config) else {
+            return Vec::new();
+        };
+
+        parse_keywords(&keywords, n)
+    }
+}
+
+/// Split a comma separated list of keywords, cleaning up each keyword and leaving out duplicates
+pub(crate) fn parse_keywords(list: &str, n: usize) -> Vec<String> {
+    let mut keywords: Vec<String> = Vec::new();
+    for keyword in list.split([',', ';']) {
+        // Strip list markers and punctuation around the keyword
+        let keyword = keyword
+            .trim()
+            .trim_start_matches(|c: char| c == '-' || c == '*' || c.is_ascii_digit())
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        if !keyword.is_empty() && !keywords.contains(&keyword) {
+            keywords.push(keyword);
+        }
+    }
+    keywords.truncate(n);
+    keywords
+}
+
+/// Find the first mention of `name` in the text, ignoring ASCII case
+fn find_span(text: &str, name: &str) -> Option<Range<usize>> {
+    if name.is_empty() {
+        return None;
+    }
+    let start = text
+        .find(name)
+        .or_else(|| text.to_ascii_lowercase().find(&name.to_ascii_lowercase()))?;
+    Some(start..start + name.len())
+}
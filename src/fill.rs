@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Default maximum number of tokens in the text generated between a prefix and suffix
+pub const DEFAULT_FILL_TOKENS: usize = 128;
+/// Number of candidate middles `fill` generates and compares
+pub const FILL_CANDIDATES: u64 = 3;
+/// Temperature the candidates are sampled with when the config has none, so they differ
+pub const DEFAULT_FILL_TEMP: f64 = 0.8;
+/// Number of words at the start of the suffix that end the generated middle when written
+const SUFFIX_STOP_WORDS: usize = 3;
+
+impl Model {
+    /// Generate the text that goes between `prefix` and `suffix`, such as a story that has to end
+    /// with "...and that is why the bridge is cursed." Several candidates are generated with
+    /// consecutive seeds, and the one the suffix follows most naturally is returned, without the prefix or suffix.
+    /// Without a temperature in the config, candidates are sampled at `DEFAULT_FILL_TEMP`.
+    /// Returns an error if both the prefix and suffix are empty.
+    pub fn fill(
+        &self,
+        prefix: impl AsRef<str>,
+        suffix: impl AsRef<str>,
+        config: &GenerationConfig,
+    ) -> Result<String> {
+        let prefix = prefix.as_ref();
+        let suffix = suffix.as_ref();
+        if prefix.trim().is_empty() && suffix.trim().is_empty() {
+            bail!("cannot fill between an empty prefix and suffix")
+        }
+
+        // Show the model the ending it has to lead into
+        let instruction = "Continue the text so that it leads naturally into the ending. \
+            Stop right before the ending.";
+        let mut extra = HashMap::new();
+        extra.insert("Ending", suffix.trim().to_string());
+
+        // Stop once the model starts writing the ending itself
+        let suffix_start = suffix.split_whitespace().take(SUFFIX_STOP_WORDS).join(" ");
+        let mut fill_config = GenerationConfig {
+            temp: config.temp.or(Some(DEFAULT_FILL_TEMP)),
+            max_tokens: Some(config.max_tokens.unwrap_or(DEFAULT_FILL_TOKENS)),
+            ..config.clone()
+        }
+        .with_stop("###");
+        if !suffix_start.is_empty() {
+            fill_config = fill_config.with_stop(suffix_start);
+        }
+
+        let mut best: Option<(String, f32)> = None;
+        let mut scored: Vec<String> = Vec::new();
+        for seed in (0..FILL_CANDIDATES).map(|i| config.seed.wrapping_add(i)) {
+            extra.insert("Response", prefix.to_string());
+            let middle = self.instruct_with(
+                instruction,
+                Some(&extra),
+                &fill_config.clone().with_seed(seed),
+            )?;
+            if suffix.trim().is_empty() {
+                return Ok(middle);
+            }
+
+            // Only score each distinct candidate once
+            if scored.contains(&middle) {
+                continue;
+            }
+            scored.push(middle.clone());
+
+            // Score how naturally the suffix follows the prefix and middle
+            extra.insert("Response", format!("{}{}", prefix, middle));
+            let prompt = self.create_instruct_prompt(instruction, Some(&extra));
+            let score = self.score_continuation(prompt, suffix)?;
+            if best
+                .as_ref()
+                .is_none_or(|(_, best_score)| score > *best_score)
+            {
+                best = Some((middle, score));
+            }
+        }
+
+        match best {
+            Some((middle, _)) => Ok(middle),
+            None => bail!("no candidate was generated to fill the text"),
+        }
+    }
+}
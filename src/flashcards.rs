@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use crate::embedding::cosine_similarity;
+use crate::generation::GenerationConfig;
+use crate::model::{Model, MAX_TOKENS};
+
+/// Maximum number of tokens in a single flashcard
+pub const MAX_FLASHCARD_TOKENS: usize = 96;
+/// Similarity of the fronts of two cards above which the later card is left out as a duplicate
+pub const DUPLICATE_CARD_SIMILARITY: f32 = 0.95;
+
+impl Model {
+    /// Generate up to `n` question and answer flashcards from the text, as (front, back) pairs.
+    /// Texts longer than `SUMMARY_CHUNK_TOKENS` are split into chunks that each get some of the cards,
+    /// and cards whose fronts are nearly identical to an earlier card are left out.
+    /// Each chunk is sampled with the next seed after the config's, at a temperature of 0.3 if the config has none.
+    pub fn generate_flashcards(
+        &self,
+        text: impl AsRef<str>,
+        n: usize,
+        config: &GenerationConfig,
+    ) -> Vec<(String, String)> {
+        let text = text.as_ref().trim();
+        if text.is_empty() || n == 0 {
+            return Vec::new();
+        }
+
+        // Share the cards between the chunks, asking for one extra per chunk to make up for duplicates
+        let chunks = self.split_into_chunks(text);
+        let cards_per_chunk = n.div_ceil(chunks.len()) + 1;
+
+        let mut cards: Vec<(String, String)> = Vec::new();
+        let mut fronts: Vec<Vec<f32>> = Vec::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let instruction = format!(
+                "Write {} flashcards for studying the text. Write each card as a question after \"Q:\" \
+                and its answer after \"A:\", on separate lines.",
+                cards_per_chunk
+            );
+            let mut extra = HashMap::new();
+            extra.insert("Text", chunk.as_str());
+            extra.insert("Response", "Q:");
+
+            // Leave the cards no more room than the context window has
+            let prompt_tokens = self
+                .create_instruct_prompt(&instruction, Some(&extra))
+                .len();
+            let room = MAX_TOKENS.saturating_sub(prompt_tokens);
+            if room == 0 {
+                continue;
+            }
+            let chunk_config = GenerationConfig {
+                seed: config.seed.wrapping_add(index as u64),
+                temp: config.temp.or(Some(0.3)),
+                max_tokens: Some((cards_per_chunk * MAX_FLASHCARD_TOKENS).min(room)),
+                ..config.clone()
+            }
+            .with_stop("###");
+            let Ok(list) = self.instruct_with(instruction, Some(&extra), &chunk_config) else {
+                continue;
+            };
+
+            // Keep the cards that aren't nearly identical to an earlier card
+            for (front, back) in parse_flashcards(&format!("Q:{}", list)) {
+                let Ok(embedding) = self.embed(&front) else {
+                    continue;
+                };
+                if fronts
+                    .iter()
+                    .any(|other| cosine_similarity(&embedding, other) > DUPLICATE_CARD_SIMILARITY)
+                {
+                    continue;
+                }
+                fronts.push(embedding);
+                cards.push((front, back));
+            }
+        }
+
+        cards.truncate(n);
+        cards
+    }
+}
+
+/// Parse cards written as a "Q: ..." line followed by an "A: ..." line, ignoring anything else
+pub(crate) fn parse_flashcards(text: &str) -> Vec<(String, String)> {
+    let mut cards = Vec::new();
+    let mut front: Option<String> = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(question) = line.strip_prefix("Q:") {
+            front = Some(question.trim().to_string());
+        } else if let Some(answer) = line.strip_prefix("A:") {
+            let answer = answer.trim();
+            match front.take() {
+                Some(question) if !question.is_empty() && !answer.is_empty() => {
+                    cards.push((question, answer.to_string()))
+                }
+                _ => {}
+            }
+        }
+    }
+    cards
+}
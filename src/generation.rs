@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::model::{find_stop, InferIter, Model, TokenDecoder, MAX_TOKENS};
+use crate::token_string::{IntoTokenString, TokenString};
+
+/// Sampling and stopping parameters used when generating text
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GenerationConfig {
+    /// Seed added to the model seed when sampling
+    pub seed: u64,
+    /// Sampling temperature, or `None` for greedy sampling
+    pub temp: Option<f64>,
+    /// Nucleus sampling probability cutoff
+    pub top_p: Option<f64>,
+    /// Penalty applied to tokens that appeared in the last `repeat_last_n` tokens
+    pub repeat_penalty: f32,
+    pub repeat_last_n: usize,
+    /// Maximum number of tokens to generate, or `None` to run until the end of text token
+    pub max_tokens: Option<usize>,
+    /// Generation stops as soon as any of these strings is generated
+    pub stop: Vec<String>,
+    /// Tokens the model is never allowed to generate
+    #[serde(default)]
+    pub banned_tokens: Vec<u32>,
+}
+
+impl GenerationConfig {
+    /// Create a config with the default parameters and the given seed
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_temp(mut self, temp: impl Into<Option<f64>>) -> Self {
+        self.temp = temp.into();
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: impl Into<Option<f64>>) -> Self {
+        self.top_p = top_p.into();
+        self
+    }
+
+    pub fn with_repeat_penalty(mut self, repeat_penalty: f32, repeat_last_n: usize) -> Self {
+        self.repeat_penalty = repeat_penalty;
+        self.repeat_last_n = repeat_last_n;
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: impl Into<Option<usize>>) -> Self {
+        self.max_tokens = max_tokens.into();
+        self
+    }
+
+    /// Add a string that stops generation
+    pub fn with_stop(mut self, stop: impl Into<String>) -> Self {
+        self.stop.push(stop.into());
+        self
+    }
+
+    /// Add tokens the model is never allowed to generate
+    pub fn with_banned_tokens(mut self, tokens: impl IntoIterator<Item = u32>) -> Self {
+        self.banned_tokens.extend(tokens);
+        self
+    }
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            temp: None,
+            top_p: None,
+            repeat_penalty: 1.0,
+            repeat_last_n: 0,
+            max_tokens: None,
+            stop: Vec::new(),
+            banned_tokens: Vec::new(),
+        }
+    }
+}
+
+/// Why a completion stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished or wrote a stop string
+    Stop,
+    /// The completion reached `max_tokens` or the end of the context window
+    Length,
+}
+
+/// Text generated by `Model::generate_streamed`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Completion {
+    /// The generated text, cut at the first stop string
+    pub text: String,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub finish_reason: FinishReason,
+}
+
+/// Get how many tokens can be generated after a prompt of `prompt_tokens` tokens before the context window is full.
+/// Returns an error if the prompt leaves no room at all.
+pub fn context_room(prompt_tokens: usize) -> Result<usize> {
+    if prompt_tokens >= MAX_TOKENS {
+        bail!(
+            "the prompt is {} tokens, but the context window only fits {}",
+            prompt_tokens,
+            MAX_TOKENS
+        )
+    }
+    Ok(MAX_TOKENS - prompt_tokens)
+}
+
+impl Model {
+    /// Get an iterator that yields tokens generated by the model using the parameters in `config`.
+    /// `max_tokens` and `stop` are not applied by the iterator itself.
+    pub fn infer_with(
+        &self,
+        prompt: impl IntoTokenString,
+        config: &GenerationConfig,
+    ) -> Result<InferIter> {
+        let mut iter = self.infer_iter(
+            prompt,
+            config.seed,
+            config.temp,
+            config.top_p,
+            config.repeat_penalty,
+            config.repeat_last_n,
+        )?;
+        iter.ban_tokens(config.banned_tokens.iter().copied());
+        Ok(iter)
+    }
+
+    /// Continue the prompt and return the generated text, respecting `max_tokens` and `stop`.
+    /// Generation also stops at the end of the context window.
+    /// Returns an error if the prompt is empty or fills the context window.
+    pub fn generate(
+        &self,
+        prompt: impl IntoTokenString,
+        config: &GenerationConfig,
+    ) -> Result<String> {
+        // Never generate past the end of the context window
+        let prompt = self.tokenize(prompt);
+        let room = context_room(prompt.len())?;
+        let max_tokens = config
+            .max_tokens
+            .map_or(room, |max_tokens| max_tokens.min(room));
+        Ok(self
+            .infer_with(prompt, config)?
+            .complete_until_any(Some(max_tokens), &config.stop))
+    }
+
+    /// Like `generate`, but call `on_text` with each piece of the text as soon as it can't be part of a stop string,
+    /// such as to stream it to a client. Generation stops early if `on_text` returns false.
+    /// `max_tokens` is lowered to the room the prompt leaves in the context window, finishing with `Length` there.
+    /// Returns an error if the prompt is empty or fills the context window.
+    pub fn generate_streamed(
+        &self,
+        prompt: impl IntoTokenString,
+        config: &GenerationConfig,
+        mut on_text: impl FnMut(&str) -> bool,
+    ) -> Result<Completion> {
+        let prompt = self.tokenize(prompt);
+        let prompt_tokens = prompt.len();
+        let room = context_room(prompt_tokens)?;
+        let max_tokens = config
+            .max_tokens
+            .map_or(room, |max_tokens| max_tokens.min(room));
+
+        // Decode only the newest tokens each time, keeping multi-token characters whole
+        let mut decoder = TokenDecoder::default();
+        let mut stop_at = None;
+        let mut sent = 0;
+        let mut finish_reason = FinishReason::Stop;
+        let mut inference = self.infer_with(prompt, config)?;
+        while let Some(token) = inference.next_token() {
+            let searched = decoder.text().len();
+            decoder.push(self, token);
+
+            // Cut the text at the earliest stop string
+            stop_at = find_stop(decoder.text(), searched, &config.stop);
+            if stop_at.is_some() {
+                break;
+            }
+
+            // Send the text that is certain to be kept
+            let text = decoder.text();
+            let ready = text.len() - held_back(text, &config.stop);
+            if ready > sent {
+                if !on_text(&text[sent..ready]) {
+                    break;
+                }
+                sent = ready;
+            }
+
+            if decoder.tokens().len() >= max_tokens {
+                finish_reason = FinishReason::Length;
+                break;
+            }
+        }
+        let text = match stop_at {
+            Some(end) => decoder.text()[..end].to_string(),
+            None => decoder.finish(self),
+        };
+        if text.len() > sent {
+            on_text(&text[sent..]);
+        }
+
+        Ok(Completion {
+            text,
+            prompt_tokens,
+            completion_tokens: decoder.tokens().len(),
+            finish_reason,
+        })
+    }
+
+    /// Continue the prompt until the output matches the regex, generating again with the next seed
+    /// up to `max_attempts` times. Returns the first output that matches, or None if none did.
+    pub fn generate_matching(
+        &self,
+        prompt: impl IntoTokenString,
+        regex: &Regex,
+        config: &GenerationConfig,
+        max_attempts: usize,
+    ) -> Option<String> {
+        let prompt = self.tokenize(prompt);
+        (0..max_attempts as u64)
+            .filter_map(|attempt| {
+                let config = config.clone().with_seed(config.seed.wrapping_add(attempt));
+                self.generate(prompt.clone(), &config).ok()
+            })
+            .find(|output| regex.is_match(output))
+    }
+
+    /// Continue the prompt like `generate_matching`, but return only the first full match of the regex.
+    /// Generation stops as soon as a match is found that further tokens can't extend.
+    pub fn generate_first_match(
+        &self,
+        prompt: impl IntoTokenString,
+        regex: &Regex,
+        config: &GenerationConfig,
+        max_attempts: usize,
+    ) -> Option<String> {
+        let prompt = self.tokenize(prompt);
+        for attempt in 0..max_attempts as u64 {
+            let config = config.clone().with_seed(config.seed.wrapping_add(attempt));
+            let Ok(inference) = self.infer_with(prompt.clone(), &config) else {
+                return None;
+            };
+
+            let mut generated = Vec::new();
+            let mut output = String::new();
+            for token in inference {
+                generated.push(token);
+                output = self.detokenize(&generated);
+
+                // A match that ends before the end of the output is finished
+                if let Some(found) = regex.find(&output) {
+                    if found.end() < output.len() {
+                        return Some(found.as_str().to_string());
+                    }
+                }
+
+                // Stop at the earliest stop string or the token limit
+                if let Some(end) = config
+                    .stop
+                    .iter()
+                    .filter(|stop| !stop.is_empty())
+                    .filter_map(|stop| output.find(stop.as_str()))
+                    .min()
+                {
+                    output.truncate(end);
+                    break;
+                }
+                if config
+                    .max_tokens
+                    .is_some_and(|max_tokens| generated.len() >= max_tokens)
+                {
+                    break;
+                }
+            }
+
+            // The match may run to the end of the output
+            if let Some(found) = regex.find(&output) {
+                return Some(found.as_str().to_string());
+            }
+        }
+
+        None
+    }
+
+    /// Instruct the model to generate a response based on the instruction
+    /// and return the generated text, respecting `max_tokens` and `stop`.
+    pub fn instruct_with(
+        &self,
+        instruction: impl AsRef<str>,
+        extra_information: Option<&HashMap<&str, impl AsRef<str>>>,
+        config: &GenerationConfig,
+    ) -> Result<String> {
+        // Create the prompt
+        let prompt = self.create_instruct_prompt(instruction, extra_information);
+
+        // Generate the response
+        self.generate(prompt, config)
+    }
+}
+
+/// Everything needed to reproduce a single generation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GenerationRecord {
+    /// The fingerprint of the model that produced the output
+    pub model: String,
+    /// The seed of the model, which is added to the seed in `config`
+    pub model_seed: u64,
+    pub config: GenerationConfig,
+    pub prompt_tokens: Vec<u32>,
+    /// Every token the model generated, including any after a stop string
+    pub output_tokens: Vec<u32>,
+    /// The generated text, cut at the first stop string
+    pub output: String,
+}
+
+/// The result of replaying a `GenerationRecord`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    /// The record produced by the replay
+    pub record: GenerationRecord,
+    /// Whether the replay generated exactly the same tokens as the original
+    pub reproduced: bool,
+}
+
+impl Model {
+    /// Like `generate`, but also capture everything needed to reproduce the output
+    pub fn generate_recorded(
+        &self,
+        prompt: impl IntoTokenString,
+        config: &GenerationConfig,
+    ) -> Result<GenerationRecord> {
+        let prompt = self.tokenize(prompt);
+        let (output_tokens, output) = self
+            .infer_with(&prompt, config)?
+            .complete_tokens_until_any(config.max_tokens, &config.stop);
+
+        Ok(GenerationRecord {
+            model: self.fingerprint()?,
+            model_seed: self.seed(),
+            config: config.clone(),
+            prompt_tokens: prompt.into_vec(),
+            output_tokens,
+            output,
+        })
+    }
+
+    /// Run a recorded generation again and check whether it reproduces the same tokens.
+    /// Returns an error if the record was made with a different model.
+    pub fn replay(&self, record: &GenerationRecord) -> Result<Replay> {
+        // Make sure the weights and tokenizer match
+        let fingerprint = self.fingerprint()?;
+        if record.model != fingerprint {
+            anyhow::bail!(
+                "record was made with model {:?} but this model is {:?}",
+                record.model,
+                fingerprint
+            )
+        }
+
+        // Use the recorded model seed so the effective seed is identical
+        let mut model = self.clone();
+        model.set_seed(record.model_seed);
+
+        // Generate from the exact recorded prompt tokens
+        let prompt = TokenString::new(record.prompt_tokens.clone(), model.clone());
+        let replayed = model.generate_recorded(prompt, &record.config)?;
+
+        Ok(Replay {
+            reproduced: replayed.output_tokens == record.output_tokens,
+            record: replayed,
+        })
+    }
+}
+
+/// Get how many bytes at the end of the text can't be sent yet, because they could be
+/// the start of a stop string or a character that is still being decoded
+pub fn held_back(text: &str, stop: &[String]) -> usize {
+    // Characters split between tokens decode as replacement characters until they are complete
+    let undecoded = text.len() - text.trim_end_matches(char::REPLACEMENT_CHARACTER).len();
+
+    // Find the longest end of the text that a stop string starts with
+    let longest_stop = stop.iter().map(String::len).max().unwrap_or(0);
+    let partial_stop = text
+        .char_indices()
+        .map(|(start, _)| &text[start..])
+        .filter(|end| end.len() < longest_stop)
+        .find(|end| stop.iter().any(|stop| stop.starts_with(end)))
+        .map_or(0, str::len);
+
+    undecoded.max(partial_stop)
+}
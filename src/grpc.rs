@@ -0,0 +1,289 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use crate::crafter::{Crafter, Theme};
+use crate::generation::{context_room, FinishReason, GenerationConfig};
+use crate::model::Model;
+use crate::scene::{Scene, SceneTurnType};
+
+use proto::phi_server::{Phi, PhiServer};
+
+/// Types and service traits generated from `proto/phi.proto`
+pub mod proto {
+    tonic::include_proto!("phi");
+}
+
+/// Default number of attempts `Choose` makes when a request doesn't say
+pub const DEFAULT_CHOOSE_ATTEMPTS: usize = 3;
+/// Default maximum number of tokens in each turn of a `Scene` request
+pub const DEFAULT_TURN_TOKENS: usize = 48;
+/// Number of streamed messages buffered before generation waits for the client
+const STREAM_BUFFER: usize = 32;
+
+impl From<proto::GenerationOptions> for GenerationConfig {
+    fn from(options: proto::GenerationOptions) -> Self {
+        GenerationConfig {
+            seed: options.seed,
+            temp: options.temp,
+            top_p: options.top_p,
+            repeat_penalty: options.repeat_penalty.unwrap_or(1.0),
+            repeat_last_n: options.repeat_last_n as usize,
+            max_tokens: options.max_tokens.map(|max_tokens| max_tokens as usize),
+            stop: options.stop,
+            banned_tokens: options.banned_tokens,
+        }
+    }
+}
+
+impl From<FinishReason> for proto::FinishReason {
+    fn from(finish_reason: FinishReason) -> Self {
+        match finish_reason {
+            FinishReason::Stop => proto::FinishReason::Stop,
+            FinishReason::Length => proto::FinishReason::Length,
+        }
+    }
+}
+
+impl From<crate::scene::SceneTurn> for proto::SceneTurn {
+    fn from(turn: crate::scene::SceneTurn) -> Self {
+        let kind = match &turn.turn_type {
+            SceneTurnType::Story(_) => proto::TurnKind::Story,
+            SceneTurnType::Dialogue(_, _) => proto::TurnKind::Dialogue,
+            SceneTurnType::Action(_, _) => proto::TurnKind::Action,
+            SceneTurnType::Player(_) => proto::TurnKind::Player,
+            SceneTurnType::Recap(_) => proto::TurnKind::Recap,
+        };
+        proto::SceneTurn {
+            kind: kind.into(),
+            character: turn.character().map(str::to_string),
+            text: turn.text().to_string(),
+            emotion: turn.emotion,
+        }
+    }
+}
+
+/// The `phi.Phi` gRPC service, answering every request with the same model
+#[derive(Clone)]
+pub struct PhiService {
+    model: Model,
+    /// Limits how many requests use the model at once
+    permits: Arc<Semaphore>,
+}
+
+impl PhiService {
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            permits: Arc::new(Semaphore::new(1)),
+        }
+    }
+
+    /// Set how many requests can use the model at once; further requests wait for a free slot
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.permits = Arc::new(Semaphore::new(max_concurrent.max(1)));
+        self
+    }
+
+    /// Wrap the service in a server that can be added to a `tonic::transport::Server`
+    pub fn into_server(self) -> PhiServer<Self> {
+        PhiServer::new(self)
+    }
+
+    /// Check the prompt fits in the context window, before waiting for a slot.
+    /// Completions that reach the end of the context window finish with `LENGTH`.
+    fn check_prompt(&self, prompt: &str) -> Result<()> {
+        context_room(self.model.tokenize_str(prompt).len()).map(|_| ())
+    }
+
+    async fn permit(&self) -> OwnedSemaphorePermit {
+        self.permits.clone().acquire_owned().await.unwrap()
+    }
+
+    /// Run `f` with the model on a blocking thread, once a slot is free
+    async fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(Model) -> Result<T> + Send + 'static,
+    ) -> Result<T, Status> {
+        let permit = self.permit().await;
+        let model = self.model.clone();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            f(model)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| Status::invalid_argument(e.to_string()))
+    }
+
+    /// Run `f` with the model on a blocking thread once a slot is free, streaming the messages it sends.
+    /// An error returned by `f` ends the stream.
+    async fn run_streaming<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(Model, &mpsc::Sender<Result<T, Status>>) -> Result<()> + Send + 'static,
+    ) -> ReceiverStream<Result<T, Status>> {
+        let permit = self.permit().await;
+        let model = self.model.clone();
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            if let Err(e) = f(model, &sender) {
+                let _ = sender.blocking_send(Err(Status::invalid_argument(e.to_string())));
+            }
+        });
+        ReceiverStream::new(receiver)
+    }
+}
+
+#[tonic::async_trait]
+impl Phi for PhiService {
+    async fn generate(
+        &self,
+        request: Request<proto::GenerateRequest>,
+    ) -> Result<Response<proto::GenerateResponse>, Status> {
+        let request = request.into_inner();
+        self.check_prompt(&request.prompt)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let config = GenerationConfig::from(request.options.unwrap_or_default());
+        let completion = self
+            .run(move |model| model.generate_streamed(request.prompt, &config, |_| true))
+            .await?;
+
+        Ok(Response::new(proto::GenerateResponse {
+            text: completion.text,
+            prompt_tokens: completion.prompt_tokens as u32,
+            completion_tokens: completion.completion_tokens as u32,
+            finish_reason: proto::FinishReason::from(completion.finish_reason).into(),
+        }))
+    }
+
+    type GenerateStreamStream = ReceiverStream<Result<proto::GenerateChunk, Status>>;
+
+    async fn generate_stream(
+        &self,
+        request: Request<proto::GenerateRequest>,
+    ) -> Result<Response<Self::GenerateStreamStream>, Status> {
+        let request = request.into_inner();
+        self.check_prompt(&request.prompt)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let config = GenerationConfig::from(request.options.unwrap_or_default());
+        let stream = self
+            .run_streaming(move |model, sender| {
+                // Stop generating if the client disconnects
+                let completion = model.generate_streamed(request.prompt, &config, |text| {
+                    let chunk = proto::GenerateChunk {
+                        text: text.to_string(),
+                        finish_reason: None,
+                    };
+                    sender.blocking_send(Ok(chunk)).is_ok()
+                })?;
+                let last = proto::GenerateChunk {
+                    text: String::new(),
+                    finish_reason: Some(proto::FinishReason::from(completion.finish_reason).into()),
+                };
+                let _ = sender.blocking_send(Ok(last));
+                Ok(())
+            })
+            .await;
+
+        Ok(Response::new(stream))
+    }
+
+    async fn craft(
+        &self,
+        request: Request<proto::CraftRequest>,
+    ) -> Result<Response<proto::CraftResponse>, Status> {
+        let request = request.into_inner();
+        if request.items.is_empty() {
+            return Err(Status::invalid_argument("cannot craft without any items"));
+        }
+        let theme = match request.theme.trim() {
+            "" => Theme::default(),
+            name => name
+                .parse::<Theme>()
+                .map_err(|e| Status::invalid_argument(e.to_string()))?,
+        };
+        let item = self
+            .run(move |model| {
+                Crafter::themed(model, theme).craft_item(&request.items, request.seed)
+            })
+            .await?;
+
+        Ok(Response::new(proto::CraftResponse { item }))
+    }
+
+    async fn choose(
+        &self,
+        request: Request<proto::ChooseRequest>,
+    ) -> Result<Response<proto::ChooseResponse>, Status> {
+        let request = request.into_inner();
+        let attempts = match request.attempts {
+            0 => DEFAULT_CHOOSE_ATTEMPTS,
+            attempts => attempts as usize,
+        };
+        let item = self
+            .run(move |model| {
+                Ok(model.try_choose_item(
+                    request.context,
+                    request.traits,
+                    &request.items,
+                    request.seed,
+                    attempts,
+                ))
+            })
+            .await?;
+
+        Ok(Response::new(proto::ChooseResponse { item }))
+    }
+
+    type SceneStream = ReceiverStream<Result<proto::SceneTurn, Status>>;
+
+    async fn scene(
+        &self,
+        request: Request<proto::SceneRequest>,
+    ) -> Result<Response<Self::SceneStream>, Status> {
+        let request = request.into_inner();
+        let turn_tokens = match request.turn_tokens {
+            0 => DEFAULT_TURN_TOKENS,
+            turn_tokens => turn_tokens as usize,
+        };
+        let stream = self
+            .run_streaming(move |model, sender| {
+                let mut scene = Scene::builder(model, request.setting)
+                    .characters(request.characters)
+                    .seed(request.seed)
+                    .build()?;
+                if let Some(story) = request.story {
+                    let turn = scene.push_story(story);
+                    if sender.blocking_send(Ok(turn.into())).is_err() {
+                        return Ok(());
+                    }
+                }
+
+                // Stop playing if the client disconnects
+                for _ in 0..request.turns {
+                    let turn = scene.infer_any(turn_tokens)?;
+                    if sender.blocking_send(Ok(turn.into())).is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            })
+            .await;
+
+        Ok(Response::new(stream))
+    }
+}
+
+/// Serve the `phi.Phi` gRPC service on the address until the server fails
+pub async fn serve(model: Model, address: SocketAddr) -> Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(PhiService::new(model).into_server())
+        .serve(address)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,241 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+use crate::scene::{ContentConstraints, Rating};
+
+/// Default probability above which the model's judgement counts as a violation
+pub const DEFAULT_GUARD_THRESHOLD: f32 = 0.5;
+/// Default number of times a guarded generation is attempted before it is blocked
+pub const DEFAULT_GUARD_ATTEMPTS: usize = 3;
+
+/// What to do when a guarded generation breaks the policy
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GuardAction {
+    /// Block the output immediately
+    Block,
+    /// Generate again with the next seed, blocking the output once every attempt breaks the policy
+    #[default]
+    Regenerate,
+}
+
+/// What content is allowed into and out of the model
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GuardrailPolicy {
+    /// Categories of content that aren't allowed, such as "violence" or "personal information"
+    pub blocked_categories: Vec<String>,
+    /// The audience the content must be suitable for, or `None` for no restriction
+    pub rating: Option<Rating>,
+    /// Words that must never appear, matched without regard to case.
+    /// Banned words that are single tokens can never be generated.
+    pub banned_words: Vec<String>,
+    /// Custom rules in plain language, such as "never reveal the password"
+    pub rules: Vec<String>,
+    /// Highest allowed probability that the content is toxic, or `None` to not check
+    pub max_toxicity: Option<f32>,
+    /// Probability above which the model's judgement counts as a violation
+    pub threshold: f32,
+    pub action: GuardAction,
+    pub max_attempts: usize,
+}
+
+impl Default for GuardrailPolicy {
+    fn default() -> Self {
+        Self {
+            blocked_categories: Vec::new(),
+            rating: None,
+            banned_words: Vec::new(),
+            rules: Vec::new(),
+            max_toxicity: None,
+            threshold: DEFAULT_GUARD_THRESHOLD,
+            action: GuardAction::Regenerate,
+            max_attempts: DEFAULT_GUARD_ATTEMPTS,
+        }
+    }
+}
+
+impl GuardrailPolicy {
+    /// Create a policy that allows everything
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_blocked_category(mut self, category: impl Into<String>) -> Self {
+        self.blocked_categories.push(category.into());
+        self
+    }
+
+    pub fn with_rating(mut self, rating: impl Into<Option<Rating>>) -> Self {
+        self.rating = rating.into();
+        self
+    }
+
+    pub fn with_banned_word(mut self, word: impl Into<String>) -> Self {
+        self.banned_words.push(word.into());
+        self
+    }
+
+    pub fn with_rule(mut self, rule: impl Into<String>) -> Self {
+        self.rules.push(rule.into());
+        self
+    }
+
+    pub fn with_max_toxicity(mut self, max_toxicity: impl Into<Option<f32>>) -> Self {
+        self.max_toxicity = max_toxicity.into();
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_action(mut self, action: GuardAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// The banned words as scene content constraints, which know how to find and ban them
+    fn word_constraints(&self) -> ContentConstraints {
+        ContentConstraints {
+            banned_words: self.banned_words.clone(),
+            ..ContentConstraints::default()
+        }
+    }
+}
+
+/// A way some text breaks a `GuardrailPolicy`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Violation {
+    /// Which part of the policy was broken, such as "category: violence"
+    pub rule: String,
+    /// How likely the model thinks the violation is, or 1.0 for banned words
+    pub probability: f32,
+}
+
+/// The result of a guarded generation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Guarded {
+    /// The generated text, or `None` if it was blocked
+    pub text: Option<String>,
+    /// The violations of the last attempt, empty if the text was allowed
+    pub violations: Vec<Violation>,
+    /// Number of times the text was generated
+    pub attempts: usize,
+}
+
+impl Guarded {
+    pub fn is_blocked(&self) -> bool {
+        self.text.is_none()
+    }
+}
+
+impl Model {
+    /// Check text, such as a user's input or a model's output, against the policy.
+    /// Returns every violation found, most likely first, or an empty list if the text is allowed.
+    pub fn check_content(
+        &self,
+        text: impl AsRef<str>,
+        policy: &GuardrailPolicy,
+    ) -> Result<Vec<Violation>> {
+        let text = text.as_ref().trim();
+        let mut violations = Vec::new();
+        if text.is_empty() {
+            return Ok(violations);
+        }
+
+        // Banned words are found without the model
+        for word in policy.word_constraints().banned_words_in(text) {
+            violations.push(Violation {
+                rule: format!("banned word: {}", word),
+                probability: 1.0,
+            });
+        }
+
+        // Ask the model about everything else
+        let mut questions = Vec::new();
+        for category in &policy.blocked_categories {
+            questions.push((
+                format!("category: {}", category),
+                format!("Does the text contain or mention {}?", category),
+            ));
+        }
+        for rule in &policy.rules {
+            questions.push((
+                format!("rule: {}", rule),
+                format!("Does the text break this rule: {}?", rule),
+            ));
+        }
+        if let Some(rating) = policy.rating {
+            questions.push((
+                format!("rating: {}", rating),
+                format!("Does the text contain anything that isn't {}?", rating),
+            ));
+        }
+        for (rule, question) in questions {
+            let probability = self.yes_probability(text, question)?;
+            if probability > policy.threshold {
+                violations.push(Violation { rule, probability });
+            }
+        }
+        if let Some(max_toxicity) = policy.max_toxicity {
+            let toxicity = self.toxicity(text);
+            if toxicity > max_toxicity {
+                violations.push(Violation {
+                    rule: "toxicity".to_string(),
+                    probability: toxicity,
+                });
+            }
+        }
+
+        violations.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+        Ok(violations)
+    }
+
+    /// Run any generation under the policy. `generate` is called with the config, with banned words
+    /// that are single tokens added to its banned tokens, and its output is checked against the policy.
+    /// Outputs that break it are blocked, or generated again with the next seed if the policy's
+    /// action is `GuardAction::Regenerate`.
+    pub fn guarded(
+        &self,
+        policy: &GuardrailPolicy,
+        config: &GenerationConfig,
+        mut generate: impl FnMut(&GenerationConfig) -> Result<String>,
+    ) -> Result<Guarded> {
+        // Make banned words impossible where possible
+        let mut config = config.clone();
+        config
+            .banned_tokens
+            .extend(policy.word_constraints().banned_tokens(self));
+
+        let max_attempts = match policy.action {
+            GuardAction::Block => 1,
+            GuardAction::Regenerate => policy.max_attempts.max(1),
+        };
+        let mut violations = Vec::new();
+        for attempt in 0..max_attempts {
+            let text = generate(&config)?;
+            violations = self.check_content(&text, policy)?;
+            if violations.is_empty() {
+                return Ok(Guarded {
+                    text: Some(text),
+                    violations,
+                    attempts: attempt + 1,
+                });
+            }
+            config.seed = config.seed.wrapping_add(1);
+        }
+
+        Ok(Guarded {
+            text: None,
+            violations,
+            attempts: max_attempts,
+        })
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Default maximum number of tokens in the flavor text of an item card
+pub const DEFAULT_MAX_FLAVOR_TOKENS: usize = 64;
+
+/// A numeric stat on an item card and the range its values are constrained to
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatRange {
+    pub name: String,
+    pub range: RangeInclusive<i64>,
+}
+
+/// Options for `Model::describe_item`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemCardConfig {
+    /// The stats every item gets, in order
+    pub stats: Vec<StatRange>,
+    pub max_flavor_tokens: usize,
+    /// Seed of both the flavor text and the stats
+    pub seed: u64,
+    /// Temperature of the flavor text. Stats are always chosen greedily.
+    pub temp: f64,
+}
+
+impl Default for ItemCardConfig {
+    fn default() -> Self {
+        Self {
+            stats: Vec::new(),
+            max_flavor_tokens: DEFAULT_MAX_FLAVOR_TOKENS,
+            seed: 0,
+            temp: 0.7,
+        }
+    }
+}
+
+impl ItemCardConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_stat(mut self, name: impl Into<String>, range: RangeInclusive<i64>) -> Self {
+        self.stats.push(StatRange {
+            name: name.into(),
+            range,
+        });
+        self
+    }
+
+    pub fn with_max_flavor_tokens(mut self, max_flavor_tokens: usize) -> Self {
+        self.max_flavor_tokens = max_flavor_tokens;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_temp(mut self, temp: f64) -> Self {
+        self.temp = temp;
+        self
+    }
+}
+
+/// Flavor text and stats describing an item
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemCard {
+    pub name: String,
+    pub flavor_text: String,
+    /// The value of every stat in the config, in the same order
+    pub stats: Vec<(String, i64)>,
+}
+
+impl ItemCard {
+    /// Get the value of a stat by name, ignoring case
+    pub fn stat(&self, name: impl AsRef<str>) -> Option<i64> {
+        let name = name.as_ref();
+        self.stats
+            .iter()
+            .find(|(stat, _)| stat.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+}
+
+impl Model {
+    /// Describe an item, such as the result of `Crafter::craft`, with a short flavor text
+    /// and a value for every stat in the config, each within its configured range.
+    /// Returns an error if a stat range is empty.
+    pub fn describe_item(
+        &self,
+        name: impl AsRef<str>,
+        tags: impl IntoIterator<Item = impl AsRef<str>>,
+        config: &ItemCardConfig,
+    ) -> Result<ItemCard> {
+        let name = name.as_ref().trim();
+        let tags = tags
+            .into_iter()
+            .map(|tag| tag.as_ref().trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .join(", ");
+        if let Some(stat) = config.stats.iter().find(|stat| stat.range.is_empty()) {
+            bail!("the range of stat \"{}\" is empty", stat.name)
+        }
+
+        // Write the flavor text
+        let mut extra = HashMap::new();
+        extra.insert("Item", name);
+        if !tags.is_empty() {
+            extra.insert("Tags", tags.as_str());
+        }
+        let flavor_config = GenerationConfig::new(config.seed)
+            .with_temp(config.temp)
+            .with_max_tokens(config.max_flavor_tokens)
+            .with_stop("\n")
+            .with_stop("###");
+        let flavor_text = self
+            .instruct_with(
+                "Write one or two sentences of flavor text for the item in a fantasy game.",
+                Some(&extra),
+                &flavor_config,
+            )?
+            .trim()
+            .to_string();
+
+        // Infer each stat from the item and its flavor text
+        let mut context = format!("Item: {}", name);
+        if !tags.is_empty() {
+            context.push_str(&format!("\nTags: {}", tags));
+        }
+        if !flavor_text.is_empty() {
+            context.push_str(&format!("\nDescription: {}", flavor_text));
+        }
+        let stat_config = GenerationConfig::new(config.seed);
+        let mut stats = Vec::with_capacity(config.stats.len());
+        for stat in &config.stats {
+            let question = format!("What is the item's {} stat?", stat.name);
+            let value = self
+                .choose_number(&context, question, stat.range.clone(), &stat_config)
+                .unwrap_or(*stat.range.start());
+            stats.push((stat.name.clone(), value));
+        }
+
+        Ok(ItemCard {
+            name: name.to_string(),
+            flavor_text,
+            stats,
+        })
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::generation::GenerationConfig;
+use crate::model::{Model, MAX_TOKENS};
+
+/// Maximum number of tokens in a single item of a generated list
+pub const MAX_LIST_ITEM_TOKENS: usize = 64;
+/// Number of times `generate_list` tops up a list that has too few unique items
+pub const LIST_TOP_UP_ATTEMPTS: u64 = 3;
+
+impl Model {
+    /// Generate a numbered list of `n` unique items, such as "tavern rumors", returning the items
+    /// without their numbers. Duplicates are left out, ignoring case, and the list is continued with
+    /// the next seed if it ends with too few items. Returns fewer items if the model keeps repeating itself
+    /// or the list fills the context window.
+    pub fn generate_list(
+        &self,
+        instruction: impl AsRef<str>,
+        n: usize,
+        config: &GenerationConfig,
+    ) -> Vec<String> {
+        let mut items: Vec<String> = Vec::new();
+        if n == 0 {
+            return items;
+        }
+
+        // Ask for exactly one item per numbered line
+        let instruction = format!(
+            "{}\nWrite a numbered list of {} items, one per line, without any other text.",
+            instruction.as_ref().trim(),
+            n
+        );
+        for attempt in 0..=LIST_TOP_UP_ATTEMPTS {
+            if items.len() >= n {
+                break;
+            }
+
+            // Continue the list after the unique items so far
+            let response = items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| format!("{}. {}\n", index + 1, item))
+                .join("");
+            let mut extra = HashMap::new();
+            extra.insert("Response", format!("{}{}.", response, items.len() + 1));
+
+            // Stop once the prompt leaves no room in the context window for another item
+            let prompt_tokens = self
+                .create_instruct_prompt(&instruction, Some(&extra))
+                .len();
+            let room = MAX_TOKENS.saturating_sub(prompt_tokens);
+            if room < MAX_LIST_ITEM_TOKENS {
+                break;
+            }
+            let list_config = GenerationConfig {
+                seed: config.seed.wrapping_add(attempt),
+                max_tokens: Some(((n - items.len()) * MAX_LIST_ITEM_TOKENS).min(room)),
+                stop: vec!["###".to_string(), "\n\n".to_string()],
+                ..config.clone()
+            };
+            let Ok(list) = self.instruct_with(&instruction, Some(&extra), &list_config) else {
+                break;
+            };
+
+            // The first line was started with its number already
+            let mut lines = list.lines();
+            let first = lines.next().unwrap_or_default().trim().to_string();
+            for item in [first].into_iter().chain(lines.filter_map(parse_list_item)) {
+                if items.len() < n
+                    && !item.is_empty()
+                    && !items.iter().any(|other| other.eq_ignore_ascii_case(&item))
+                {
+                    items.push(item);
+                }
+            }
+        }
+
+        items
+    }
+}
+
+/// Get the text of a numbered or bulleted list item, or None if the line isn't one
+pub(crate) fn parse_list_item(line: &str) -> Option<String> {
+    let line = line.trim();
+    let text = if let Some(text) = line.strip_prefix(['-', '*', '•']) {
+        text
+    } else {
+        let digits = line.chars().take_while(char::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        line[digits..].strip_prefix(['.', ')', ':'])?
+    };
+
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
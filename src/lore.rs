@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::VectorMemory;
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Maximum number of tokens in a generated lore entry
+pub const MAX_LORE_TOKENS: usize = 128;
+/// Number of times `Lore::generate` tries to write an entry that agrees with canon
+pub const LORE_ATTEMPTS: u64 = 3;
+/// Default number of most similar canon facts each new entry is checked against
+pub const DEFAULT_CHECKED_FACTS: usize = 5;
+/// Default probability of contradiction above which an entry is rejected
+pub const DEFAULT_MAX_CONTRADICTION: f32 = 0.5;
+
+/// A piece of lore about a topic
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoreEntry {
+    pub topic: String,
+    pub text: String,
+}
+
+/// A canon fact that a text likely contradicts
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Contradiction {
+    pub fact: LoreEntry,
+    /// How likely the model thinks the contradiction is, from 0.0 to 1.0
+    pub probability: f32,
+}
+
+/// Every lore entry accepted so far, searchable by similarity
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Canon {
+    entries: Vec<LoreEntry>,
+    /// The text of every entry, in the same order as `entries`
+    memory: VectorMemory,
+}
+
+impl Canon {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry to canon without checking it
+    pub fn insert(&mut self, model: &Model, entry: LoreEntry) -> Result<()> {
+        self.memory
+            .insert(model, format!("{}: {}", entry.topic, entry.text))?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Find the `k` entries most similar to the text, most similar first
+    pub fn search(
+        &self,
+        model: &Model,
+        text: impl AsRef<str>,
+        k: usize,
+    ) -> Result<Vec<&LoreEntry>> {
+        if self.is_empty() || k == 0 {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .memory
+            .search(model, text, k)?
+            .into_iter()
+            .filter_map(|(found, _)| {
+                self.memory
+                    .entries()
+                    .iter()
+                    .position(|entry| std::ptr::eq(found, entry))
+            })
+            .map(|index| &self.entries[index])
+            .collect())
+    }
+
+    pub fn entries(&self) -> &[LoreEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Write the canon to a JSON file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Read a canon from a JSON file written by `Canon::save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// Generates lore that doesn't contradict the canon established so far
+pub struct Lore {
+    model: Model,
+    canon: Canon,
+    /// Number of most similar canon facts each new entry is checked against
+    pub checked_facts: usize,
+    /// Probability of contradiction above which an entry is rejected
+    pub max_contradiction: f32,
+}
+
+impl Lore {
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            canon: Canon::new(),
+            checked_facts: DEFAULT_CHECKED_FACTS,
+            max_contradiction: DEFAULT_MAX_CONTRADICTION,
+        }
+    }
+
+    pub fn with_canon(mut self, canon: Canon) -> Self {
+        self.canon = canon;
+        self
+    }
+
+    pub fn with_checked_facts(mut self, checked_facts: usize) -> Self {
+        self.checked_facts = checked_facts;
+        self
+    }
+
+    pub fn with_max_contradiction(mut self, max_contradiction: f32) -> Self {
+        self.max_contradiction = max_contradiction;
+        self
+    }
+
+    pub fn canon(&self) -> &Canon {
+        &self.canon
+    }
+
+    /// Add an established fact to canon without checking it
+    pub fn add_canon(&mut self, topic: impl Into<String>, text: impl Into<String>) -> Result<()> {
+        let entry = LoreEntry {
+            topic: topic.into().trim().to_string(),
+            text: text.into().trim().to_string(),
+        };
+        self.canon.insert(&self.model, entry)
+    }
+
+    /// Get the canon facts most similar to the text that it likely contradicts, most likely first
+    pub fn contradictions(&self, text: impl AsRef<str>) -> Result<Vec<Contradiction>> {
+        let text = text.as_ref().trim();
+        let mut contradictions = Vec::new();
+        for fact in self.canon.search(&self.model, text, self.checked_facts)? {
+            let comparison = format!("Established fact: {}\nNew lore: {}", fact.text, text);
+            let probability = self.model.yes_probability(
+                comparison,
+                "Does the new lore contradict the established fact?",
+            )?;
+            if probability > self.max_contradiction {
+                contradictions.push(Contradiction {
+                    fact: fact.clone(),
+                    probability,
+                });
+            }
+        }
+        contradictions.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+        Ok(contradictions)
+    }
+
+    /// Generate a lore entry about the topic that agrees with canon and add it to canon.
+    /// The related canon is given to the model, and entries that contradict it are
+    /// written again with the next seed.
+    /// Returns an error if the topic is empty or every attempt contradicted canon.
+    pub fn generate(&mut self, topic: impl AsRef<str>, seed: u64) -> Result<LoreEntry> {
+        let topic = topic.as_ref().trim();
+        if topic.is_empty() {
+            bail!("cannot generate lore about an empty topic")
+        }
+
+        // Give the model the related canon
+        let related = self
+            .canon
+            .search(&self.model, topic, self.checked_facts)?
+            .into_iter()
+            .map(|entry| format!("- {}: {}", entry.topic, entry.text))
+            .join("\n");
+        let instruction = format!(
+            "Write a short entry for a fantasy world's encyclopedia about {}. \
+            It must agree with the established lore.",
+            topic
+        );
+        let mut extra = HashMap::new();
+        if !related.is_empty() {
+            extra.insert("Established Lore", related.as_str());
+        }
+
+        let mut last_contradiction = None;
+        for seed in seed..seed.saturating_add(LORE_ATTEMPTS) {
+            // Write an entry until the next section or the token limit
+            let config = GenerationConfig::new(seed)
+                .with_temp(0.8)
+                .with_max_tokens(MAX_LORE_TOKENS)
+                .with_stop("###");
+            let text = self
+                .model
+                .instruct_with(&instruction, Some(&extra), &config)?;
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            // Only accept it if it agrees with canon
+            let contradictions = self.contradictions(text)?;
+            if let Some(contradiction) = contradictions.into_iter().next() {
+                last_contradiction = Some(contradiction);
+                continue;
+            }
+            let entry = LoreEntry {
+                topic: topic.to_string(),
+                text: text.to_string(),
+            };
+            self.canon.insert(&self.model, entry.clone())?;
+            return Ok(entry);
+        }
+
+        match last_contradiction {
+            Some(contradiction) => bail!(
+                "every lore entry about {} contradicted canon, such as \"{}\"",
+                topic,
+                contradiction.fact.text
+            ),
+            None => bail!("could not generate lore about {}", topic),
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
 
 use anyhow::{Error as E, Result};
 
@@ -9,23 +12,52 @@ use candle_transformers::models::mixformer::{Config, MixFormerSequentialForCausa
 use candle_core::{DType, Device, Tensor};
 use candle_nn::VarBuilder;
 use candle_transformers::generation::LogitsProcessor;
+#[cfg(not(target_arch = "wasm32"))]
 use hf_hub::api::sync::Api;
+use itertools::Itertools;
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
 use tokenizers::Tokenizer;
 
+use crate::generation::GenerationConfig;
 use crate::token_string::{IntoTokenString, TokenString};
 
 pub const MAX_TOKENS: usize = 2048;
+/// Hugging Face repository the model and tokenizer are downloaded from
+pub const MODEL_REPO: &str = "lmz/candle-quantized-phi";
+pub const MODEL_FILE: &str = "model-phi-hermes-1_3B.safetensors";
+pub const TOKENIZER_FILE: &str = "tokenizer-puffin-phi-v2.json";
+/// Number of entries in the model's (padded) vocabulary
+pub const VOCAB_SIZE: usize = 50304;
+/// Size of the model's token embeddings
+pub const EMBEDDING_SIZE: usize = 2048;
 
 #[derive(Clone)]
 pub struct Model {
     config: Config,
     vb: VarBuilder<'static>,
-    tokenizer: Tokenizer,
+    tokenizer: Arc<Tokenizer>,
     device: Device,
     seed: u64,
+    /// The token embedding table, loaded the first time it is needed
+    token_embeddings: Arc<OnceLock<Tensor>>,
+    /// Where the weights and tokenizer came from, for computing the fingerprint
+    source: Arc<ModelSource>,
+    /// Identifies the weights and tokenizer, computed the first time it is needed
+    fingerprint: Arc<OnceLock<String>>,
+}
+
+/// Where a model's weights and tokenizer were loaded from
+enum ModelSource {
+    /// Files that are hashed when the fingerprint is first needed
+    Files { model: PathBuf, tokenizer: PathBuf },
+    /// Bytes that were hashed as they were loaded, since they aren't kept
+    Bytes,
 }
 
 impl Model {
+    /// Download the model and tokenizer from Hugging Face, or load them from the local cache
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn new(seed: u64, use_cuda: bool) -> Result<Self> {
         let device = if use_cuda && candle_core::utils::cuda_is_available() {
             Device::new_cuda(0).unwrap()
@@ -33,27 +65,55 @@ impl Model {
             Device::Cpu
         };
         let api = Api::new()?;
-        let repo = api.model("lmz/candle-quantized-phi".to_string());
-        let tokenizer_filename = repo.get("tokenizer-puffin-phi-v2.json")?;
-        let model_filename = repo.get("model-phi-hermes-1_3B.safetensors")?;
-
-        // Create model config
-        let config = Config::phi_hermes_1_3b();
+        let repo = api.model(MODEL_REPO.to_string());
+        let tokenizer_filename = repo.get(TOKENIZER_FILE)?;
+        let model_filename = repo.get(MODEL_FILE)?;
 
         // Create VarBuilder
         let vb =
-            unsafe { VarBuilder::from_mmaped_safetensors(&[model_filename], DType::F32, &device)? };
+            unsafe { VarBuilder::from_mmaped_safetensors(&[&model_filename], DType::F32, &device)? };
 
         // Create tokenizer
-        let tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(E::msg)?;
+        let tokenizer = Tokenizer::from_file(&tokenizer_filename).map_err(E::msg)?;
+
+        let source = ModelSource::Files {
+            model: model_filename,
+            tokenizer: tokenizer_filename,
+        };
+        Ok(Self::from_parts(vb, tokenizer, device, seed, source))
+    }
+
+    /// Load the model on the CPU from the contents of `MODEL_FILE` and `TOKENIZER_FILE`, without downloading
+    /// or memory-mapping anything. This is how the model is loaded in the browser, where the caller fetches the files.
+    pub fn from_bytes(model_bytes: Vec<u8>, tokenizer_bytes: impl AsRef<[u8]>, seed: u64) -> Result<Self> {
+        // Hash the bytes now, since the weights are moved into the VarBuilder
+        let fingerprint = fingerprint_of(
+            hash_bytes(FNV_OFFSET, &model_bytes),
+            hash_bytes(FNV_OFFSET, tokenizer_bytes.as_ref()),
+        );
+
+        let device = Device::Cpu;
+        let vb = VarBuilder::from_buffered_safetensors(model_bytes, DType::F32, &device)?;
+        let tokenizer = Tokenizer::from_bytes(tokenizer_bytes).map_err(E::msg)?;
+
+        let model = Self::from_parts(vb, tokenizer, device, seed, ModelSource::Bytes);
+        model.fingerprint.get_or_init(|| fingerprint);
+        Ok(model)
+    }
 
-        Ok(Self {
-            config,
+    fn from_parts(vb: VarBuilder<'static>, tokenizer: Tokenizer, device: Device, seed: u64, source: ModelSource) -> Self {
+        Self {
+            // Create model config
+            config: Config::phi_hermes_1_3b(),
             vb,
-            tokenizer,
-            device: device.clone(),
+            // The tokenizer is shared so cloning the model (and every TokenString) stays cheap
+            tokenizer: Arc::new(tokenizer),
+            device,
             seed,
-        })
+            token_embeddings: Arc::new(OnceLock::new()),
+            source: Arc::new(source),
+            fingerprint: Arc::new(OnceLock::new()),
+        }
     }
 
     pub fn seed(&self) -> u64 {
@@ -64,6 +124,21 @@ impl Model {
         self.seed = seed;
     }
 
+    /// Get a string identifying the weights and tokenizer used by the model, from a hash of their contents.
+    /// Models loaded from the files and from the same bytes have the same fingerprint.
+    /// The files are hashed the first time this is called, which reads all of the weights.
+    /// Returns an error if the files can't be read.
+    pub fn fingerprint(&self) -> Result<String> {
+        if let Some(fingerprint) = self.fingerprint.get() {
+            return Ok(fingerprint.clone());
+        }
+        let fingerprint = match self.source.as_ref() {
+            ModelSource::Files { model, tokenizer } => fingerprint_of(hash_file(model)?, hash_file(tokenizer)?),
+            ModelSource::Bytes => unreachable!("models loaded from bytes are fingerprinted as they load"),
+        };
+        Ok(self.fingerprint.get_or_init(|| fingerprint).clone())
+    }
+
     pub fn max_tokens(&self) -> usize {
         MAX_TOKENS
     }
@@ -85,6 +160,27 @@ impl Model {
         text.into_token_string(self)
     }
 
+    pub(crate) fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Get the token embedding table of the model, loading it on first use
+    pub(crate) fn token_embeddings(&self) -> Result<&Tensor> {
+        if let Some(token_embeddings) = self.token_embeddings.get() {
+            return Ok(token_embeddings);
+        }
+
+        // Load the table from the embedding layer of the weights
+        let token_embeddings = self
+            .vb
+            .pp("layers")
+            .pp(0)
+            .pp("wte")
+            .get((VOCAB_SIZE, EMBEDDING_SIZE), "weight")?;
+
+        Ok(self.token_embeddings.get_or_init(|| token_embeddings))
+    }
+
     pub(crate) fn detokenize(&self, tokens: impl AsRef<[u32]>) -> String {
         // Decode the tokens into a string
         let text = self.tokenizer.decode(tokens.as_ref(), true).map_err(E::msg).unwrap();
@@ -143,7 +239,7 @@ impl Model {
     }
 
     /// Convenience function to create a prompt for instruct
-    fn create_instruct_prompt(
+    pub(crate) fn create_instruct_prompt(
         &self,
         instruction: impl AsRef<str>,
         extra_information: Option<&HashMap<&str, impl AsRef<str>>>,
@@ -207,8 +303,21 @@ impl Model {
         seed: u64,
         attempts: usize,
     ) -> Option<String> {
-        let mut prompt_extra = HashMap::new();
+        self.try_choose_item_indexed(context, desired_traits, items, seed, attempts)
+            .map(|choice| choice.normalized)
+    }
 
+    /// Like `try_choose_item`, but returns where the chosen item was in the input and how it was written there,
+    /// so it can be mapped back to the caller's own data.
+    /// If several items are the same once lowercased and trimmed, the first of them is returned.
+    pub fn try_choose_item_indexed(
+        &self,
+        context: impl AsRef<str>,
+        desired_traits: impl AsRef<str>,
+        items: impl IntoIterator<Item = impl AsRef<str>>,
+        seed: u64,
+        attempts: usize,
+    ) -> Option<Choice> {
         // Make sure that seed + attempts doesn't overflow by subtracting u64::MAX / 2
         let seed = if seed > u64::MAX - attempts as u64 {
             seed - u64::MAX / 2
@@ -216,11 +325,173 @@ impl Model {
             seed
         };
 
+        // Keep the original items, then trim and lowercase them
+        let originals: Vec<String> = items
+            .into_iter()
+            .map(|item| item.as_ref().to_string())
+            .collect();
+        let items: Vec<String> = originals
+            .iter()
+            .map(|item| item.trim().to_lowercase())
+            .collect();
+
+        // Create the prompt
+        let prompt = self.create_choice_prompt(context, desired_traits, &items);
+
+        // Keep trying until the model chooses an item, incrementing the seed each time
+        // After each attempt, temperature is increased to encourage diversity
+        let mut response = None;
+        let mut temperature = 0.2;
+        for seed in seed..seed + attempts as u64 {
+            // If the model settles on an item, return it
+            response = self.choose_by_trie(prompt.clone(), &items, "]", seed, temperature);
+            if response.is_some() {
+                break;
+            }
+
+            // Raise the temperature and try again
+            temperature += 0.2;
+        }
+        
+        // Find where the chosen item was in the input
+        let normalized = response?;
+        let index = items.iter().position(|item| *item == normalized).unwrap();
+        Some(Choice {
+            index,
+            original: originals[index].clone(),
+            normalized,
+        })
+    }
+
+    /// Given a list of items and a context string, try to choose the `k` most appropriate distinct items
+    /// based on the context, one at a time. Items already chosen are removed from the list
+    /// and mentioned in the context, so the same item is never picked twice.
+    /// Returns the chosen items (lowercased and trimmed) in the order they were chosen,
+    /// which may be fewer than `k` if the model fails to choose or runs out of items.
+    pub fn try_choose_items(
+        &self,
+        context: impl AsRef<str>,
+        desired_traits: impl AsRef<str>,
+        items: impl IntoIterator<Item = impl AsRef<str>>,
+        k: usize,
+        seed: u64,
+        attempts: usize,
+    ) -> Vec<String> {
         // Trim and lowercase all the items
+        let mut remaining: Vec<String> = items
+            .into_iter()
+            .map(|item| item.as_ref().trim().to_lowercase())
+            .unique()
+            .collect();
+
+        let mut chosen: Vec<String> = Vec::new();
+        while chosen.len() < k && !remaining.is_empty() {
+            // Tell the model what it already chose
+            let context = if chosen.is_empty() {
+                context.as_ref().to_string()
+            } else {
+                format!("{}\nAlready chosen: [{}]", context.as_ref(), chosen.join("]["))
+            };
+
+            // Choose from the remaining items, using a different seed for each pick
+            let seed = seed.wrapping_add((chosen.len() * attempts) as u64);
+            let Some(item) = self.try_choose_item(&context, desired_traits.as_ref(), &remaining, seed, attempts) else {
+                break;
+            };
+
+            // Move the item from the remaining items to the chosen ones
+            remaining.retain(|remaining| *remaining != item);
+            chosen.push(item);
+        }
+
+        chosen
+    }
+
+    /// Given a list of items and a context string, choose the item the model finds most likely
+    /// to follow the prompt used by `try_choose_item`, by scoring every item rather than generating text.
+    /// Unlike `try_choose_item`, items that begin with another item (like "sword" and "swordfish") are told apart.
+    /// Returns the chosen item (lowercased and trimmed), or None if there are no items.
+    pub fn choose_item_scored(
+        &self,
+        context: impl AsRef<str>,
+        desired_traits: impl AsRef<str>,
+        items: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Option<String> {
+        // Choose the item with the highest total log-probability
+        let (best, _) = self.item_log_likelihoods(context, desired_traits, items)
+            .into_iter()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+        Some(best)
+    }
+
+    /// Rank every item by how suitable the model finds it for the context and desired traits,
+    /// most suitable first. The scores are probabilities that sum to 1, sharpened or flattened
+    /// by the config's temperature (the model's own probabilities are used if it has none).
+    /// Items are lowercased and trimmed like `choose_item_scored`.
+    pub fn rank_items(
+        &self,
+        context: impl AsRef<str>,
+        desired_traits: impl AsRef<str>,
+        items: impl IntoIterator<Item = impl AsRef<str>>,
+        config: &GenerationConfig,
+    ) -> Vec<(String, f32)> {
+        let (items, log_likelihoods): (Vec<String>, Vec<f32>) = self.item_log_likelihoods(context, desired_traits, items)
+            .into_iter()
+            .unzip();
+
+        // Turn the log-likelihoods into probabilities
+        let temp = config.temp.filter(|temp| *temp > 0.0).unwrap_or(1.0) as f32;
+        let probabilities = softmax(log_likelihoods.iter().map(|log_likelihood| log_likelihood / temp));
+
+        // Most suitable first
+        let mut ranking: Vec<(String, f32)> = items.into_iter().zip(probabilities).collect();
+        ranking.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranking
+    }
+
+    /// Get the total log-probability of each item (lowercased, trimmed and without empty items)
+    /// followed by the closing bracket as the response to the choice prompt.
+    /// Returns an empty list if there are no items or scoring fails.
+    pub(crate) fn item_log_likelihoods(
+        &self,
+        context: impl AsRef<str>,
+        desired_traits: impl AsRef<str>,
+        items: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Vec<(String, f32)> {
+        // Trim and lowercase all the items, skipping empty ones
         let items: Vec<String> = items
             .into_iter()
             .map(|item| item.as_ref().trim().to_lowercase())
+            .filter(|item| !item.is_empty())
+            .unique()
             .collect();
+        if items.is_empty() {
+            return Vec::new();
+        }
+
+        // Score each item followed by the closing bracket, so the end of the item counts too
+        let prompt = self.create_choice_prompt(context, desired_traits, &items);
+        let Ok(log_probs) = self.continuation_log_probs(prompt, items.iter().map(|item| format!("{}]", item))) else {
+            return Vec::new();
+        };
+
+        items
+            .into_iter()
+            .zip(log_probs)
+            .map(|(item, log_probs)| (item, log_probs.iter().sum::<f32>()))
+            .collect()
+    }
+
+    /// Create the prompt asking the model to choose one of the items (already lowercased and trimmed),
+    /// with the response started off with a [ character
+    pub(crate) fn create_choice_prompt(
+        &self,
+        context: impl AsRef<str>,
+        desired_traits: impl AsRef<str>,
+        items: &[String],
+    ) -> TokenString {
+        let mut prompt_extra = HashMap::new();
 
         // Format the items like so: "[item1], [item2], [item3]"
         let items_string = format!("[{}]", items.join("]["));
@@ -232,68 +503,173 @@ impl Model {
 
         // Start the response with a [ character
         prompt_extra.insert("Response", "[");
-        
+
         // Create the prompt
-        let prompt = self.create_instruct_prompt(
+        self.create_instruct_prompt(
             "Choose the most appropriate item for the context and desired traits.",
             Some(&prompt_extra),
-        );
+        )
+    }
 
-        // Keep trying until the model chooses an item, incrementing the seed each time
-        // After each attempt, temperature is increased to encourage diversity
-        let mut response = None;
-        let mut temperature = 0.2;
-        for seed in seed..seed + attempts as u64 {
-            // Clone the items
-            let mut possible_items = items.clone();
-            
-            // Begin inference
-            let mut inference = self.infer_iter(prompt.clone(), seed, Some(temperature), None, 1.0, 0).unwrap();
-            
-            // Infer while possible_items > 1
-            let mut inferred = String::new();
-            while possible_items.len() > 1 {
-                // Attempt to get the next token and check if it matches any of the possible items
-                if let Some(next_token) = inference.next_token() {
-                    // Add the token to the inferred string
-                    inferred.push_str(&self.detokenize(&[next_token]));
-                    
-                    // Remove the item from the list if it doesn't begin with the inferred string
-                    let formatted = inferred.trim().to_lowercase();
-                    possible_items.retain(|item| item.starts_with(&formatted));
-                }
-                // If there are no more tokens, empty the possible items and break
-                else {
-                    possible_items.clear();
-                    break;
+    /// Continue the prompt, only letting the model write tokens that lead to one of the items followed by `end`,
+    /// until only one of the items can still be what the model is writing.
+    /// This always settles on an item unless there are none.
+    pub(crate) fn choose_by_trie(&self, prompt: TokenString, items: &[String], end: &str, seed: u64, temp: f64) -> Option<String> {
+        let mut inference = self.infer_iter(prompt, seed, Some(temp), None, 1.0, 0).unwrap();
+        self.write_by_trie(&mut inference, items, end, false).map(|index| items[index].clone())
+    }
+
+    /// Like `choose_by_trie`, but continue an inference that is already running and return the index of the item.
+    /// If `finish` is true, the rest of the item and `end` are written too, so the inference can carry on after them.
+    pub(crate) fn write_by_trie(&self, inference: &mut InferIter, items: &[String], end: &str, finish: bool) -> Option<usize> {
+        // Build a trie of the items' tokens
+        let tokenized: Vec<TokenString> = items.iter().map(|item| self.tokenize_str(format!("{}{}", item, end))).collect();
+        let mut trie = TokenTrie::default();
+        for (index, tokens) in tokenized.iter().enumerate() {
+            trie.insert(tokens.as_slice(), index);
+        }
+
+        // Follow the trie until only one item is left, or the end of identical items is reached
+        let mut node = &trie;
+        let mut written = 0;
+        while node.items.len() > 1 && !node.children.is_empty() {
+            // Only allow the tokens that continue an item
+            inference.allow_only(Some(node.children.keys().copied().collect()));
+            let next = inference.next_token().and_then(|token| node.children.get(&token));
+            match next {
+                Some(child) => node = child,
+                None => {
+                    inference.allow_only(None);
+                    return None;
                 }
             }
+            written += 1;
+        }
+        inference.allow_only(None);
 
-            // If there is only one item left, return it
-            if possible_items.len() == 1 {
-                response = Some(possible_items.pop().unwrap());
-                break;
+        // Write the rest of the item
+        let index = *node.items.first()?;
+        if finish {
+            inference.push_tokens(tokenized[index].get(written..).unwrap());
+        }
+        Some(index)
+    }
+
+    /// Get the average log-probability of each token of `continuation` directly following `prompt`.
+    /// Higher (closer to 0) means the model finds the continuation more likely.
+    /// Returns an error if the prompt or the continuation is empty.
+    pub fn score_continuation(&self, prompt: impl IntoTokenString, continuation: impl IntoTokenString) -> Result<f32> {
+        let log_probs = self.continuation_log_probs(prompt, [continuation])?.pop().unwrap();
+        Ok(log_probs.iter().sum::<f32>() / log_probs.len() as f32)
+    }
+
+    /// Get the log-probability of each token of each continuation directly following `prompt`.
+    /// The prompt is only processed once and shared between the continuations.
+    /// Returns an error if the prompt or any of the continuations is empty.
+    pub fn continuation_log_probs(
+        &self,
+        prompt: impl IntoTokenString,
+        continuations: impl IntoIterator<Item = impl IntoTokenString>,
+    ) -> Result<Vec<Vec<f32>>> {
+        // Tokenize the prompt and the continuations
+        let prompt = self.tokenize(prompt);
+        let continuations: Vec<TokenString> = continuations
+            .into_iter()
+            .map(|continuation| self.tokenize(continuation))
+            .collect();
+
+        // Fail if there is nothing to score
+        if prompt.is_empty() {
+            anyhow::bail!("prompt was empty")
+        }
+        if continuations.iter().any(|continuation| continuation.is_empty()) {
+            anyhow::bail!("continuation was empty")
+        }
+
+        // Feed the whole prompt through a pipeline once
+        let mut prompt_pipeline = MixFormer::new(&self.config, self.vb.clone())?;
+        let input = Tensor::new(prompt.as_slice(), &self.device)?.unsqueeze(0)?;
+        let prompt_logits = prompt_pipeline.forward(&input)?;
+
+        let mut all_log_probs = Vec::with_capacity(continuations.len());
+        for continuation in continuations {
+            // Continue from a copy of the pipeline that has seen the prompt
+            let mut pipeline = prompt_pipeline.clone();
+            let mut logits = prompt_logits.clone();
+            let mut log_probs = Vec::with_capacity(continuation.len());
+            for (i, &token) in continuation.iter().enumerate() {
+                // Add the log-probability of the expected token
+                let token_log_probs = candle_nn::ops::log_softmax(
+                    &logits.squeeze(0)?.to_dtype(DType::F32)?,
+                    candle_core::D::Minus1,
+                )?;
+                log_probs.push(token_log_probs.get(token as usize)?.to_scalar::<f32>()?);
+
+                // Forward the token to get the logits for the next one
+                if i + 1 < continuation.len() {
+                    let input = Tensor::new(&[token], &self.device)?.unsqueeze(0)?;
+                    logits = pipeline.forward(&input)?;
+                }
             }
+            all_log_probs.push(log_probs);
+        }
 
-            // Raise the temperature and try again
-            temperature += 0.2;
+        Ok(all_log_probs)
+    }
+}
+
+/// An item chosen by `Model::try_choose_item_indexed`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Choice {
+    /// Index of the item in the input
+    pub index: usize,
+    /// The item as it was written in the input
+    pub original: String,
+    /// The item lowercased and trimmed
+    pub normalized: String,
+}
+
+/// The tokens of several items arranged as a tree, so items with the same first tokens share a path
+#[derive(Default)]
+struct TokenTrie {
+    children: HashMap<u32, TokenTrie>,
+    /// Indices of the items whose tokens pass through this node
+    items: Vec<usize>,
+}
+
+impl TokenTrie {
+    fn insert(&mut self, tokens: &[u32], item: usize) {
+        self.items.push(item);
+        if let Some((first, rest)) = tokens.split_first() {
+            self.children.entry(*first).or_default().insert(rest, item);
         }
-        
-        // Return the response
-        response
     }
 }
 
+/// Turn scores into probabilities that sum to 1
+pub fn softmax(scores: impl IntoIterator<Item = f32>) -> Vec<f32> {
+    let scores: Vec<f32> = scores.into_iter().collect();
+
+    // Subtract the highest score to keep the exponents from overflowing
+    let max_score = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let weights: Vec<f32> = scores.iter().map(|score| (score - max_score).exp()).collect();
+    let total: f32 = weights.iter().sum();
+    weights.into_iter().map(|weight| weight / total).collect()
+}
+
 pub struct InferIter {
     device: Device,
     tokens: TokenString,
-    step: usize,
+    /// Number of tokens already forwarded through the pipeline
+    fed: usize,
     pipeline: MixFormer,
     logits_processor: LogitsProcessor,
     repeat_penalty: f32,
     repeat_last_n: usize,
     eos_token: u32,
     reached_eos: bool,
+    banned_tokens: Vec<u32>,
+    allowed_tokens: Option<Vec<u32>>,
 }
 
 impl InferIter {
@@ -309,35 +685,56 @@ impl InferIter {
         Self {
             device,
             tokens,
-            step: 0,
+            fed: 0,
             pipeline,
             logits_processor,
             repeat_penalty,
             repeat_last_n,
             eos_token,
             reached_eos: false,
+            banned_tokens: Vec::new(),
+            allowed_tokens: None,
         }
     }
 
+    /// Prevent the given tokens from being generated from now on
+    pub fn ban_tokens(&mut self, tokens: impl IntoIterator<Item = u32>) {
+        self.banned_tokens.extend(tokens);
+    }
+
+    /// Only allow the given tokens to be generated from now on, or any token if None
+    pub fn allow_only(&mut self, tokens: Option<Vec<u32>>) {
+        self.allowed_tokens = tokens;
+    }
+
+    /// Write the tokens as if the model had generated them, so the next token continues after them
+    pub(crate) fn push_tokens(&mut self, tokens: &[u32]) {
+        self.tokens.push_tokens(tokens);
+        self.reached_eos = false;
+    }
+
     pub fn next_token(&mut self) -> Option<u32> {
         // Exit early if we already got the end of text token
         if self.reached_eos {
             return None;
         }
 
-        // Get the context size for this step
-        let context_size = if self.step > 0 { 1 } else { self.tokens.len() };
-
-        // Get the context
-        let context = self.tokens
-            .get(self.tokens.len().saturating_sub(context_size)..)
-            .unwrap();
+        // Get the tokens the pipeline hasn't seen yet
+        let context = self.tokens.get(self.fed..).unwrap().to_vec();
 
-        // Create the input tensor containing the context
-        let input = Tensor::new(context, &self.device).unwrap().unsqueeze(0).unwrap();
+        // The first step forwards the whole prompt at once, but once the pipeline has a cache
+        // it can only take one token at a time, so pushed tokens are forwarded one by one
+        let inputs: Vec<&[u32]> = if self.fed == 0 { vec![&context] } else { context.chunks(1).collect() };
+        let mut logits = None;
+        for input in inputs {
+            // Create the input tensor containing the context
+            let input = Tensor::new(input, &self.device).unwrap().unsqueeze(0).unwrap();
 
-        // Forward the input through the pipeline
-        let logits = self.pipeline.forward(&input).unwrap();
+            // Forward the input through the pipeline
+            logits = Some(self.pipeline.forward(&input).unwrap());
+        }
+        self.fed = self.tokens.len();
+        let logits = logits.unwrap();
 
         // Get the logits
         let logits = logits.squeeze(0).unwrap().to_dtype(DType::F32).unwrap();
@@ -355,12 +752,37 @@ impl InferIter {
             ).unwrap()
         };
 
+        // Make the banned tokens impossible to sample
+        let logits = if self.banned_tokens.is_empty() {
+            logits
+        } else {
+            let mut values = logits.to_vec1::<f32>().unwrap();
+            for &token in &self.banned_tokens {
+                if let Some(value) = values.get_mut(token as usize) {
+                    *value = f32::NEG_INFINITY;
+                }
+            }
+            Tensor::new(values, &self.device).unwrap()
+        };
+
+        // Make every token that isn't allowed impossible to sample
+        let logits = match &self.allowed_tokens {
+            Some(allowed_tokens) => {
+                let values = logits.to_vec1::<f32>().unwrap();
+                let mut masked = vec![f32::NEG_INFINITY; values.len()];
+                for &token in allowed_tokens {
+                    if let Some(value) = values.get(token as usize) {
+                        masked[token as usize] = *value;
+                    }
+                }
+                Tensor::new(masked, &self.device).unwrap()
+            }
+            None => logits,
+        };
+
         // Sample the next token
         let next_token = self.logits_processor.sample(&logits).unwrap();
 
-        // Increment the step
-        self.step += 1;
-
         // If the token is not the end of text token, add it to the tokens
         if next_token != self.eos_token {
             self.tokens.push_token(next_token);
@@ -401,6 +823,143 @@ impl InferIter {
 
         response
     }
+
+    /// Run the iterator until completion, until `max_tokens` tokens have been generated
+    /// or until any of the `stop` strings is generated, and return everything before the stop string
+    pub fn complete_until_any(
+        self,
+        max_tokens: Option<usize>,
+        stop: &[impl AsRef<str>],
+    ) -> String {
+        self.complete_tokens_until_any(max_tokens, stop).1
+    }
+
+    /// Like `complete_until_any`, but also return every token that was generated,
+    /// including any that come after the stop string
+    pub(crate) fn complete_tokens_until_any(
+        mut self,
+        max_tokens: Option<usize>,
+        stop: &[impl AsRef<str>],
+    ) -> (Vec<u32>, String) {
+        let model = self.tokens.model.clone();
+        let mut decoder = TokenDecoder::default();
+        while let Some(token) = self.next_token() {
+            // Decode only the newest tokens, keeping multi-token characters whole
+            let searched = decoder.text().len();
+            decoder.push(&model, token);
+
+            // Cut the response at the earliest stop string, if any was generated
+            if let Some(end) = find_stop(decoder.text(), searched, stop) {
+                let response = decoder.text()[..end].to_string();
+                return (decoder.into_tokens(), response);
+            }
+
+            // Stop once the token limit has been reached
+            if max_tokens.is_some_and(|max_tokens| decoder.tokens().len() >= max_tokens) {
+                break;
+            }
+        }
+
+        let response = decoder.finish(&model);
+        (decoder.into_tokens(), response)
+    }
+}
+
+/// Starting value of a 64-bit FNV-1a hash
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// Continue a 64-bit FNV-1a hash with the bytes.
+/// Unlike `std`'s hasher it is the same between Rust versions, so fingerprints can be saved.
+fn hash_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Hash the contents of a file
+fn hash_file(path: &Path) -> Result<u64> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("cannot read {} to fingerprint it: {}", path.display(), e))?;
+    let mut buffer = vec![0; 1 << 20];
+    let mut hash = FNV_OFFSET;
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hash),
+            read => hash = hash_bytes(hash, &buffer[..read]),
+        }
+    }
+}
+
+fn fingerprint_of(model_hash: u64, tokenizer_hash: u64) -> String {
+    format!("{}@{:016x}+{}@{:016x}", MODEL_FILE, model_hash, TOKENIZER_FILE, tokenizer_hash)
+}
+
+/// Decodes generated tokens as they come, only decoding the newest few again each time
+/// so long generations don't decode everything after every token
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TokenDecoder {
+    tokens: Vec<u32>,
+    /// Start of the tokens decoded again for context
+    prefix_index: usize,
+    /// Start of the tokens whose text hasn't been added yet
+    read_index: usize,
+    text: String,
+}
+
+impl TokenDecoder {
+    /// Add a token, adding its text once any character split between tokens is complete
+    pub(crate) fn push(&mut self, model: &Model, token: u32) {
+        self.tokens.push(token);
+        let prefix = model.detokenize(&self.tokens[self.prefix_index..self.read_index]);
+        let text = model.detokenize(&self.tokens[self.prefix_index..]);
+        if text.len() > prefix.len() && !text.ends_with(char::REPLACEMENT_CHARACTER) {
+            if let Some(new_text) = text.get(prefix.len()..) {
+                self.text.push_str(new_text);
+                self.prefix_index = self.read_index;
+                self.read_index = self.tokens.len();
+            }
+        }
+    }
+
+    /// The text of the tokens so far, without a character that is still split between tokens
+    pub(crate) fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub(crate) fn tokens(&self) -> &[u32] {
+        &self.tokens
+    }
+
+    pub(crate) fn into_tokens(self) -> Vec<u32> {
+        self.tokens
+    }
+
+    /// Get the text of every token, including any character left incomplete
+    pub(crate) fn finish(&self, model: &Model) -> String {
+        if self.read_index == self.tokens.len() {
+            return self.text.clone();
+        }
+        let prefix = model.detokenize(&self.tokens[self.prefix_index..self.read_index]);
+        let text = model.detokenize(&self.tokens[self.prefix_index..]);
+        format!("{}{}", self.text, text.get(prefix.len()..).unwrap_or_default())
+    }
+}
+
+/// Find where the earliest stop string in the text starts, only looking for matches that end after
+/// the first `searched` bytes, which were already searched
+pub(crate) fn find_stop(text: &str, searched: usize, stop: &[impl AsRef<str>]) -> Option<usize> {
+    stop.iter()
+        .map(AsRef::as_ref)
+        .filter(|stop| !stop.is_empty())
+        .filter_map(|stop| {
+            // A new match starts at most one byte short of the stop string's length before the new text
+            let mut start = searched.min(text.len()).saturating_sub(stop.len() - 1);
+            while !text.is_char_boundary(start) {
+                start -= 1;
+            }
+            text[start..].find(stop).map(|index| start + index)
+        })
+        .min()
 }
 
 impl Iterator for InferIter {
@@ -423,30 +982,284 @@ impl Into<String> for InferIter {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum InferValue {
     String(String),
     Float(f64),
     Int(i64),
+    Bool(bool),
+    List(Vec<InferValue>),
+    Map(BTreeMap<String, InferValue>),
+    Null,
 }
 
 impl InferValue {
     pub fn to_string(&self) -> String {
         match self {
-            Self::String(s) => s.clone(),
+            // Strings that would parse as something else, such as "", "null" or "5", are quoted
+            Self::String(s) => match Self::from_str(s) {
+                Ok(Self::String(parsed)) if parsed == *s => s.clone(),
+                _ => quote(s),
+            },
             Self::Float(f) => f.to_string(),
             Self::Int(i) => i.to_string(),
+            Self::Bool(b) => b.to_string(),
+            Self::List(list) => format!(
+                "[{}]",
+                list.iter().map(|value| value.to_nested_string()).join(", ")
+            ),
+            Self::Map(map) => format!(
+                "{{{}}}",
+                map.iter()
+                    .map(|(key, value)| format!("{}: {}", key, value.to_nested_string()))
+                    .join(", ")
+            ),
+            Self::Null => "null".to_string(),
+        }
+    }
+
+    /// Format the value for use inside a list or map, quoting strings so they survive parsing
+    fn to_nested_string(&self) -> String {
+        match self {
+            Self::String(s) => quote(s),
+            _ => self.to_string(),
         }
     }
 
     pub fn from_str(s: &str) -> Result<Self> {
-        if let Ok(f) = s.parse::<f64>() {
-            Ok(Self::Float(f))
-        } else if let Ok(i) = s.parse::<i64>() {
+        let s = s.trim();
+
+        // Lists and maps are parsed recursively
+        if let Some(inner) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            return Ok(Self::List(
+                split_top_level(inner)
+                    .into_iter()
+                    .map(Self::from_str)
+                    .collect::<Result<_>>()?,
+            ));
+        }
+        if let Some(inner) = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+            let mut map = BTreeMap::new();
+            for entry in split_top_level(inner) {
+                let Some((key, value)) = split_top_level_once(entry, ':') else {
+                    anyhow::bail!("map entry {:?} is missing a ':'", entry)
+                };
+                let key = key.trim().trim_matches('"').to_string();
+                map.insert(key, Self::from_str(value)?);
+            }
+            return Ok(Self::Map(map));
+        }
+
+        // Quoted strings are kept verbatim, even if they are empty or look like another type
+        if let Some(text) = unquote(s) {
+            return Ok(Self::String(text));
+        }
+
+        // Scalars, checking integers before floats so "5" stays an Int.
+        // Words like "nan" and "infinity" parse as floats, but are kept as strings.
+        if let Ok(i) = s.parse::<i64>() {
             Ok(Self::Int(i))
+        } else if let Some(f) = s.parse::<f64>().ok().filter(|f| f.is_finite()) {
+            Ok(Self::Float(f))
         } else {
-            Ok(Self::String(s.to_string().trim_matches('"').to_string()))
+            match s.to_lowercase().as_str() {
+                "true" | "yes" => Ok(Self::Bool(true)),
+                "false" | "no" => Ok(Self::Bool(false)),
+                "" | "null" | "none" => Ok(Self::Null),
+                _ => Ok(Self::String(s.trim_matches('"').to_string())),
+            }
+        }
+    }
+
+    /// Get the value as a string slice if it is a `String`
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Get the value as a float if it is a `Float` or an `Int`
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Float(f) => Some(*f),
+            Self::Int(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    /// Get the value as an integer if it is an `Int`
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int(i) => Some(*i),
+            _ => None,
         }
     }
+
+    /// Get the value as a bool if it is a `Bool`
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Get the value as a slice of values if it is a `List`
+    pub fn as_list(&self) -> Option<&[InferValue]> {
+        match self {
+            Self::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Get the value as a map if it is a `Map`
+    pub fn as_map(&self) -> Option<&BTreeMap<String, InferValue>> {
+        match self {
+            Self::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Check if the value is `Null`
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+}
+
+/// Quote a string so `InferValue::from_str` reads it back verbatim
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Get the text of a string written by `quote`, or None if `s` isn't a single quoted string
+fn unquote(s: &str) -> Option<String> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.push(chars.next()?),
+            '"' => return None,
+            c => text.push(c),
+        }
+    }
+    Some(text)
+}
+
+/// Split a string on commas that are not nested inside brackets, braces or quotes.
+/// Empty trailing entries are dropped.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = s;
+    while let Some((part, remaining)) = split_top_level_once(rest, ',') {
+        parts.push(part);
+        rest = remaining;
+    }
+    if !rest.trim().is_empty() {
+        parts.push(rest);
+    }
+    parts
+}
+
+/// Split a string at the first `separator` that is not nested inside brackets, braces or quotes
+fn split_top_level_once(s: &str, separator: char) -> Option<(&str, &str)> {
+    let mut depth = 0usize;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        // Skip characters escaped inside quotes
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '[' | '{' if !in_quotes => depth += 1,
+            ']' | '}' if !in_quotes => depth = depth.saturating_sub(1),
+            c if c == separator && depth == 0 && !in_quotes => {
+                return Some((&s[..i], &s[i + c.len_utf8()..]))
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+impl Serialize for InferValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Self::String(s) => serializer.serialize_str(s),
+            Self::Float(f) => serializer.serialize_f64(*f),
+            Self::Int(i) => serializer.serialize_i64(*i),
+            Self::Bool(b) => serializer.serialize_bool(*b),
+            Self::List(list) => serializer.collect_seq(list),
+            Self::Map(map) => serializer.collect_map(map),
+            Self::Null => serializer.serialize_unit(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for InferValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_any(InferValueVisitor)
+    }
+}
+
+/// Deserializes an `InferValue` from any self-describing format
+struct InferValueVisitor;
+
+impl<'de> Visitor<'de> for InferValueVisitor {
+    type Value = InferValue;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a string, number, bool, list, map or null")
+    }
+
+    fn visit_bool<Er>(self, b: bool) -> std::result::Result<InferValue, Er> {
+        Ok(InferValue::Bool(b))
+    }
+
+    fn visit_i64<Er>(self, i: i64) -> std::result::Result<InferValue, Er> {
+        Ok(InferValue::Int(i))
+    }
+
+    fn visit_u64<Er>(self, i: u64) -> std::result::Result<InferValue, Er> {
+        // Integers too large for an i64 are kept as floats instead of wrapping around
+        Ok(i64::try_from(i).map_or(InferValue::Float(i as f64), InferValue::Int))
+    }
+
+    fn visit_f64<Er>(self, f: f64) -> std::result::Result<InferValue, Er> {
+        Ok(InferValue::Float(f))
+    }
+
+    fn visit_str<Er>(self, s: &str) -> std::result::Result<InferValue, Er> {
+        Ok(InferValue::String(s.to_string()))
+    }
+
+    fn visit_unit<Er>(self) -> std::result::Result<InferValue, Er> {
+        Ok(InferValue::Null)
+    }
+
+    fn visit_none<Er>(self) -> std::result::Result<InferValue, Er> {
+        Ok(InferValue::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<InferValue, A::Error> {
+        let mut list = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            list.push(value);
+        }
+        Ok(InferValue::List(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> std::result::Result<InferValue, A::Error> {
+        let mut map = BTreeMap::new();
+        while let Some((key, value)) = access.next_entry()? {
+            map.insert(key, value);
+        }
+        Ok(InferValue::Map(map))
+    }
 }
 
 impl FromStr for InferValue {
@@ -529,9 +1342,39 @@ impl From<isize> for InferValue {
     }
 }
 
-impl<T: Into<InferValue>> From<&T> for InferValue {
+impl From<bool> for InferValue {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl<T: Into<InferValue>> From<Vec<T>> for InferValue {
+    fn from(list: Vec<T>) -> Self {
+        Self::List(list.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<K: Display, V: Into<InferValue>> From<BTreeMap<K, V>> for InferValue {
+    fn from(map: BTreeMap<K, V>) -> Self {
+        Self::Map(map.into_iter().map(|(key, value)| (key.to_string(), value.into())).collect())
+    }
+}
+
+impl<K: Display, V: Into<InferValue>> From<HashMap<K, V>> for InferValue {
+    fn from(map: HashMap<K, V>) -> Self {
+        Self::Map(map.into_iter().map(|(key, value)| (key.to_string(), value.into())).collect())
+    }
+}
+
+impl<T: Into<InferValue>> From<Option<T>> for InferValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+impl<T: Clone + Into<InferValue>> From<&T> for InferValue {
     fn from(t: &T) -> Self {
-        t.into()
+        t.clone().into()
     }
 }
 
@@ -0,0 +1,202 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Maximum number of tokens in a generated name
+pub const MAX_NAME_TOKENS: usize = 12;
+/// How many times more names than requested `NameGenerator::generate` may try before giving up
+pub const NAME_ATTEMPTS_PER_NAME: usize = 4;
+/// Number of the most recently issued or reserved names the model is shown to avoid
+pub const MAX_AVOIDED_NAMES: usize = 20;
+
+/// What the names made by a `NameGenerator` should sound like
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameStyle {
+    /// The culture or setting the names come from, such as "elvish" or "Viking"
+    pub culture: Option<String>,
+    /// The number of syllables, such as 2..=3, or None for any
+    pub syllables: Option<(usize, usize)>,
+    /// Example names in the style
+    pub examples: Vec<String>,
+}
+
+impl NameStyle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_culture(mut self, culture: impl Into<String>) -> Self {
+        self.culture = Some(culture.into());
+        self
+    }
+
+    pub fn with_syllables(mut self, min: usize, max: usize) -> Self {
+        self.syllables = Some((min, max));
+        self
+    }
+
+    pub fn with_example(mut self, example: impl Into<String>) -> Self {
+        self.examples.push(example.into());
+        self
+    }
+}
+
+/// Generates unique names in a style, never issuing the same name twice
+pub struct NameGenerator {
+    model: Model,
+    style: NameStyle,
+    /// Lowercased names already issued or reserved
+    issued: BTreeSet<String>,
+    /// The last `MAX_AVOIDED_NAMES` names issued or reserved, oldest first
+    recent: VecDeque<String>,
+    seed: u64,
+}
+
+impl NameGenerator {
+    pub fn new(model: Model, style: NameStyle) -> Self {
+        Self {
+            model,
+            style,
+            issued: BTreeSet::new(),
+            recent: VecDeque::new(),
+            seed: 0,
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn style(&self) -> &NameStyle {
+        &self.style
+    }
+
+    /// Prevent names from being issued, such as names already used in the world
+    pub fn reserve(&mut self, names: impl IntoIterator<Item = impl AsRef<str>>) {
+        for name in names {
+            self.remember(name.as_ref().trim());
+        }
+    }
+
+    /// Mark the name as issued and keep it among the recent names
+    fn remember(&mut self, name: &str) {
+        if !self.issued.insert(name.to_lowercase()) {
+            return;
+        }
+        self.recent.push_back(name.to_string());
+        if self.recent.len() > MAX_AVOIDED_NAMES {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Whether the name was already issued or reserved, ignoring case
+    pub fn is_taken(&self, name: impl AsRef<str>) -> bool {
+        self.issued.contains(&name.as_ref().trim().to_lowercase())
+    }
+
+    /// Generate up to `n` names that haven't been issued before, and remember them as issued.
+    /// Fewer names are returned if the model keeps repeating itself.
+    pub fn generate(&mut self, n: usize) -> Vec<String> {
+        let mut names = Vec::with_capacity(n);
+        for _ in 0..n.saturating_mul(NAME_ATTEMPTS_PER_NAME) {
+            if names.len() >= n {
+                break;
+            }
+
+            // Generate a name with the next seed
+            let seed = self.seed;
+            self.seed = self.seed.wrapping_add(1);
+            let Some(name) = self.generate_one(seed) else {
+                continue;
+            };
+
+            // Skip names that were already issued or break the style
+            if self.is_taken(&name) || !self.fits_syllables(&name) {
+                continue;
+            }
+            self.remember(&name);
+            names.push(name);
+        }
+
+        names
+    }
+
+    /// Generate a single name, trimmed and without quotes
+    fn generate_one(&self, seed: u64) -> Option<String> {
+        // Describe the style
+        let mut instruction = "Invent a new name".to_string();
+        if let Some(culture) = &self.style.culture {
+            instruction.push_str(&format!(" that sounds {}", culture));
+        }
+        if let Some((min, max)) = self.style.syllables {
+            instruction.push_str(&format!(" with {} to {} syllables", min, max));
+        }
+        instruction.push_str(". Answer with only the name.");
+
+        // Show the examples and the most recently issued names to avoid
+        let examples = self.style.examples.join(", ");
+        let avoid = self.recent.iter().join(", ");
+        let mut extra = HashMap::new();
+        if !examples.is_empty() {
+            extra.insert("Examples", examples.as_str());
+        }
+        if !avoid.is_empty() {
+            extra.insert("Names Already Used", avoid.as_str());
+        }
+        extra.insert("Response", "Name:");
+
+        let config = GenerationConfig::new(seed)
+            .with_temp(0.9)
+            .with_max_tokens(MAX_NAME_TOKENS)
+            .with_stop("\n")
+            .with_stop(",");
+        let name = self
+            .model
+            .instruct_with(instruction, Some(&extra), &config)
+            .ok()?;
+        let name = name
+            .trim()
+            .trim_matches(|c: char| matches!(c, '"' | '\'' | '.' | '*'))
+            .split_whitespace()
+            .join(" ");
+
+        (!name.is_empty()).then_some(name)
+    }
+
+    /// Whether the name has a number of syllables allowed by the style
+    fn fits_syllables(&self, name: &str) -> bool {
+        match self.style.syllables {
+            Some((min, max)) => (min..=max).contains(&count_syllables(name)),
+            None => true,
+        }
+    }
+}
+
+/// Roughly count the syllables of a name by counting groups of vowels in each word
+pub fn count_syllables(name: &str) -> usize {
+    name.split_whitespace()
+        .map(|word| {
+            let word = word.to_lowercase();
+            let mut syllables = 0;
+            let mut previous_vowel = false;
+            for c in word.chars() {
+                let vowel = matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+                if vowel && !previous_vowel {
+                    syllables += 1;
+                }
+                previous_vowel = vowel;
+            }
+
+            // A silent e at the end of a longer word doesn't count
+            if syllables > 1 && word.ends_with('e') && !word.ends_with("le") {
+                syllables -= 1;
+            }
+            syllables.max(1)
+        })
+        .sum()
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Maximum number of tokens in an NPC's spoken line
+pub const MAX_LINE_TOKENS: usize = 64;
+/// Maximum number of tokens in the target of an NPC's response
+pub const MAX_TARGET_TOKENS: usize = 8;
+/// Targets the model may write when an NPC isn't addressing anyone
+const NO_TARGETS: &[&str] = &["", "none", "nobody", "no one", "n/a"];
+
+/// What an NPC decided to do, what it says, and who it's directed at
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NpcResponse<I> {
+    /// One of the allowed intents
+    pub intent: I,
+    /// What the NPC says out loud
+    pub line: String,
+    /// Who or what the response is directed at, if anyone
+    pub target: Option<String>,
+}
+
+impl Model {
+    /// Decide how an NPC responds to the situation. The intent is constrained to one of
+    /// `allowed_intents` while decoding, so game logic can branch on it; the line and target are free text.
+    /// Returns an error if there are no allowed intents.
+    pub fn npc_respond<I: Clone + Display>(
+        &self,
+        npc_profile: impl AsRef<str>,
+        situation: impl AsRef<str>,
+        allowed_intents: impl IntoIterator<Item = I>,
+    ) -> Result<NpcResponse<I>> {
+        self.npc_respond_with(
+            npc_profile,
+            situation,
+            allowed_intents,
+            &GenerationConfig::new(0).with_temp(0.7),
+        )
+    }
+
+    /// Decide how an NPC responds like `npc_respond`, sampling with the config
+    pub fn npc_respond_with<I: Clone + Display>(
+        &self,
+        npc_profile: impl AsRef<str>,
+        situation: impl AsRef<str>,
+        allowed_intents: impl IntoIterator<Item = I>,
+        config: &GenerationConfig,
+    ) -> Result<NpcResponse<I>> {
+        let allowed_intents = allowed_intents.into_iter().collect_vec();
+        if allowed_intents.is_empty() {
+            bail!("an NPC needs at least one allowed intent")
+        }
+        let intent_names = allowed_intents
+            .iter()
+            .map(|intent| intent.to_string())
+            .collect_vec();
+
+        // Describe the NPC and what it can do
+        let instruction = format!(
+            "Decide how the NPC responds to the situation. \
+            Choose an intent from: {}. Then write what the NPC says and who it says it to.",
+            intent_names.join(", ")
+        );
+        let mut extra = HashMap::new();
+        extra.insert("NPC", npc_profile.as_ref().trim().to_string());
+        extra.insert("Situation", situation.as_ref().trim().to_string());
+
+        // Only let the model write one of the intents, followed by the end of the line
+        extra.insert("Response", "Intent:".to_string());
+        let prompt = self.create_instruct_prompt(&instruction, Some(&extra));
+        let items = intent_names
+            .iter()
+            .map(|intent| format!(" {}", intent))
+            .collect_vec();
+        let chosen = self
+            .choose_by_trie(
+                prompt,
+                &items,
+                "\n",
+                config.seed,
+                config.temp.unwrap_or(0.0),
+            )
+            .unwrap_or_else(|| items[0].clone());
+        let index = items.iter().position(|item| *item == chosen).unwrap_or(0);
+
+        // Write the line in quotes
+        let response = format!("Intent: {}\nLine: \"", intent_names[index]);
+        extra.insert("Response", response.clone());
+        let line_config = GenerationConfig {
+            max_tokens: Some(MAX_LINE_TOKENS),
+            stop: vec!["\"".to_string(), "\n".to_string()],
+            ..config.clone()
+        };
+        let line = self
+            .instruct_with(&instruction, Some(&extra), &line_config)?
+            .trim()
+            .to_string();
+
+        // Write who the line is for
+        extra.insert("Response", format!("{}{}\"\nTarget:", response, line));
+        let target_config = GenerationConfig {
+            max_tokens: Some(MAX_TARGET_TOKENS),
+            stop: vec!["\n".to_string()],
+            ..config.clone()
+        };
+        let target = self
+            .instruct_with(&instruction, Some(&extra), &target_config)?
+            .trim()
+            .trim_end_matches('.')
+            .to_string();
+        let target = (!NO_TARGETS.contains(&target.to_lowercase().as_str())).then_some(target);
+
+        Ok(NpcResponse {
+            intent: allowed_intents[index].clone(),
+            line,
+            target,
+        })
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Default maximum number of steps in a plan without slots
+pub const DEFAULT_MAX_PLAN_STEPS: usize = 8;
+/// Maximum number of tokens in a single step of a plan
+pub const MAX_STEP_TOKENS: usize = 48;
+
+/// Options for `Model::plan`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlanConfig {
+    /// Maximum number of steps when there are no slots
+    pub max_steps: usize,
+    /// Labels of the steps, such as "morning", "noon" and "evening".
+    /// If there are any, the plan has exactly one step per slot, in order.
+    pub slots: Vec<String>,
+    /// Whether to ask the model how feasible each step is under the constraints
+    pub score_feasibility: bool,
+    /// Seed of the first step. Each later step adds its index, so steps don't repeat each other.
+    pub seed: u64,
+    pub temp: f64,
+}
+
+impl Default for PlanConfig {
+    fn default() -> Self {
+        Self {
+            max_steps: DEFAULT_MAX_PLAN_STEPS,
+            slots: Vec::new(),
+            score_feasibility: false,
+            seed: 0,
+            temp: 0.3,
+        }
+    }
+}
+
+impl PlanConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn with_slots(mut self, slots: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.slots = slots.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_feasibility(mut self, score_feasibility: bool) -> Self {
+        self.score_feasibility = score_feasibility;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_temp(mut self, temp: f64) -> Self {
+        self.temp = temp;
+        self
+    }
+}
+
+/// A step of a plan
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlanStep {
+    /// The slot the step is in, if the plan has slots
+    pub slot: Option<String>,
+    pub action: String,
+    /// How likely the model thinks the step can be done under the constraints, if scored
+    pub feasibility: Option<f32>,
+}
+
+impl Model {
+    /// Break the goal down into ordered steps that respect the constraints, such as a day plan
+    /// for an NPC. Without slots in the config, the model decides how many steps are needed
+    /// up to the config's maximum. Returns an error if the goal is empty or no steps were written.
+    pub fn plan(
+        &self,
+        goal: impl AsRef<str>,
+        constraints: impl IntoIterator<Item = impl AsRef<str>>,
+        config: &PlanConfig,
+    ) -> Result<Vec<PlanStep>> {
+        let goal = goal.as_ref().trim();
+        if goal.is_empty() {
+            bail!("cannot plan for an empty goal")
+        }
+        let constraints = constraints
+            .into_iter()
+            .map(|constraint| format!("- {}", constraint.as_ref().trim()))
+            .join("\n");
+
+        // Describe the plan wanted
+        let instruction = if config.slots.is_empty() {
+            "Write a plan to achieve the goal as a numbered list of short, concrete steps in order."
+                .to_string()
+        } else {
+            format!(
+                "Write a plan to achieve the goal with one short, concrete step for each of: {}.",
+                config.slots.join(", ")
+            )
+        };
+        let mut extra = HashMap::new();
+        extra.insert("Goal", goal.to_string());
+        if !constraints.is_empty() {
+            extra.insert("Constraints", constraints.clone());
+        }
+
+        // Write one step at a time, labeled with its number or slot
+        let labels = if config.slots.is_empty() {
+            (1..=config.max_steps)
+                .map(|number| format!("{}.", number))
+                .collect_vec()
+        } else {
+            config
+                .slots
+                .iter()
+                .map(|slot| format!("{}:", slot))
+                .collect_vec()
+        };
+        let mut response = String::new();
+        let mut steps = Vec::new();
+        for (index, label) in labels.iter().enumerate() {
+            extra.insert("Response", format!("{}{}", response, label));
+            let generation_config = GenerationConfig::new(config.seed.wrapping_add(index as u64))
+                .with_temp(config.temp)
+                .with_max_tokens(MAX_STEP_TOKENS)
+                .with_stop("\n")
+                .with_stop("###");
+            let action = self.instruct_with(&instruction, Some(&extra), &generation_config)?;
+            let action = action.trim();
+
+            // Without slots, an empty step means the plan is done
+            if action.is_empty() && config.slots.is_empty() {
+                break;
+            }
+            response.push_str(&format!("{} {}\n", label, action));
+            steps.push(PlanStep {
+                slot: config.slots.get(index).cloned(),
+                action: action.to_string(),
+                feasibility: None,
+            });
+        }
+        if steps.is_empty() {
+            bail!("could not write a plan for the goal")
+        }
+
+        // Score how feasible each step is
+        if config.score_feasibility {
+            for step in &mut steps {
+                let context = format!(
+                    "Goal: {}\nConstraints:\n{}\nStep: {}",
+                    goal, constraints, step.action
+                );
+                step.feasibility = Some(self.yes_probability(
+                    context,
+                    "Can the step be done without breaking any of the constraints?",
+                )?);
+            }
+        }
+
+        Ok(steps)
+    }
+}
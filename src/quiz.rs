@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Number of options every generated quiz question has
+pub const QUIZ_OPTIONS: usize = 4;
+/// Maximum number of tokens in a generated quiz question with its options and answer
+pub const MAX_QUESTION_TOKENS: usize = 160;
+/// How many times more questions than requested `generate_quiz` may try before giving up
+pub const QUESTION_ATTEMPTS_PER_QUESTION: usize = 3;
+/// Labels of the options of a quiz question
+const QUIZ_LABELS: [char; QUIZ_OPTIONS] = ['A', 'B', 'C', 'D'];
+
+/// How hard a quiz should be
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "easy"),
+            Difficulty::Medium => write!(f, "medium"),
+            Difficulty::Hard => write!(f, "hard"),
+        }
+    }
+}
+
+/// A multiple choice question with exactly one correct option
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuizQuestion {
+    pub question: String,
+    pub options: Vec<String>,
+    /// Index of the correct option
+    pub correct_index: usize,
+}
+
+impl QuizQuestion {
+    /// Whether the question has text, at least two distinct options, and a correct option that exists
+    pub fn is_valid(&self) -> bool {
+        !self.question.is_empty()
+            && self.options.len() >= 2
+            && self.options.iter().all(|option| !option.is_empty())
+            && self
+                .options
+                .iter()
+                .map(|option| option.to_lowercase())
+                .all_unique()
+            && self.correct_index < self.options.len()
+    }
+
+    pub fn correct_option(&self) -> &str {
+        &self.options[self.correct_index]
+    }
+}
+
+impl Model {
+    /// Generate up to `n` multiple choice questions about the topic with `QUIZ_OPTIONS` options each.
+    /// Questions that don't parse, fail `QuizQuestion::is_valid`, or repeat an earlier question are
+    /// generated again. Returns fewer questions if the model keeps failing.
+    pub fn generate_quiz(
+        &self,
+        topic: impl AsRef<str>,
+        difficulty: Difficulty,
+        n: usize,
+    ) -> Vec<QuizQuestion> {
+        self.generate_quiz_with(
+            topic,
+            difficulty,
+            n,
+            &GenerationConfig::new(0).with_temp(0.7),
+        )
+    }
+
+    /// Generate a quiz like `generate_quiz`, sampling with the config
+    pub fn generate_quiz_with(
+        &self,
+        topic: impl AsRef<str>,
+        difficulty: Difficulty,
+        n: usize,
+        config: &GenerationConfig,
+    ) -> Vec<QuizQuestion> {
+        let topic = topic.as_ref().trim();
+        let instruction = format!(
+            "Write a new {} trivia question about {} with {} options labeled {}, \
+            exactly one of which is correct. Then write the letter of the correct option after \"Answer:\".",
+            difficulty,
+            topic,
+            QUIZ_OPTIONS,
+            QUIZ_LABELS.iter().join(", ")
+        );
+
+        let mut questions: Vec<QuizQuestion> = Vec::with_capacity(n);
+        for attempt in 0..n.saturating_mul(QUESTION_ATTEMPTS_PER_QUESTION) {
+            if questions.len() >= n {
+                break;
+            }
+
+            // Show the earlier questions so they aren't repeated
+            let mut extra = HashMap::new();
+            let earlier = questions
+                .iter()
+                .map(|question| format!("- {}", question.question))
+                .join("\n");
+            if !earlier.is_empty() {
+                extra.insert("Questions Already Asked", earlier);
+            }
+            extra.insert("Response", "Question:".to_string());
+            let question_config = GenerationConfig {
+                seed: config.seed.wrapping_add(attempt as u64),
+                max_tokens: Some(MAX_QUESTION_TOKENS),
+                stop: vec!["###".to_string(), "\n\n".to_string()],
+                ..config.clone()
+            };
+            let Ok(text) = self.instruct_with(&instruction, Some(&extra), &question_config) else {
+                continue;
+            };
+
+            // Keep valid questions with every option that weren't asked already
+            let Some(question) = parse_quiz_question(&format!("Question:{}", text)) else {
+                continue;
+            };
+            if question.options.len() == QUIZ_OPTIONS
+                && !questions
+                    .iter()
+                    .any(|other| other.question.eq_ignore_ascii_case(&question.question))
+            {
+                questions.push(question);
+            }
+        }
+
+        questions
+    }
+}
+
+/// Parse a question written as "Question: ...", one "A) ..." line per option, and "Answer: A".
+/// Returns None if it isn't in that form or isn't valid.
+pub(crate) fn parse_quiz_question(text: &str) -> Option<QuizQuestion> {
+    let mut question = None;
+    let mut options = Vec::new();
+    let mut answer = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(text) = line.strip_prefix("Question:") {
+            question = Some(text.trim().to_string());
+        } else if let Some(text) = line.strip_prefix("Answer:") {
+            answer = text.trim().chars().next();
+        } else if let Some(label) = line.chars().next() {
+            // Options are in label order, such as "B) ..." or "B. ..."
+            let rest = &line[label.len_utf8()..];
+            let expected = QUIZ_LABELS.get(options.len());
+            if expected == Some(&label) && rest.starts_with([')', '.', ':']) {
+                options.push(rest[1..].trim().to_string());
+            }
+        }
+    }
+
+    let correct_index = QUIZ_LABELS.iter().position(|label| {
+        Some(label.to_ascii_uppercase()) == answer.map(|a| a.to_ascii_uppercase())
+    })?;
+    let question = QuizQuestion {
+        question: question?,
+        options,
+        correct_index,
+    };
+    question.is_valid().then_some(question)
+}
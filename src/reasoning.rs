@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Marks the start of the model's hidden reasoning
+pub const REASONING_START: &str = "<thinking>";
+/// Marks the end of the model's hidden reasoning
+pub const REASONING_END: &str = "</thinking>";
+/// Maximum number of tokens the model may spend reasoning before it must answer
+pub const MAX_REASONING_TOKENS: usize = 256;
+
+/// The result of a chain-of-thought generation.
+/// `answer` never contains any of the reasoning text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reasoned {
+    /// The step-by-step reasoning the model produced before answering
+    pub reasoning: String,
+    /// The final answer
+    pub answer: String,
+}
+
+impl Model {
+    /// Instruct the model to reason step by step before answering.
+    /// The reasoning is generated inside `REASONING_START` and `REASONING_END`, then stripped
+    /// from the answer and returned separately. `max_tokens` and `stop` in `config` only apply to the answer.
+    pub fn instruct_reasoned(
+        &self,
+        instruction: impl AsRef<str>,
+        extra_information: Option<&HashMap<&str, impl AsRef<str>>>,
+        config: &GenerationConfig,
+    ) -> Result<Reasoned> {
+        // Copy the extra information so the response can be started with the reasoning delimiter
+        let mut extra: HashMap<&str, String> = extra_information
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (*key, value.as_ref().to_string()))
+            .collect();
+        let response_prefix = extra.remove("Response").unwrap_or_default();
+
+        // Ask for the reasoning to be written inside the delimiters before the answer
+        let instruction = format!(
+            "{}\nFirst think step by step inside {} and {}, then write the final answer after \"Answer:\".",
+            instruction.as_ref(),
+            REASONING_START,
+            REASONING_END,
+        );
+
+        // Generate the reasoning until the end delimiter
+        let mut response = format!("{}{}\n", response_prefix, REASONING_START);
+        extra.insert("Response", response.clone());
+        let reasoning_config = GenerationConfig {
+            max_tokens: Some(MAX_REASONING_TOKENS),
+            stop: vec![REASONING_END.to_string()],
+            ..config.clone()
+        };
+        let reasoning = self.instruct_with(&instruction, Some(&extra), &reasoning_config)?;
+
+        // Close the reasoning and generate the answer
+        response.push_str(&reasoning);
+        response.push_str(&format!("\n{}\nAnswer:", REASONING_END));
+        extra.insert("Response", response);
+        let answer = self.instruct_with(&instruction, Some(&extra), config)?;
+
+        Ok(Reasoned {
+            reasoning: reasoning.trim().to_string(),
+            answer: answer.trim().to_string(),
+        })
+    }
+}
+
+/// Maximum number of tokens the model may spend critiquing a draft
+pub const MAX_CRITIQUE_TOKENS: usize = 128;
+
+/// A single draft and the critique the model wrote about it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Revision {
+    pub draft: String,
+    pub critique: String,
+}
+
+/// The result of a self-critique and revise loop
+#[derive(Clone, Debug, PartialEq)]
+pub struct Revised {
+    /// The final revised output
+    pub output: String,
+    /// Every draft that was revised along with its critique, oldest first
+    pub revisions: Vec<Revision>,
+}
+
+impl Model {
+    /// Generate a response to the instruction, then `n_rounds` times ask the model to critique
+    /// its own output against the instruction and regenerate with the critique included.
+    /// The seed in `config` is incremented for every generation.
+    pub fn generate_revised(
+        &self,
+        instruction: impl AsRef<str>,
+        config: &GenerationConfig,
+        n_rounds: usize,
+    ) -> Result<Revised> {
+        let instruction = instruction.as_ref();
+
+        // Generate the first draft
+        let mut output = self.instruct_with(instruction, None::<&HashMap<&str, &str>>, config)?;
+
+        // Critique and regenerate for each round
+        let mut revisions = Vec::with_capacity(n_rounds);
+        for round in 0..n_rounds as u64 {
+            // Ask the model what is wrong with the current draft
+            let mut extra = HashMap::new();
+            extra.insert("Original Instruction", instruction);
+            extra.insert("Draft", output.as_str());
+            let critique_config = GenerationConfig {
+                seed: config.seed.wrapping_add(round * 2 + 1),
+                max_tokens: Some(MAX_CRITIQUE_TOKENS),
+                stop: vec!["###".to_string()],
+                ..config.clone()
+            };
+            let critique = self.instruct_with(
+                "Critique the draft against the original instruction. Briefly list what should be improved.",
+                Some(&extra),
+                &critique_config,
+            )?;
+            let critique = critique.trim().to_string();
+
+            // Regenerate with the draft and critique appended
+            let mut extra = HashMap::new();
+            extra.insert("Previous Draft", output.as_str());
+            extra.insert("Critique", critique.as_str());
+            let revise_config = config.clone().with_seed(config.seed.wrapping_add(round * 2 + 2));
+            let revised = self.instruct_with(
+                format!("{}\nWrite an improved response that addresses the critique.", instruction),
+                Some(&extra),
+                &revise_config,
+            )?;
+
+            revisions.push(Revision {
+                draft: std::mem::replace(&mut output, revised),
+                critique,
+            });
+        }
+
+        Ok(Revised { output, revisions })
+    }
+}
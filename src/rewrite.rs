@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Default maximum number of tokens in a rewritten text
+pub const DEFAULT_REWRITE_TOKENS: usize = 256;
+/// Number of times `rewrite` tries to rewrite the text without changing its meaning
+pub const REWRITE_ATTEMPTS: u64 = 3;
+/// Lowest probability that a rewrite means the same as the original for it to be accepted
+pub const MIN_MEANING_PRESERVED: f32 = 0.5;
+
+/// How formal a rewritten text should be
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Formality {
+    Casual,
+    Neutral,
+    Formal,
+}
+
+/// The voice `Model::rewrite` should rewrite a text in
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StyleSpec {
+    /// The tone of the text, such as "cheerful" or "menacing"
+    pub tone: Option<String>,
+    pub formality: Option<Formality>,
+    /// Who the text should sound like it is written by, such as "a grumpy old dwarf"
+    pub persona: Option<String>,
+    /// Maximum number of tokens in the rewritten text, or `DEFAULT_REWRITE_TOKENS` if None
+    pub max_len: Option<usize>,
+    /// Seed of the first rewrite. A rewrite that changes the meaning is retried with the next seed.
+    pub seed: u64,
+}
+
+impl StyleSpec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tone(mut self, tone: impl Into<Option<String>>) -> Self {
+        self.tone = tone.into();
+        self
+    }
+
+    pub fn with_formality(mut self, formality: impl Into<Option<Formality>>) -> Self {
+        self.formality = formality.into();
+        self
+    }
+
+    pub fn with_persona(mut self, persona: impl Into<Option<String>>) -> Self {
+        self.persona = persona.into();
+        self
+    }
+
+    pub fn with_max_len(mut self, max_len: impl Into<Option<usize>>) -> Self {
+        self.max_len = max_len.into();
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Describe the style as an instruction
+    fn describe(&self) -> String {
+        let mut instruction =
+            "Rewrite the text in a different voice, keeping exactly the same meaning and details."
+                .to_string();
+        if let Some(persona) = &self.persona {
+            instruction.push_str(&format!(" Write it as {} would say it.", persona));
+        }
+        if let Some(tone) = &self.tone {
+            instruction.push_str(&format!(" Use a {} tone.", tone));
+        }
+        match self.formality {
+            Some(Formality::Casual) => instruction.push_str(" Make it casual."),
+            Some(Formality::Neutral) => instruction.push_str(" Keep it neither casual nor formal."),
+            Some(Formality::Formal) => instruction.push_str(" Make it formal."),
+            None => {}
+        }
+        instruction
+    }
+}
+
+impl Model {
+    /// Rewrite the text in the voice described by `style` without changing its meaning.
+    /// Each rewrite is checked by asking the model whether it means the same as the original,
+    /// and rewritten again with the next seed if not.
+    /// Returns an error if the text is empty or every attempt changed the meaning.
+    pub fn rewrite(&self, text: impl AsRef<str>, style: StyleSpec) -> Result<String> {
+        let text = text.as_ref().trim();
+        if text.is_empty() {
+            bail!("cannot rewrite empty text")
+        }
+
+        let instruction = style.describe();
+        let mut extra = HashMap::new();
+        extra.insert("Text", text);
+        for seed in style.seed..style.seed.saturating_add(REWRITE_ATTEMPTS) {
+            // Rewrite the text until the next section or the token limit
+            let config = GenerationConfig::new(seed)
+                .with_temp(0.7)
+                .with_max_tokens(style.max_len.unwrap_or(DEFAULT_REWRITE_TOKENS))
+                .with_stop("###");
+            let rewritten = self.instruct_with(&instruction, Some(&extra), &config)?;
+            let rewritten = rewritten.trim();
+            if rewritten.is_empty() {
+                continue;
+            }
+
+            // Make sure the meaning didn't drift
+            let comparison = format!("Original: {}\nRewritten: {}", text, rewritten);
+            let preserved = self.yes_probability(
+                comparison,
+                "Does the rewritten text contain the same information as the original?",
+            )?;
+            if preserved >= MIN_MEANING_PRESERVED {
+                return Ok(rewritten.to_string());
+            }
+        }
+
+        bail!("every rewrite changed the meaning of the text")
+    }
+}
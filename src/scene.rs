@@ -0,0 +1,2428 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::sync::Arc;
+
+use anyhow::Result;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::cosine_similarity;
+use crate::generation::GenerationConfig;
+use crate::model::{InferValue, Model, MAX_TOKENS};
+use crate::token_string::TokenString;
+
+pub mod clock;
+pub mod constraints;
+pub mod directive;
+pub mod export;
+pub mod facts;
+pub mod format;
+pub mod genre;
+pub mod memory;
+pub mod relationships;
+pub mod scheduler;
+pub mod state;
+pub mod style;
+
+pub use clock::{SceneClock, TimeOfDay};
+pub use constraints::{ContentConstraints, Rating};
+pub use directive::Directive;
+pub use export::ExportFormat;
+pub use facts::Fact;
+pub use format::SceneFormat;
+pub use genre::Genre;
+pub use memory::{KeepRecent, MemoryPolicy, ParaphraseAll, Recall};
+pub use relationships::{Relationship, Relationships};
+pub use scheduler::{
+    Initiative, LeastRecent, RoundRobin, SpeakerSelection, TurnScheduler, WeightedByMentions,
+};
+pub use state::WorldState;
+pub use style::{NarrationStyle, Pacing, PointOfView, Tense, Verbosity};
+
+/// Default number of memory tokens a scene may hold before it is compressed
+pub const DEFAULT_MAX_MEMORY: usize = 1024;
+/// Maximum number of tokens spent listing important facts during compression
+pub const MAX_FACT_TOKENS: usize = 128;
+/// Maximum number of important facts a scene keeps pinned; the oldest are forgotten first
+pub const MAX_PINNED_FACTS: usize = 32;
+/// Recalled turns take at most 1/`MAX_RECALL_FRACTION` of the maximum memory of the scene
+pub const MAX_RECALL_FRACTION: usize = 4;
+/// Maximum number of tokens spent listing the structured facts of a single turn
+pub const MAX_TRIPLE_TOKENS: usize = 96;
+/// Maximum number of tokens spent rewriting the world state after a turn
+pub const MAX_STATE_TOKENS: usize = 128;
+/// Maximum number of tokens spent listing relationship changes after a turn
+pub const MAX_RELATIONSHIP_TOKENS: usize = 96;
+/// Maximum number of tokens in a recap inserted automatically
+pub const MAX_RECAP_TOKENS: usize = 96;
+/// Number of lines generated per requested suggestion before `suggest_dialogue` gives up on finding distinct ones
+pub const MAX_SUGGESTION_ATTEMPTS: usize = 3;
+/// Text of a turn that kept breaking the content constraints
+pub const CONSTRAINED_FALLBACK: &str = "...";
+/// Emotions generated dialogue is tagged with when emotion tagging is enabled without custom labels
+pub const DEFAULT_EMOTIONS: &[&str] = &[
+    "neutral",
+    "joyful",
+    "angry",
+    "afraid",
+    "sad",
+    "surprised",
+    "disgusted",
+];
+
+/// A scene in which characters talk and a story unfolds.
+/// The scene remembers what happened in two parts: the long-term memory holds the setting
+/// and a compressed account of older events, and the short-term memory holds the latest turns verbatim.
+#[derive(Clone)]
+pub struct Scene {
+    model: Model,
+    setting: String,
+    characters: Vec<Character>,
+    /// Characters who left the scene for good, with the reason they left
+    retired: Vec<RetiredCharacter>,
+    /// How turns are written in the memory
+    format: SceneFormat,
+    narration_style: Option<NarrationStyle>,
+    /// The named style of the scene, described in the preamble
+    genre: Option<Genre>,
+    /// How much tension story turns should have, or `None` to leave it to the model
+    pacing: Option<Pacing>,
+    /// Number of inferred turns after which the pacing advances to the next stage, if it advances on its own
+    pacing_schedule: Option<usize>,
+    /// Number of turns inferred since the pacing last changed
+    inferred_since_pacing: usize,
+    /// Number of turns between automatic recaps, if recaps are inserted automatically
+    recap_every: Option<usize>,
+    /// Maximum number of tokens in the introduction generated for characters that join, if they are introduced
+    introduce_characters: Option<usize>,
+    long_term_memory: TokenString,
+    /// Compressed account of older events, kept in the long-term memory after the preamble and facts
+    summary: TokenString,
+    /// Important facts that are kept in the long-term memory and never compressed
+    facts: Vec<String>,
+    /// Whether important facts are extracted from events before they are compressed
+    extract_facts: bool,
+    /// Vector memory of compressed turns that are recalled when relevant, shared with checkpoints until it changes
+    recall: Option<Arc<Recall>>,
+    /// Typed facts about the world, shown to the model before every inference
+    state: WorldState,
+    /// In-world time, shown to the model before every inference
+    clock: Option<SceneClock>,
+    /// Whether the world state is updated from every inferred turn
+    auto_update_state: bool,
+    /// How the characters feel about each other, shown to the model before every inference
+    relationships: Relationships,
+    /// Whether the relationships are updated from every inferred turn by a character
+    track_relationships: bool,
+    /// Emotions inferred dialogue is tagged with, or `None` if emotion tagging is disabled
+    emotion_labels: Option<Vec<String>>,
+    /// Goals each character has achieved, oldest first
+    achieved_goals: BTreeMap<String, Vec<String>>,
+    /// Limits on what inferred turns may contain
+    constraints: ContentConstraints,
+    /// Instructions from the director that steer inference until they are fulfilled
+    directives: Vec<Directive>,
+    /// Whether the scene was concluded with an epilogue
+    closed: bool,
+    short_term_memory: TokenString,
+    /// Number of tokens of each turn in the short-term memory, oldest first
+    short_term_turns: Vec<usize>,
+    /// Every turn of the scene, oldest first
+    /// Every turn, shared with checkpoints until a turn is added
+    turns: Arc<Vec<SceneTurn>>,
+    /// Structured facts already extracted from each turn, by the turn's index
+    extracted_facts: BTreeMap<usize, Vec<Fact>>,
+    max_memory: usize,
+    memory_policy: Arc<dyn MemoryPolicy>,
+    config: GenerationConfig,
+    turn_weights: TurnWeights,
+    turn_scheduler: Arc<dyn TurnScheduler>,
+    last_speaker: Option<String>,
+}
+
+/// How often `Scene::infer_any` generates dialogue compared to narration
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TurnWeights {
+    pub dialogue: u64,
+    pub story: u64,
+}
+
+impl Default for TurnWeights {
+    /// Three dialogue turns for every two narration turns
+    fn default() -> Self {
+        Self {
+            dialogue: 3,
+            story: 2,
+        }
+    }
+}
+
+impl Scene {
+    /// Create a scene with the default memory settings
+    pub(crate) fn new(
+        model: Model,
+        setting: impl Into<String>,
+        characters: Vec<Character>,
+        config: GenerationConfig,
+    ) -> Self {
+        let long_term_memory = model.new_token_string();
+        let summary = model.new_token_string();
+        let short_term_memory = model.new_token_string();
+
+        let mut scene = Self {
+            model,
+            setting: setting.into(),
+            characters,
+            retired: Vec::new(),
+            format: SceneFormat::default(),
+            narration_style: None,
+            genre: None,
+            pacing: None,
+            pacing_schedule: None,
+            inferred_since_pacing: 0,
+            recap_every: None,
+            introduce_characters: None,
+            long_term_memory,
+            summary,
+            facts: Vec::new(),
+            extract_facts: true,
+            recall: None,
+            state: WorldState::new(),
+            clock: None,
+            auto_update_state: false,
+            relationships: Relationships::new(),
+            track_relationships: false,
+            emotion_labels: None,
+            achieved_goals: BTreeMap::new(),
+            constraints: ContentConstraints::default(),
+            directives: Vec::new(),
+            closed: false,
+            short_term_memory,
+            short_term_turns: Vec::new(),
+            turns: Arc::new(Vec::new()),
+            extracted_facts: BTreeMap::new(),
+            max_memory: DEFAULT_MAX_MEMORY,
+            memory_policy: Arc::new(ParaphraseAll),
+            config,
+            turn_weights: TurnWeights::default(),
+            turn_scheduler: Arc::new(RoundRobin),
+            last_speaker: None,
+        };
+
+        // Start the long-term memory with a description of the scene
+        scene.rebuild_long_term_memory();
+        scene
+    }
+
+    /// Start building a new scene
+    pub fn builder(model: Model, setting: impl Into<String>) -> SceneBuilder {
+        SceneBuilder::new(model, setting)
+    }
+
+    /// Create the text that the long-term memory starts with
+    fn preamble(&self) -> String {
+        let mut preamble = format!(
+            "The following is a story. {}\nSetting: {}\nCharacters: {}\n",
+            self.format.description,
+            self.setting,
+            self.characters
+                .iter()
+                .map(|character| &character.name)
+                .join(", "),
+        );
+        if let Some(genre) = &self.genre {
+            preamble.push_str(&format!("Genre: {}. {}\n", genre.name, genre.description));
+        }
+        if let Some(narration_style) = &self.narration_style {
+            preamble.push_str(&format!("Narration style: {}\n", narration_style));
+        }
+        if let Some(rules) = self.constraints.describe() {
+            preamble.push_str(&format!("Content rules: {}\n", rules));
+        }
+        for retired in &self.retired {
+            preamble.push_str(&format!(
+                "{} has left the story for good ({}) and can never appear or speak again.\n",
+                retired.character.name, retired.reason
+            ));
+        }
+        preamble
+    }
+
+    /// Get how turns are written in the memory
+    pub fn format(&self) -> &SceneFormat {
+        &self.format
+    }
+
+    /// Get the style narration is written in, if one was set
+    pub fn narration_style(&self) -> Option<&NarrationStyle> {
+        self.narration_style.as_ref()
+    }
+
+    /// Change the style narration is written in
+    pub fn set_narration_style(&mut self, narration_style: Option<NarrationStyle>) {
+        self.narration_style = narration_style;
+        self.rebuild_long_term_memory();
+    }
+
+    /// Get the named style of the scene, if one was chosen
+    pub fn genre(&self) -> Option<&Genre> {
+        self.genre.as_ref()
+    }
+
+    /// Get how much tension story turns should have, if it is being steered
+    pub fn pacing(&self) -> Option<Pacing> {
+        self.pacing
+    }
+
+    pub fn set_pacing(&mut self, pacing: Option<Pacing>) {
+        self.pacing = pacing;
+        self.inferred_since_pacing = 0;
+    }
+
+    /// Get the number of inferred turns after which the pacing advances on its own, if it does
+    pub fn pacing_schedule(&self) -> Option<usize> {
+        self.pacing_schedule
+    }
+
+    pub fn set_pacing_schedule(&mut self, pacing_schedule: Option<usize>) {
+        self.pacing_schedule = pacing_schedule;
+    }
+
+    /// Advance the pacing to its next stage, starting at calm if it wasn't set, and return the new stage
+    pub fn advance_pacing(&mut self) -> Pacing {
+        let pacing = self.pacing.map_or(Pacing::Calm, Pacing::next);
+        self.set_pacing(Some(pacing));
+        pacing
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    pub fn setting(&self) -> &str {
+        &self.setting
+    }
+
+    pub fn characters(&self) -> &[Character] {
+        &self.characters
+    }
+
+    /// Get a character in the scene by name
+    pub fn character(&self, name: impl AsRef<str>) -> Option<&Character> {
+        self.characters.iter().find(|character| character.name == name.as_ref())
+    }
+
+    pub fn long_term_memory(&self) -> &TokenString {
+        &self.long_term_memory
+    }
+
+    pub fn short_term_memory(&self) -> &TokenString {
+        &self.short_term_memory
+    }
+
+    /// Get the important facts that are protected from memory compression
+    pub fn facts(&self) -> &[String] {
+        &self.facts
+    }
+
+    /// Add an important fact that is kept in the long-term memory and never compressed.
+    /// At most `MAX_PINNED_FACTS` facts are kept, and only as many as fit in the memory budget
+    /// beside the preamble and summary; the oldest facts are forgotten first.
+    pub fn pin_fact(&mut self, fact: impl AsRef<str>) {
+        if self.add_fact(fact.as_ref()) {
+            self.rebuild_long_term_memory();
+        }
+    }
+
+    /// Add a fact unless it is empty or already pinned, forgetting the oldest facts over the limit.
+    /// Returns `true` if the fact was added.
+    fn add_fact(&mut self, fact: &str) -> bool {
+        let fact = fact.trim();
+        if fact.is_empty() || self.has_fact(fact) {
+            return false;
+        }
+        self.facts.push(fact.to_string());
+        if self.facts.len() > MAX_PINNED_FACTS {
+            self.facts.drain(..self.facts.len() - MAX_PINNED_FACTS);
+        }
+        true
+    }
+
+    /// Remove a pinned fact. Returns `true` if the fact was pinned.
+    pub fn unpin_fact(&mut self, fact: impl AsRef<str>) -> bool {
+        let count = self.facts.len();
+        let fact = normalize_fact(fact.as_ref());
+        self.facts.retain(|pinned| normalize_fact(pinned) != fact);
+        let removed = self.facts.len() != count;
+        if removed {
+            self.rebuild_long_term_memory();
+        }
+        removed
+    }
+
+    /// Check if a fact is already pinned, ignoring case, spacing and trailing punctuation
+    fn has_fact(&self, fact: &str) -> bool {
+        let fact = normalize_fact(fact);
+        self.facts.iter().any(|pinned| normalize_fact(pinned) == fact)
+    }
+
+    /// Get the vector memory of compressed turns, if recall is enabled
+    pub fn recall(&self) -> Option<&Recall> {
+        self.recall.as_deref()
+    }
+
+    /// Enable or disable recalling compressed turns.
+    /// When enabled, the `top_k` most relevant compressed turns are added to the prompt before every inference.
+    pub fn set_recall(&mut self, top_k: Option<usize>) {
+        match (top_k, &mut self.recall) {
+            (Some(top_k), Some(recall)) => Arc::make_mut(recall).top_k = top_k,
+            (Some(top_k), None) => self.recall = Some(Arc::new(Recall::new(top_k))),
+            (None, _) => self.recall = None,
+        }
+    }
+
+    /// Get the world state of the scene
+    pub fn state(&self) -> &WorldState {
+        &self.state
+    }
+
+    /// Get the world state of the scene for modification
+    pub fn state_mut(&mut self) -> &mut WorldState {
+        &mut self.state
+    }
+
+    /// Whether the world state is updated from every inferred turn
+    pub fn auto_updates_state(&self) -> bool {
+        self.auto_update_state
+    }
+
+    pub fn set_auto_update_state(&mut self, auto_update_state: bool) {
+        self.auto_update_state = auto_update_state;
+    }
+
+    /// Ask the model how the turn changed the world state and apply the changes.
+    /// Only keys that are already in the state are updated. Returns the keys whose values changed.
+    pub fn update_state_from(&mut self, turn: &SceneTurn) -> Result<Vec<String>> {
+        if self.state.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Give the model the current state and the turn
+        let lines = self.state.to_lines();
+        let turn_text = self.format.render(turn);
+        let mut extra = HashMap::new();
+        extra.insert("World State", lines.as_str());
+        extra.insert("Latest Event", turn_text.as_str());
+
+        // Ask for the updated state in the same format
+        let config = GenerationConfig::new(self.next_seed())
+            .with_temp(0.1)
+            .with_max_tokens(MAX_STATE_TOKENS)
+            .with_stop("###");
+        let updated = self
+            .model
+            .instruct_with(
+                "Rewrite the world state so it is true after the latest event. Keep the same keys and write one key: value per line.",
+                Some(&extra),
+                &config,
+            )?;
+
+        Ok(self.state.apply_lines(updated, true))
+    }
+
+    /// Get how the characters feel about each other
+    pub fn relationships(&self) -> &Relationships {
+        &self.relationships
+    }
+
+    /// Get mutable access to the relationships, such as to set how characters feel about each other when the scene starts
+    pub fn relationships_mut(&mut self) -> &mut Relationships {
+        &mut self.relationships
+    }
+
+    /// Whether the relationships are updated from every inferred turn by a character
+    pub fn tracks_relationships(&self) -> bool {
+        self.track_relationships
+    }
+
+    pub fn set_track_relationships(&mut self, track_relationships: bool) {
+        self.track_relationships = track_relationships;
+    }
+
+    /// Ask the model how a turn changed the way the characters feel about each other and apply the changes.
+    /// Returns the `(from, to)` pairs whose relationship changed.
+    pub fn update_relationships_from(&mut self, turn: &SceneTurn) -> Result<Vec<(String, String)>> {
+        if self.characters.len() < 2 {
+            return Ok(Vec::new());
+        }
+
+        // Give the model the cast, the current relationships and the turn
+        let names: Vec<String> = self
+            .characters
+            .iter()
+            .map(|character| character.name.clone())
+            .collect();
+        let cast = names.join(", ");
+        let mut current = self.relationships.to_lines();
+        if current.is_empty() {
+            current = "Nobody has strong feelings yet.".to_string();
+        }
+        let turn_text = self.format.render(turn);
+        let mut extra = HashMap::new();
+        extra.insert("Characters", cast.as_str());
+        extra.insert("Relationships", current.as_str());
+        extra.insert("Latest Event", turn_text.as_str());
+
+        // Ask for the changes as one line per pair
+        let config = GenerationConfig::new(self.next_seed())
+            .with_temp(0.1)
+            .with_max_tokens(MAX_RELATIONSHIP_TOKENS)
+            .with_stop("###");
+        let changes = self
+            .model
+            .instruct_with(
+                "List how the latest event changed the way characters feel about each other. Write one line per change as From -> To: affinity <change>, trust <change>, with changes between -1 and 1. Write nothing if nothing changed.",
+                Some(&extra),
+                &config,
+            )?;
+
+        Ok(self.relationships.apply_lines(changes, &names))
+    }
+
+    /// Move the scene to a new setting, such as from the tavern to the forest, keeping the characters and their history.
+    /// When `compress_previous` is set, every turn so far is compressed into the long-term memory
+    /// so details of the old location don't crowd the new one.
+    /// Returns the generated narration of the transition, at most `max_tokens` tokens long.
+    pub fn change_setting(
+        &mut self,
+        new_setting: impl Into<String>,
+        compress_previous: bool,
+        max_tokens: usize,
+    ) -> Result<SceneTurn> {
+        let previous_setting = std::mem::replace(&mut self.setting, new_setting.into());
+
+        // Put what happened in the old location behind the new preamble
+        if compress_previous {
+            self.compress_all_but(0)?;
+        }
+        self.rebuild_long_term_memory();
+
+        // Narrate the move
+        let direction = format!(
+            "(The story moves from {} to {}. Describe the characters arriving.)\n",
+            previous_setting.trim_end_matches('.'),
+            self.setting.trim_end_matches('.')
+        );
+        self.infer_story_directed(Some(&direction), max_tokens)
+    }
+
+    /// Get the emotions inferred dialogue is tagged with, or `None` if emotion tagging is disabled
+    pub fn emotion_labels(&self) -> Option<&[String]> {
+        self.emotion_labels.as_deref()
+    }
+
+    /// Enable emotion tagging of inferred dialogue with the given emotions, or disable it with `None`
+    pub fn set_emotion_labels(&mut self, labels: Option<Vec<String>>) {
+        self.emotion_labels = labels;
+    }
+
+    /// Classify the emotion a turn expresses as one of the emotion labels of the scene,
+    /// or the default emotions if tagging is disabled.
+    /// Returns `None` if the model could not decide.
+    pub fn classify_emotion(&mut self, turn: &SceneTurn) -> Option<String> {
+        let labels: Vec<String> = match &self.emotion_labels {
+            Some(labels) => labels.clone(),
+            None => DEFAULT_EMOTIONS
+                .iter()
+                .map(|label| label.to_string())
+                .collect(),
+        };
+
+        let seed = self.next_seed();
+        self.model.try_choose_item(
+            self.format.render(turn),
+            "The emotion the speaker feels while saying this",
+            labels,
+            seed,
+            3,
+        )
+    }
+
+    /// Called with every turn generated by the model after it has been pushed
+    fn on_inferred_turn(&mut self, turn: &mut SceneTurn) -> Result<()> {
+        // Tag dialogue with the emotion it expresses
+        if self.emotion_labels.is_some() && matches!(turn.turn_type, SceneTurnType::Dialogue(..)) {
+            turn.emotion = self.classify_emotion(turn);
+            if let Some(last) = Arc::make_mut(&mut self.turns).last_mut() {
+                last.emotion = turn.emotion.clone();
+            }
+        }
+
+        if self.auto_update_state {
+            self.update_state_from(turn)?;
+        }
+        if self.track_relationships && turn.character().is_some() {
+            self.update_relationships_from(turn)?;
+        }
+        if !self.directives.is_empty() {
+            self.update_directives(turn);
+        }
+
+        // Recap what happened since the last recap on schedule
+        if let Some(recap_every) = self.recap_every {
+            let since_recap = self
+                .turns
+                .iter()
+                .rev()
+                .take_while(|turn| !matches!(turn.turn_type, SceneTurnType::Recap(_)))
+                .count();
+            if since_recap >= recap_every.max(1) {
+                self.recap(MAX_RECAP_TOKENS)?;
+            }
+        }
+
+        // Move the story along its arc on schedule, counting only inferred turns
+        self.inferred_since_pacing += 1;
+        if let Some(schedule) = self.pacing_schedule {
+            if self.inferred_since_pacing >= schedule.max(1) {
+                self.advance_pacing();
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether important facts are extracted from events before they are compressed
+    pub fn extracts_facts(&self) -> bool {
+        self.extract_facts
+    }
+
+    pub fn set_extract_facts(&mut self, extract_facts: bool) {
+        self.extract_facts = extract_facts;
+    }
+
+    pub fn max_memory(&self) -> usize {
+        self.max_memory
+    }
+
+    /// Get the policy that decides when and how the memory is compressed
+    pub fn memory_policy(&self) -> &dyn MemoryPolicy {
+        self.memory_policy.as_ref()
+    }
+
+    /// Change the policy that decides when and how the memory is compressed
+    pub fn set_memory_policy(&mut self, memory_policy: impl MemoryPolicy + 'static) {
+        self.memory_policy = Arc::new(memory_policy);
+    }
+
+    /// Get the default generation parameters of the scene
+    pub fn config(&self) -> &GenerationConfig {
+        &self.config
+    }
+
+    /// Get the name of the character who spoke last, if any
+    pub fn last_speaker(&self) -> Option<&str> {
+        self.last_speaker.as_deref()
+    }
+
+    /// Get the seed to use for the next inference and advance the scene seed
+    fn next_seed(&mut self) -> u64 {
+        let seed = self.config.seed;
+        self.config.seed = self.config.seed.wrapping_add(1);
+        seed
+    }
+
+    /// Get the full memory of the scene as a prompt
+    fn prompt(&self) -> Result<TokenString> {
+        let mut prompt = self.long_term_memory.clone();
+
+        // Show the model the current state of the world
+        if !self.state.is_empty() {
+            prompt.push_str(format!("World state:\n{}", self.state.to_lines()));
+        }
+
+        // Keep the narrative consistent with the in-world time
+        if let Some(clock) = &self.clock {
+            prompt.push_str(format!("Time: {}\n", clock));
+        }
+
+        // Remind the model how the characters feel about each other
+        let relationships = self.relationships.to_lines();
+        if !relationships.is_empty() {
+            prompt.push_str(format!("Relationships:\n{}", relationships));
+        }
+
+        // Bring back the compressed turns most relevant to what is happening now
+        if let Some(recall) = &self.recall {
+            let max_tokens = self.max_memory / MAX_RECALL_FRACTION;
+            let recalled = recall.recall(&self.model, &self.short_term_memory, max_tokens)?;
+            if !recalled.is_empty() {
+                prompt.push_str(format!("Earlier in the story:\n{}", recalled.join("")));
+            }
+        }
+
+        prompt.push(&self.short_term_memory);
+
+        // Pass on the director's instructions right before the new turn
+        for directive in &self.directives {
+            prompt.push_str(format!(
+                "(Director: {}.)\n",
+                directive.instruction.trim_end_matches('.')
+            ));
+        }
+
+        Ok(prompt)
+    }
+
+    /// Register a transient instruction, such as "introduce a sudden storm", that steers every inferred turn
+    /// until the model judges it fulfilled. The instruction never enters the memory.
+    pub fn direct(&mut self, instruction: impl Into<String>) {
+        self.directives.push(Directive::new(instruction));
+    }
+
+    /// Like `Scene::direct`, but the instruction expires after `turns` inferred turns even if it wasn't fulfilled
+    pub fn direct_within(&mut self, instruction: impl Into<String>, turns: usize) {
+        self.directives
+            .push(Directive::new(instruction).within(turns));
+    }
+
+    /// Get the director's instructions that are still pending
+    pub fn directives(&self) -> &[Directive] {
+        &self.directives
+    }
+
+    pub fn clear_directives(&mut self) {
+        self.directives.clear();
+    }
+
+    /// Drop the directives the turn fulfilled and count down the ones with a deadline
+    fn update_directives(&mut self, turn: &SceneTurn) {
+        let directives = std::mem::take(&mut self.directives);
+        for mut directive in directives {
+            let question = format!(
+                "The answer to: Does this text carry out the instruction \"{}\"?",
+                directive.instruction
+            );
+            let seed = self.next_seed();
+            let fulfilled = self
+                .model
+                .try_choose_item(self.format.render(turn), question, ["yes", "no"], seed, 3)
+                .as_deref()
+                == Some("yes");
+            if fulfilled {
+                continue;
+            }
+
+            // Expire directives that ran out of turns
+            if let Some(turns_left) = &mut directive.turns_left {
+                *turns_left = turns_left.saturating_sub(1);
+                if *turns_left == 0 {
+                    continue;
+                }
+            }
+            self.directives.push(directive);
+        }
+    }
+
+    /// Get the limits on what inferred turns may contain
+    pub fn constraints(&self) -> &ContentConstraints {
+        &self.constraints
+    }
+
+    pub fn set_constraints(&mut self, constraints: ContentConstraints) {
+        self.constraints = constraints;
+        self.rebuild_long_term_memory();
+    }
+
+    /// Check text against the content constraints of the scene.
+    /// Returns a description of the first constraint it breaks, or `None` if it breaks none.
+    pub fn check_constraints(&mut self, text: impl AsRef<str>) -> Option<String> {
+        let text = text.as_ref();
+
+        // Banned words are found without the model
+        if let Some(word) = self.constraints.banned_words_in(text).first() {
+            return Some(format!("uses the banned word {:?}", word));
+        }
+
+        // Ask the model whether the text strays into banned topics or past the rating
+        let mut rules = Vec::new();
+        if !self.constraints.banned_topics.is_empty() {
+            rules.push(format!(
+                "mention {}",
+                self.constraints.banned_topics.iter().join(", ")
+            ));
+        }
+        if let Some(rating) = self.constraints.rating {
+            rules.push(format!("contain anything that isn't {}", rating));
+        }
+        if rules.is_empty() {
+            return None;
+        }
+        let question = format!("The answer to: Does this text {}?", rules.join(" or "));
+        let seed = self.next_seed();
+        match self
+            .model
+            .try_choose_item(text, question, ["yes", "no"], seed, 3)
+            .as_deref()
+        {
+            Some("yes") => Some(format!("may {}", rules.join(" or "))),
+            _ => None,
+        }
+    }
+
+    /// Generate the text of a turn, generating it again with a new seed while it breaks the content constraints.
+    /// Falls back to `CONSTRAINED_FALLBACK` if every attempt breaks them.
+    fn generate_turn_text(
+        &mut self,
+        prompt: TokenString,
+        mut config: GenerationConfig,
+    ) -> Result<String> {
+        // End the turn on the stop strings of the genre too
+        if let Some(genre) = &self.genre {
+            config.stop.extend(genre.stop.iter().cloned());
+        }
+
+        if self.constraints.is_empty() {
+            return self.model.generate(prompt, &config);
+        }
+
+        // Make banned words impossible where possible and keep the line short enough
+        config
+            .banned_tokens
+            .extend(self.constraints.banned_tokens(&self.model));
+        if let Some(max_line_tokens) = self.constraints.max_line_tokens {
+            config.max_tokens = Some(config.max_tokens.map_or(max_line_tokens, |max_tokens| {
+                max_tokens.min(max_line_tokens)
+            }));
+        }
+
+        // Keep generating until a line passes the checks
+        for attempt in 0..self.constraints.max_attempts.max(1) {
+            if attempt > 0 {
+                config.seed = self.next_seed();
+            }
+            let text = self.model.generate(prompt.clone(), &config)?;
+            if self.check_constraints(&text).is_none() {
+                return Ok(text);
+            }
+        }
+
+        Ok(CONSTRAINED_FALLBACK.to_string())
+    }
+
+    /// Add a turn to the short-term memory
+    fn push_turn(&mut self, turn: &SceneTurn) {
+        // Let in-world time pass
+        if let Some(clock) = &mut self.clock {
+            if let Some(minutes) = clock.minutes_per_turn {
+                clock.advance(minutes);
+            }
+        }
+
+        let tokens = self.model.tokenize(self.format.render(turn));
+        self.short_term_turns.push(tokens.len());
+        self.short_term_memory.push(tokens);
+        Arc::make_mut(&mut self.turns).push(turn.clone());
+    }
+
+    /// Get every turn of the scene, oldest first
+    pub fn turns(&self) -> &[SceneTurn] {
+        &self.turns
+    }
+
+    /// Get the last `n` turns of the scene, oldest first
+    pub fn last_n(&self, n: usize) -> &[SceneTurn] {
+        &self.turns[self.turns.len().saturating_sub(n)..]
+    }
+
+    /// Get an iterator over the turns spoken or acted by a character, oldest first
+    pub fn turns_by<'a>(&'a self, character: &'a str) -> impl Iterator<Item = &'a SceneTurn> + 'a {
+        self.turns
+            .iter()
+            .filter(move |turn| turn.character() == Some(character))
+    }
+
+    /// Push a line of narration to the scene.
+    /// If the scene has a clock, `{day}`, `{time}` and `{time_of_day}` in the text are replaced with the current time.
+    pub fn push_story(&mut self, text: impl AsRef<str>) -> SceneTurn {
+        let text = match &self.clock {
+            Some(clock) => clock.format(text),
+            None => text.as_ref().to_string(),
+        };
+        let turn = SceneTurn::new(SceneTurnType::Story(text.trim().to_string()));
+        self.push_turn(&turn);
+        turn
+    }
+
+    /// Push a line of dialogue spoken by a character to the scene
+    pub fn push_dialogue(&mut self, character: impl AsRef<str>, text: impl AsRef<str>) -> SceneTurn {
+        let character = character.as_ref().to_string();
+        let turn = SceneTurn::new(SceneTurnType::Dialogue(
+            character.clone(),
+            text.as_ref().trim().to_string(),
+        ));
+        self.push_turn(&turn);
+        self.last_speaker = Some(character);
+        turn
+    }
+
+    /// Generate a "previously on" recap of at most `max_tokens` tokens from the short-term memory and add it to the turns.
+    /// Recaps are kept in the turn history but not in the memory, since they only repeat what the memory already holds.
+    pub fn recap(&mut self, max_tokens: usize) -> Result<SceneTurn> {
+        let seed = self.next_seed();
+        let recap = self.short_term_memory.shortened(max_tokens, seed);
+        let turn = SceneTurn::new(SceneTurnType::Recap(recap.to_string().trim().to_string()));
+        Arc::make_mut(&mut self.turns).push(turn.clone());
+        Ok(turn)
+    }
+
+    /// Add a character to the scene, replacing any character with the same name.
+    /// If the scene introduces characters, an introduction is generated and returned.
+    pub fn add_character(&mut self, character: impl Into<Character>) -> Result<Option<SceneTurn>> {
+        let character = character.into();
+        let name = character.name.clone();
+        match self
+            .characters
+            .iter_mut()
+            .find(|other| other.name == character.name)
+        {
+            Some(existing) => *existing = character,
+            None => self.characters.push(character),
+        }
+        self.rebuild_long_term_memory();
+
+        match self.introduce_characters {
+            Some(max_tokens) => self.introduce(name, max_tokens).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove a character from the scene for good, such as when they die or leave town.
+    /// The departure is narrated in at most `max_tokens` tokens, the character never speaks again,
+    /// and the reason is kept in the preamble so neither compression nor forgotten facts can bring them back.
+    /// Returns an error if the character isn't in the scene.
+    pub fn retire_character(
+        &mut self,
+        name: impl AsRef<str>,
+        reason: impl AsRef<str>,
+        max_tokens: usize,
+    ) -> Result<SceneTurn> {
+        let (name, reason) = (name.as_ref(), reason.as_ref().trim().trim_end_matches('.'));
+        let Some(position) = self
+            .characters
+            .iter()
+            .position(|character| character.name == name)
+        else {
+            anyhow::bail!("character {:?} is not in the scene", name)
+        };
+
+        // Narrate the departure while the character is still listed
+        let direction = format!("(Narrate {} leaving the story: {}.)\n", name, reason);
+        let turn = self.infer_story_directed(Some(&direction), max_tokens)?;
+
+        // Take the character out of the speaker pool for good
+        let character = self.characters.remove(position);
+        if self.last_speaker.as_deref() == Some(name) {
+            self.last_speaker = None;
+        }
+        self.retired.push(RetiredCharacter {
+            character,
+            reason: reason.to_string(),
+        });
+        self.rebuild_long_term_memory();
+
+        Ok(turn)
+    }
+
+    /// Get the characters who left the scene for good, in the order they left
+    pub fn retired_characters(&self) -> &[RetiredCharacter] {
+        &self.retired
+    }
+
+    /// Generate an introduction of at most `max_tokens` tokens for a character, consistent with the setting
+    /// and the character's profile, and push it to the scene as narration
+    pub fn introduce(&mut self, character: impl AsRef<str>, max_tokens: usize) -> Result<SceneTurn> {
+        let character = character.as_ref();
+        let mut direction = format!("(Introduce {} as they appear in the scene.", character);
+        if let Some(profile) = self.character(character).and_then(Character::profile) {
+            direction.push_str(&format!(" {}", profile));
+        }
+        direction.push_str(")\n");
+
+        self.infer_story_directed(Some(&direction), max_tokens)
+    }
+
+    /// Get the maximum number of tokens in introductions generated for characters that join, if they are introduced
+    pub fn introduces_characters(&self) -> Option<usize> {
+        self.introduce_characters
+    }
+
+    pub fn set_introduce_characters(&mut self, max_tokens: Option<usize>) {
+        self.introduce_characters = max_tokens;
+    }
+
+    /// Get the in-world time of the scene, if it has a clock
+    pub fn clock(&self) -> Option<&SceneClock> {
+        self.clock.as_ref()
+    }
+
+    /// Get mutable access to the clock, such as to advance it when the game's own time passes
+    pub fn clock_mut(&mut self) -> Option<&mut SceneClock> {
+        self.clock.as_mut()
+    }
+
+    pub fn set_clock(&mut self, clock: Option<SceneClock>) {
+        self.clock = clock;
+    }
+
+    /// Get the number of turns between automatic recaps, if they are inserted automatically
+    pub fn recap_every(&self) -> Option<usize> {
+        self.recap_every
+    }
+
+    pub fn set_recap_every(&mut self, recap_every: Option<usize>) {
+        self.recap_every = recap_every;
+    }
+
+    /// Push a physical action performed by a character to the scene, such as "draws their sword"
+    pub fn push_action(
+        &mut self,
+        character: impl AsRef<str>,
+        action: impl AsRef<str>,
+    ) -> SceneTurn {
+        let turn = SceneTurn::new(SceneTurnType::Action(
+            character.as_ref().to_string(),
+            action.as_ref().trim().to_string(),
+        ));
+        self.push_turn(&turn);
+        turn
+    }
+
+    /// Push an action taken by the player to the scene
+    pub fn push_player_action(&mut self, text: impl AsRef<str>) -> SceneTurn {
+        let turn = SceneTurn::new(SceneTurnType::Player(text.as_ref().trim().to_string()));
+        self.push_turn(&turn);
+        turn
+    }
+
+    /// Generate a line of narration of at most `max_tokens` tokens describing the consequences
+    /// of the player's last action and push it to the scene
+    pub fn respond_to_player(&mut self, max_tokens: usize) -> Result<SceneTurn> {
+        // Point the model at the action it should respond to
+        let direction = self
+            .turns
+            .iter()
+            .rev()
+            .find(|turn| matches!(turn.turn_type, SceneTurnType::Player(_)))
+            .map(|turn| {
+                format!(
+                    "(Describe what happens as a result of the player's action: {}.)\n",
+                    turn.text()
+                )
+            });
+
+        self.infer_story_directed(direction.as_deref(), max_tokens)
+    }
+
+    /// Generate a line of narration of at most `max_tokens` tokens and push it to the scene
+    pub fn infer_story(&mut self, max_tokens: usize) -> Result<SceneTurn> {
+        self.infer_story_directed(None, max_tokens)
+    }
+
+    /// Generate a line of narration, optionally preceded by a direction that never enters the memory
+    fn infer_story_directed(
+        &mut self,
+        direction: Option<&str>,
+        max_tokens: usize,
+    ) -> Result<SceneTurn> {
+        self.ensure_open()?;
+
+        // Make room for the new turn
+        self.compress_memory()?;
+
+        // Remind the model of the narration style
+        let mut prompt = self.prompt()?;
+        if let Some(narration_style) = &self.narration_style {
+            prompt.push_str(format!("(Narrate in {}.)\n", narration_style));
+        }
+
+        // Steer the tension of the story
+        if let Some(pacing) = self.pacing {
+            prompt.push_str(format!("({}.)\n", pacing));
+        }
+
+        // Tell the model what the narration should cover
+        if let Some(direction) = direction {
+            prompt.push_str(direction);
+        }
+
+        // Start the narration with its opening delimiter
+        prompt.push_str(self.format.narration_prefix());
+
+        // Generate until the closing delimiter
+        let config = GenerationConfig {
+            seed: self.next_seed(),
+            max_tokens: Some(max_tokens),
+            stop: self.format.stops(&self.format.narration_close),
+            ..self.config.clone()
+        };
+        let text = self.generate_turn_text(prompt, config)?;
+
+        let mut turn = self.push_story(text);
+        self.on_inferred_turn(&mut turn)?;
+        Ok(turn)
+    }
+
+    /// Generate a line of dialogue of at most `max_tokens` tokens spoken by `character`
+    /// and push it to the scene.
+    /// Returns an error if the character isn't in the scene or has left it.
+    pub fn infer_dialogue(
+        &mut self,
+        character: impl AsRef<str>,
+        max_tokens: usize,
+    ) -> Result<SceneTurn> {
+        self.infer_dialogue_directed(character.as_ref(), None, max_tokens)
+    }
+
+    /// Generate a line of dialogue of at most `max_tokens` tokens spoken by `character`
+    /// that steers the conversation toward `topic`, such as "ask about the stolen amulet",
+    /// and push it to the scene. The topic is only shown to the model for this line and never enters the memory.
+    pub fn infer_dialogue_about(
+        &mut self,
+        character: impl AsRef<str>,
+        topic: impl AsRef<str>,
+        max_tokens: usize,
+    ) -> Result<SceneTurn> {
+        self.infer_dialogue_directed(character.as_ref(), Some(topic.as_ref()), max_tokens)
+    }
+
+    /// Generate `n` distinct candidate lines of dialogue of at most `max_tokens` tokens spoken by `character`,
+    /// each with a different seed, without pushing any of them to the scene or compressing its memory.
+    /// Pass the chosen line to `Scene::accept` to commit it.
+    /// Identical candidates are dropped, so fewer than `n` lines may be returned.
+    pub fn suggest_dialogue(
+        &mut self,
+        character: impl AsRef<str>,
+        n: usize,
+        max_tokens: usize,
+    ) -> Result<Vec<SceneTurn>> {
+        self.suggest_dialogue_within(character.as_ref(), n, max_tokens, None)
+    }
+
+    /// Like `suggest_dialogue`, but also drop candidates whose embedding has a cosine similarity
+    /// above `max_similarity` with an earlier candidate
+    pub fn suggest_distinct_dialogue(
+        &mut self,
+        character: impl AsRef<str>,
+        n: usize,
+        max_tokens: usize,
+        max_similarity: f32,
+    ) -> Result<Vec<SceneTurn>> {
+        self.suggest_dialogue_within(character.as_ref(), n, max_tokens, Some(max_similarity))
+    }
+
+    /// Generate candidate lines until there are `n` distinct ones or the attempts run out
+    fn suggest_dialogue_within(
+        &mut self,
+        character: &str,
+        n: usize,
+        max_tokens: usize,
+        max_similarity: Option<f32>,
+    ) -> Result<Vec<SceneTurn>> {
+        let mut suggestions: Vec<SceneTurn> = Vec::new();
+        let mut embeddings: Vec<Vec<f32>> = Vec::new();
+        for _ in 0..n * MAX_SUGGESTION_ATTEMPTS {
+            if suggestions.len() >= n {
+                break;
+            }
+            let turn = self.generate_dialogue(character, None, max_tokens)?;
+
+            // Drop lines that repeat an earlier suggestion
+            let text = turn.text().to_lowercase();
+            if suggestions
+                .iter()
+                .any(|suggestion| suggestion.text().to_lowercase() == text)
+            {
+                continue;
+            }
+            if let Some(max_similarity) = max_similarity {
+                let embedding = self.model.embed(turn.text())?;
+                if embeddings
+                    .iter()
+                    .any(|other| cosine_similarity(&embedding, other) > max_similarity)
+                {
+                    continue;
+                }
+                embeddings.push(embedding);
+            }
+
+            suggestions.push(turn);
+        }
+        Ok(suggestions)
+    }
+
+    /// Push a turn that was generated without being pushed, such as one returned by `suggest_dialogue`,
+    /// and update the scene the same way as after any inferred turn
+    pub fn accept(&mut self, turn: SceneTurn) -> Result<SceneTurn> {
+        let mut turn = turn;
+        self.push_turn(&turn);
+        if let SceneTurnType::Dialogue(character, _) = &turn.turn_type {
+            self.last_speaker = Some(character.clone());
+        }
+        self.on_inferred_turn(&mut turn)?;
+        Ok(turn)
+    }
+
+    /// Generate a line of dialogue, optionally conditioned on a topic for the line, and push it to the scene
+    fn infer_dialogue_directed(
+        &mut self,
+        character: &str,
+        topic: Option<&str>,
+        max_tokens: usize,
+    ) -> Result<SceneTurn> {
+        self.ensure_open()?;
+        self.ensure_present(character)?;
+
+        // Make room for the new turn
+        self.compress_memory()?;
+
+        let turn = self.generate_dialogue(character, topic, max_tokens)?;
+        self.accept(turn)
+    }
+
+    /// Generate a line of dialogue from the scene's current prompt, optionally conditioned on a topic for the line,
+    /// without pushing it or compressing the memory
+    fn generate_dialogue(
+        &mut self,
+        character: &str,
+        topic: Option<&str>,
+        max_tokens: usize,
+    ) -> Result<SceneTurn> {
+        self.ensure_open()?;
+        self.ensure_present(character)?;
+
+        // Remind the model who the character is right before they speak
+        let mut prompt = self.prompt()?;
+        if let Some(profile) = self.character(character).and_then(Character::profile) {
+            prompt.push_str(format!("({})\n", profile));
+        }
+
+        // Tell the model what the line should be about
+        if let Some(topic) = topic {
+            prompt.push_str(format!(
+                "(In the next line, {} will {}.)\n",
+                character,
+                topic.trim()
+            ));
+        }
+
+        // Start the line with the character's name and the opening delimiter
+        prompt.push_str(self.format.dialogue_prefix(character));
+
+        // Generate until the closing delimiter, using the character's own parameters where set
+        let mut config = GenerationConfig {
+            seed: self.next_seed(),
+            max_tokens: Some(max_tokens),
+            stop: self.format.stops(&self.format.dialogue_close),
+            ..self.config.clone()
+        };
+        if let Some(profile) = self.character(character) {
+            config.temp = profile.temp.or(config.temp);
+            config.max_tokens = profile
+                .max_tokens
+                .map(|limit| limit.min(max_tokens))
+                .or(config.max_tokens);
+            config.stop.extend(profile.stop.iter().cloned());
+        }
+        let text = self.generate_turn_text(prompt, config)?;
+
+        Ok(SceneTurn::new(SceneTurnType::Dialogue(
+            character.to_string(),
+            text.trim().to_string(),
+        )))
+    }
+
+    /// Generate a physical action of at most `max_tokens` tokens performed by `character`
+    /// and push it to the scene.
+    /// Returns an error if the character isn't in the scene or has left it.
+    pub fn infer_action(
+        &mut self,
+        character: impl AsRef<str>,
+        max_tokens: usize,
+    ) -> Result<SceneTurn> {
+        let character = character.as_ref();
+        self.ensure_open()?;
+        self.ensure_present(character)?;
+
+        // Make room for the new turn
+        self.compress_memory()?;
+
+        // Remind the model who the character is right before they act
+        let mut prompt = self.prompt()?;
+        if let Some(profile) = self.character(character).and_then(Character::profile) {
+            prompt.push_str(format!("({})\n", profile));
+        }
+
+        // Start the action with its opening delimiter and the character's name
+        prompt.push_str(self.format.action_prefix(character));
+
+        // Generate until the closing delimiter
+        let mut config = GenerationConfig {
+            seed: self.next_seed(),
+            max_tokens: Some(max_tokens),
+            stop: self.format.stops(&self.format.action_close),
+            ..self.config.clone()
+        };
+        if let Some(profile) = self.character(character) {
+            config.temp = profile.temp.or(config.temp);
+        }
+        let text = self.generate_turn_text(prompt, config)?;
+
+        let mut turn = self.push_action(character, text);
+        self.on_inferred_turn(&mut turn)?;
+        Ok(turn)
+    }
+
+    /// Generate either narration or a line of dialogue from a character other than the last speaker,
+    /// chosen according to the turn weights and turn scheduler of the scene.
+    /// Falls back to narration when no character can speak.
+    /// Returns an error if both turn weights are zero or the scene has concluded.
+    pub fn infer_any(&mut self, max_tokens: usize) -> Result<SceneTurn> {
+        self.ensure_open()?;
+
+        let total_weight = self.turn_weights.dialogue + self.turn_weights.story;
+        if total_weight == 0 {
+            anyhow::bail!("dialogue and story turn weights are both zero")
+        }
+
+        // Decide whether this turn is dialogue based on the weights
+        let seed = self.config.seed;
+        if seed % total_weight < self.turn_weights.dialogue {
+            if let Some(character) = self.choose_speaker(seed / total_weight) {
+                return self.infer_dialogue(character, max_tokens);
+            }
+        }
+
+        self.infer_story(max_tokens)
+    }
+
+    /// Generate a line of dialogue from the character with an unachieved goal who has waited longest to speak,
+    /// steering the line toward their first goal, then ask the model whether the goal was achieved.
+    /// Achieved goals are removed from the character and listed by `Scene::achieved_goals`.
+    /// Falls back to `infer_any` when no character other than the last speaker has a goal.
+    pub fn infer_toward_goals(&mut self, max_tokens: usize) -> Result<GoalTurn> {
+        // Give the turn to whoever with a goal spoke least recently
+        let chosen = self
+            .characters
+            .iter()
+            .filter(|character| {
+                !character.goals.is_empty() && Some(character.name.as_str()) != self.last_speaker()
+            })
+            .min_by_key(|character| {
+                self.turns
+                    .iter()
+                    .rposition(|turn| turn.character() == Some(character.name.as_str()))
+                    .map_or(0, |position| position + 1)
+            })
+            .map(|character| (character.name.clone(), character.goals[0].clone()));
+        let Some((character, goal)) = chosen else {
+            return Ok(GoalTurn {
+                turn: self.infer_any(max_tokens)?,
+                goal: None,
+                achieved: false,
+            });
+        };
+
+        // Have the character pursue the goal
+        let topic = format!("try to {}", goal.trim_end_matches('.'));
+        let turn = self.infer_dialogue_directed(&character, Some(&topic), max_tokens)?;
+
+        // Check whether it worked
+        let achieved = self.goal_achieved(&character, &goal);
+        if achieved {
+            self.complete_goal(&character, &goal);
+        }
+
+        Ok(GoalTurn {
+            turn,
+            goal: Some(goal),
+            achieved,
+        })
+    }
+
+    /// Ask the model whether the recent turns show that `character` achieved `goal`
+    pub fn goal_achieved(&mut self, character: impl AsRef<str>, goal: impl AsRef<str>) -> bool {
+        let recent = self.short_term_memory.to_string();
+        let question = format!(
+            "The answer to: Has {} managed to {}?",
+            character.as_ref(),
+            goal.as_ref().trim_end_matches('.')
+        );
+        let seed = self.next_seed();
+        self.model
+            .try_choose_item(recent, question, ["yes", "no"], seed, 3)
+            .as_deref()
+            == Some("yes")
+    }
+
+    /// Mark a goal of a character as achieved, removing it from the character's goals.
+    /// Returns false if the character doesn't have that goal.
+    pub fn complete_goal(&mut self, character: impl AsRef<str>, goal: impl AsRef<str>) -> bool {
+        let (character, goal) = (character.as_ref(), goal.as_ref());
+        let Some(profile) = self
+            .characters
+            .iter_mut()
+            .find(|other| other.name == character)
+        else {
+            return false;
+        };
+        let Some(position) = profile.goals.iter().position(|other| other == goal) else {
+            return false;
+        };
+
+        profile.goals.remove(position);
+        self.achieved_goals
+            .entry(character.to_string())
+            .or_default()
+            .push(goal.to_string());
+        true
+    }
+
+    /// Get the goals a character has achieved, oldest first
+    pub fn achieved_goals(&self, character: impl AsRef<str>) -> &[String] {
+        self.achieved_goals
+            .get(character.as_ref())
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Ask the model whether the scene has reached a natural end, such as a resolved conflict or a farewell
+    pub fn should_conclude(&mut self) -> bool {
+        if self.closed {
+            return true;
+        }
+        if self.turns.is_empty() {
+            return false;
+        }
+
+        let recent = self.short_term_memory.to_string();
+        let seed = self.next_seed();
+        self.model
+            .try_choose_item(
+                recent,
+                "The answer to: Has this scene reached a natural ending?",
+                ["yes", "no"],
+                seed,
+                3,
+            )
+            .as_deref()
+            == Some("yes")
+    }
+
+    /// Generate a wrap-up narration of at most `max_tokens` tokens, push it to the scene and close the scene.
+    /// Every kind of inferred turn returns an error once the scene is closed.
+    pub fn conclude(&mut self, max_tokens: usize) -> Result<SceneTurn> {
+        let turn = self.infer_story_directed(
+            Some(
+                "(Bring the scene to a close with a short epilogue that wraps up what happened.)\n",
+            ),
+            max_tokens,
+        )?;
+        self.closed = true;
+        Ok(turn)
+    }
+
+    /// Whether the scene was concluded with `Scene::conclude`
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Return an error if the scene was concluded, since nothing can be inferred after the epilogue
+    fn ensure_open(&self) -> Result<()> {
+        if self.closed {
+            anyhow::bail!("the scene has already concluded")
+        }
+        Ok(())
+    }
+
+    /// Return an error if the character left the scene for good or was never in it
+    fn ensure_present(&self, character: &str) -> Result<()> {
+        if let Some(retired) = self
+            .retired
+            .iter()
+            .find(|retired| retired.character.name == character)
+        {
+            anyhow::bail!("character {:?} has left the story ({})", character, retired.reason)
+        }
+        if self.character(character).is_none() {
+            anyhow::bail!("character {:?} is not in the scene", character)
+        }
+        Ok(())
+    }
+
+    /// Ask the turn scheduler who speaks next, or `None` if nobody should
+    fn choose_speaker(&self, seed: u64) -> Option<String> {
+        self.turn_scheduler
+            .next_speaker(self, seed)
+            .filter(|name| self.character(name).is_some())
+    }
+
+    /// Get how often `infer_any` generates dialogue compared to narration
+    pub fn turn_weights(&self) -> TurnWeights {
+        self.turn_weights
+    }
+
+    pub fn set_turn_weights(&mut self, turn_weights: TurnWeights) {
+        self.turn_weights = turn_weights;
+    }
+
+    /// Get what `infer_any` uses to choose who speaks
+    pub fn turn_scheduler(&self) -> &dyn TurnScheduler {
+        self.turn_scheduler.as_ref()
+    }
+
+    /// Change what `infer_any` uses to choose who speaks.
+    /// The scheduler isn't saved by `Scene::save`, so set it again after `Scene::load`.
+    pub fn set_turn_scheduler(&mut self, turn_scheduler: impl TurnScheduler + 'static) {
+        self.turn_scheduler = Arc::new(turn_scheduler);
+    }
+
+    pub fn set_speaker_selection(&mut self, speaker_selection: SpeakerSelection) {
+        self.set_turn_scheduler(speaker_selection);
+    }
+
+    /// Compress the memory if the long and short-term memory together exceed the threshold of the memory policy.
+    /// Everything after the preamble except the turns the policy keeps verbatim is compressed into the long-term memory.
+    pub fn compress_memory(&mut self) -> Result<()> {
+        let threshold = self.memory_policy.threshold(self.max_memory);
+        if self.long_term_memory.len() + self.short_term_memory.len() <= threshold {
+            return Ok(());
+        }
+
+        self.compress_all_but(self.memory_policy.keep_verbatim())
+    }
+
+    /// Compress every turn in the short-term memory except the last `keep_turns` into the long-term memory
+    fn compress_all_but(&mut self, keep_turns: usize) -> Result<()> {
+        // Split the short-term memory into older turns and the turns kept verbatim
+        let keep_turns = keep_turns.min(self.short_term_turns.len());
+        let compress_turns = self.short_term_turns.len() - keep_turns;
+        if compress_turns == 0 {
+            return Ok(());
+        }
+        let split_at: usize = self.short_term_turns[..compress_turns].iter().sum();
+
+        // Remember the compressed turns verbatim so they can be recalled later
+        if let Some(recall) = &mut self.recall {
+            let recall = Arc::make_mut(recall);
+            let mut start = 0;
+            for length in &self.short_term_turns[..compress_turns] {
+                let turn = &self.short_term_memory.as_slice()[start..start + length];
+                recall.memory.insert(&self.model, self.model.detokenize(turn))?;
+                start += length;
+            }
+        }
+
+        // Gather the older events
+        let mut events = self.summary.clone();
+        events.push_tokens(&self.short_term_memory.as_slice()[..split_at]);
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        // Pin the important facts of the newly compressed turns before they can be paraphrased away.
+        // The summary isn't searched again because its facts were pinned when it was compressed.
+        if self.extract_facts {
+            let seed = self.next_seed();
+            let compressed = TokenString::new(
+                self.short_term_memory.as_slice()[..split_at].to_vec(),
+                self.model.clone(),
+            );
+            for fact in self.find_important_facts(&compressed, seed)? {
+                self.add_fact(&fact);
+            }
+        }
+
+        // Compress the events into half of the memory budget
+        let seed = self.next_seed();
+        let summary = self.memory_policy.compress(&events, self.max_memory / 2, seed);
+        self.summary = self.model.tokenize(summary.to_string().trim());
+        self.rebuild_long_term_memory();
+
+        // Keep only the recent turns in the short-term memory
+        let recent = self.short_term_memory.as_slice()[split_at..].to_vec();
+        self.short_term_memory = TokenString::new(recent, self.model.clone());
+        self.short_term_turns.drain(..compress_turns);
+        Ok(())
+    }
+
+    /// Extract structured facts from every turn of the scene, such as ("blacksmith", "owns", "enchanted hammer").
+    /// Each turn is read by the model separately so every fact can be traced to the turn it came from.
+    /// Recaps are skipped because they only repeat earlier turns.
+    /// Turns that were already read are remembered, so only new turns are given to the model.
+    pub fn extract_facts(&mut self) -> Result<Vec<Fact>> {
+        let mut facts: Vec<Fact> = Vec::new();
+        for index in 0..self.turns.len() {
+            for fact in self.extract_facts_from_turn(index)? {
+                // Keep only the first source of each fact
+                if !facts.iter().any(|other| {
+                    other.subject == fact.subject
+                        && other.relation == fact.relation
+                        && other.object == fact.object
+                }) {
+                    facts.push(fact);
+                }
+            }
+        }
+        Ok(facts)
+    }
+
+    /// Extract structured facts from the turn at `index`, or nothing if there is no such turn.
+    /// The facts are remembered, so each turn is only read by the model once.
+    pub fn extract_facts_from_turn(&mut self, index: usize) -> Result<Vec<Fact>> {
+        if let Some(facts) = self.extracted_facts.get(&index) {
+            return Ok(facts.clone());
+        }
+        let Some(turn) = self.turns.get(index) else {
+            return Ok(Vec::new());
+        };
+        if matches!(turn.turn_type, SceneTurnType::Recap(_)) {
+            return Ok(Vec::new());
+        }
+
+        // Give the model the turn and start the list
+        let mut extra = HashMap::new();
+        extra.insert("Setting", self.setting.clone());
+        extra.insert("Text", self.format.render(turn));
+        extra.insert("Response", "(".to_string());
+
+        // Generate the list until the next section
+        let config = GenerationConfig::new(self.next_seed())
+            .with_temp(0.1)
+            .with_max_tokens(MAX_TRIPLE_TOKENS)
+            .with_stop("###");
+        let list = self
+            .model
+            .instruct_with(
+                "List the facts stated in the text as (subject, relation, object) triples, such as (blacksmith, owns, enchanted hammer). Write one triple per line.",
+                Some(&extra),
+                &config,
+            )?;
+
+        let facts = Fact::parse_lines(format!("({}", list), Some(index));
+        self.extracted_facts.insert(index, facts.clone());
+        Ok(facts)
+    }
+
+    /// Ask the model for the facts in the events that must never be forgotten
+    fn find_important_facts(&self, events: &TokenString, seed: u64) -> Result<Vec<String>> {
+        // Give the model the events and start the list
+        let mut extra = HashMap::new();
+        extra.insert("Text", events.to_string());
+        extra.insert("Response", "- ".to_string());
+
+        // Generate the list until the next section
+        let config = GenerationConfig::new(seed)
+            .with_temp(0.2)
+            .with_max_tokens(MAX_FACT_TOKENS)
+            .with_stop("###");
+        let list = self
+            .model
+            .instruct_with(
+                "List the important facts in the text that must never be forgotten, such as names, promises, items and deaths. Write one short fact per line starting with \"- \".",
+                Some(&extra),
+                &config,
+            )?;
+
+        // Each line of the list is a fact
+        Ok(format!("- {}", list)
+            .lines()
+            .map(|line| line.trim().trim_start_matches(['-', '*']).trim())
+            .filter(|fact| !fact.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Summarize everything that happened in the scene so far into at most `max_tokens` tokens
+    pub fn summarize(&mut self, max_tokens: usize) -> String {
+        // Gather the compressed events and the recent turns
+        let mut events = self.summary.clone();
+        events.push(&self.short_term_memory);
+        if events.is_empty() {
+            return String::new();
+        }
+
+        let seed = self.next_seed();
+        events
+            .shortened(max_tokens, seed)
+            .to_string()
+            .trim()
+            .to_string()
+    }
+
+    /// Replace the compressed account of older events, such as with context carried over from an earlier scene
+    pub(crate) fn set_summary(&mut self, summary: impl AsRef<str>) {
+        self.summary = self.model.tokenize(summary.as_ref().trim());
+        self.rebuild_long_term_memory();
+    }
+
+    /// Rebuild the long-term memory from the preamble, the pinned facts and the summary.
+    /// The oldest facts are forgotten until the long-term memory fits in the memory budget.
+    fn rebuild_long_term_memory(&mut self) {
+        let mut long_term_memory = self.model.tokenize(self.preamble());
+        let summary = if self.summary.is_empty() {
+            self.model.new_token_string()
+        } else {
+            self.model.tokenize_str(format!(
+                "{}{}{}\n",
+                self.format.narration_prefix(),
+                self.summary,
+                self.format.narration_close
+            ))
+        };
+
+        // Keep only the newest facts that fit beside the preamble and summary
+        let budget = self
+            .max_memory
+            .min(MAX_TOKENS)
+            .saturating_sub(long_term_memory.len() + summary.len());
+        let mut facts = self.model.new_token_string();
+        while !self.facts.is_empty() {
+            facts = self.model.tokenize_str(format!(
+                "Important facts:\n{}",
+                self.facts
+                    .iter()
+                    .map(|fact| format!("- {}\n", fact))
+                    .join("")
+            ));
+            if facts.len() <= budget {
+                break;
+            }
+            self.facts.remove(0);
+            facts = self.model.new_token_string();
+        }
+
+        long_term_memory.push(facts);
+        long_term_memory.push(summary);
+        self.long_term_memory = long_term_memory;
+    }
+}
+
+/// Everything needed to resume a scene later, produced by `Scene::save`.
+/// The memory policy and turn scheduler aren't included.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneSnapshot {
+    /// The fingerprint of the model whose tokens are stored in the memory
+    pub model: String,
+    pub setting: String,
+    pub characters: Vec<Character>,
+    #[serde(default)]
+    pub retired: Vec<RetiredCharacter>,
+    #[serde(default)]
+    pub format: SceneFormat,
+    #[serde(default)]
+    pub narration_style: Option<NarrationStyle>,
+    #[serde(default)]
+    pub genre: Option<Genre>,
+    #[serde(default)]
+    pub pacing: Option<Pacing>,
+    #[serde(default)]
+    pub pacing_schedule: Option<usize>,
+    #[serde(default)]
+    pub inferred_since_pacing: usize,
+    #[serde(default)]
+    pub recap_every: Option<usize>,
+    #[serde(default)]
+    pub introduce_characters: Option<usize>,
+    pub long_term_memory: Vec<u32>,
+    /// Compressed account of older events
+    #[serde(default)]
+    pub summary: Vec<u32>,
+    /// Important facts protected from compression
+    #[serde(default)]
+    pub facts: Vec<String>,
+    #[serde(default = "default_extract_facts")]
+    pub extract_facts: bool,
+    /// Vector memory of compressed turns, if recall is enabled
+    #[serde(default)]
+    pub recall: Option<Recall>,
+    #[serde(default)]
+    pub state: WorldState,
+    #[serde(default)]
+    pub clock: Option<SceneClock>,
+    #[serde(default)]
+    pub auto_update_state: bool,
+    #[serde(default)]
+    pub relationships: Relationships,
+    #[serde(default)]
+    pub track_relationships: bool,
+    #[serde(default)]
+    pub emotion_labels: Option<Vec<String>>,
+    #[serde(default)]
+    pub achieved_goals: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub constraints: ContentConstraints,
+    #[serde(default)]
+    pub directives: Vec<Directive>,
+    #[serde(default)]
+    pub closed: bool,
+    pub short_term_memory: Vec<u32>,
+    /// Number of tokens of each turn in the short-term memory, oldest first
+    #[serde(default)]
+    pub short_term_turns: Vec<usize>,
+    /// Every turn of the scene, oldest first
+    #[serde(default)]
+    pub turns: Vec<SceneTurn>,
+    /// Structured facts already extracted from each turn, by the turn's index
+    #[serde(default)]
+    pub extracted_facts: BTreeMap<usize, Vec<Fact>>,
+    pub max_memory: usize,
+    pub config: GenerationConfig,
+    #[serde(default)]
+    pub turn_weights: TurnWeights,
+    pub last_speaker: Option<String>,
+}
+
+impl Scene {
+    /// Capture the state of the scene so it can be resumed later with `Scene::load`.
+    /// Returns an error if the model's files can't be read to fingerprint it.
+    pub fn save(&self) -> Result<SceneSnapshot> {
+        Ok(SceneSnapshot {
+            model: self.model.fingerprint()?,
+            setting: self.setting.clone(),
+            characters: self.characters.clone(),
+            retired: self.retired.clone(),
+            format: self.format.clone(),
+            narration_style: self.narration_style.clone(),
+            genre: self.genre.clone(),
+            pacing: self.pacing,
+            pacing_schedule: self.pacing_schedule,
+            inferred_since_pacing: self.inferred_since_pacing,
+            recap_every: self.recap_every,
+            introduce_characters: self.introduce_characters,
+            long_term_memory: self.long_term_memory.as_slice().to_vec(),
+            summary: self.summary.as_slice().to_vec(),
+            facts: self.facts.clone(),
+            extract_facts: self.extract_facts,
+            recall: self.recall.as_deref().cloned(),
+            state: self.state.clone(),
+            clock: self.clock.clone(),
+            auto_update_state: self.auto_update_state,
+            relationships: self.relationships.clone(),
+            track_relationships: self.track_relationships,
+            emotion_labels: self.emotion_labels.clone(),
+            achieved_goals: self.achieved_goals.clone(),
+            constraints: self.constraints.clone(),
+            directives: self.directives.clone(),
+            closed: self.closed,
+            short_term_memory: self.short_term_memory.as_slice().to_vec(),
+            short_term_turns: self.short_term_turns.clone(),
+            turns: self.turns.as_ref().clone(),
+            extracted_facts: self.extracted_facts.clone(),
+            max_memory: self.max_memory,
+            config: self.config.clone(),
+            turn_weights: self.turn_weights,
+            last_speaker: self.last_speaker.clone(),
+        })
+    }
+
+    /// Resume a scene from a snapshot using the default memory policy and turn scheduler.
+    /// Policies and schedulers are arbitrary code and aren't part of the snapshot,
+    /// so a scene that used others needs them set again with `Scene::set_memory_policy` and `Scene::set_turn_scheduler`.
+    /// Returns an error if the snapshot was saved with a different model.
+    pub fn load(model: Model, snapshot: SceneSnapshot) -> Result<Self> {
+        let fingerprint = model.fingerprint()?;
+        if snapshot.model != fingerprint {
+            anyhow::bail!(
+                "scene was saved with model {:?} but this model is {:?}",
+                snapshot.model,
+                fingerprint
+            )
+        }
+
+        Ok(Self::from_snapshot(
+            model,
+            snapshot,
+            Arc::new(ParaphraseAll),
+            Arc::new(RoundRobin),
+        ))
+    }
+
+    /// Create a scene from a snapshot without checking the model
+    fn from_snapshot(
+        model: Model,
+        snapshot: SceneSnapshot,
+        memory_policy: Arc<dyn MemoryPolicy>,
+        turn_scheduler: Arc<dyn TurnScheduler>,
+    ) -> Self {
+        // Snapshots without turn lengths treat the whole short-term memory as one turn
+        let short_term_turns =
+            if snapshot.short_term_turns.is_empty() && !snapshot.short_term_memory.is_empty() {
+                vec![snapshot.short_term_memory.len()]
+            } else {
+                snapshot.short_term_turns
+            };
+
+        Self {
+            long_term_memory: TokenString::new(snapshot.long_term_memory, model.clone()),
+            summary: TokenString::new(snapshot.summary, model.clone()),
+            facts: snapshot.facts,
+            extract_facts: snapshot.extract_facts,
+            recall: snapshot.recall.map(Arc::new),
+            state: snapshot.state,
+            clock: snapshot.clock,
+            auto_update_state: snapshot.auto_update_state,
+            relationships: snapshot.relationships,
+            track_relationships: snapshot.track_relationships,
+            emotion_labels: snapshot.emotion_labels,
+            achieved_goals: snapshot.achieved_goals,
+            constraints: snapshot.constraints,
+            directives: snapshot.directives,
+            closed: snapshot.closed,
+            short_term_memory: TokenString::new(snapshot.short_term_memory, model.clone()),
+            short_term_turns,
+            turns: Arc::new(snapshot.turns),
+            extracted_facts: snapshot.extracted_facts,
+            model,
+            setting: snapshot.setting,
+            characters: snapshot.characters,
+            retired: snapshot.retired,
+            format: snapshot.format,
+            narration_style: snapshot.narration_style,
+            genre: snapshot.genre,
+            pacing: snapshot.pacing,
+            pacing_schedule: snapshot.pacing_schedule,
+            inferred_since_pacing: snapshot.inferred_since_pacing,
+            recap_every: snapshot.recap_every,
+            introduce_characters: snapshot.introduce_characters,
+            max_memory: snapshot.max_memory,
+            memory_policy,
+            config: snapshot.config,
+            turn_weights: snapshot.turn_weights,
+            turn_scheduler,
+            last_speaker: snapshot.last_speaker,
+        }
+    }
+
+    /// Remember the current state of the scene so it can be returned to with `Scene::restore`.
+    /// The turns and recalled memory are shared with the scene until either of them changes,
+    /// so making a checkpoint doesn't copy the history.
+    pub fn checkpoint(&self) -> SceneCheckpoint {
+        SceneCheckpoint {
+            scene: Arc::new(self.clone()),
+        }
+    }
+
+    /// Return the scene to the state it was in when the checkpoint was made, including its memory policy
+    /// and turn scheduler. The checkpoint can be restored more than once.
+    pub fn restore(&mut self, checkpoint: &SceneCheckpoint) {
+        *self = checkpoint.scene.as_ref().clone();
+    }
+
+    /// Create an independent copy of the scene that continues in an alternate timeline.
+    /// The copy shares the model's weights and tokenizer with this scene.
+    pub fn branch(&self) -> Scene {
+        self.clone()
+    }
+}
+
+/// A saved state of a scene, produced by `Scene::checkpoint`
+#[derive(Clone)]
+pub struct SceneCheckpoint {
+    scene: Arc<Scene>,
+}
+
+impl SceneCheckpoint {
+    /// Save the scene held by the checkpoint, like `Scene::save`
+    pub fn save(&self) -> Result<SceneSnapshot> {
+        self.scene.save()
+    }
+}
+
+fn default_extract_facts() -> bool {
+    true
+}
+
+/// Get the form of a fact used to spot duplicates
+fn normalize_fact(fact: &str) -> String {
+    fact.trim()
+        .trim_end_matches(['.', '!', ';'])
+        .split_whitespace()
+        .join(" ")
+        .to_lowercase()
+}
+
+impl Display for Scene {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.long_term_memory, self.short_term_memory)
+    }
+}
+
+/// A character taking part in a scene.
+/// The profile is shown to the model right before the character speaks so each character keeps their own voice.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Character {
+    pub name: String,
+    /// Personality traits, such as "gruff" or "curious"
+    pub traits: Vec<String>,
+    /// How the character talks, such as "short sentences and old-fashioned words"
+    pub speech_style: Option<String>,
+    /// What the character wants
+    pub goals: Vec<String>,
+    /// Sampling temperature for the character's lines, overriding the scene default
+    #[serde(default)]
+    pub temp: Option<f64>,
+    /// Maximum number of tokens in each of the character's lines
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    /// Extra strings that end the character's lines
+    #[serde(default)]
+    pub stop: Vec<String>,
+}
+
+impl Character {
+    /// Create a character with only a name
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            traits: Vec::new(),
+            speech_style: None,
+            goals: Vec::new(),
+            temp: None,
+            max_tokens: None,
+            stop: Vec::new(),
+        }
+    }
+
+    /// Add a personality trait
+    pub fn with_trait(mut self, personality_trait: impl Into<String>) -> Self {
+        self.traits.push(personality_trait.into());
+        self
+    }
+
+    pub fn with_speech_style(mut self, speech_style: impl Into<String>) -> Self {
+        self.speech_style = Some(speech_style.into());
+        self
+    }
+
+    /// Add a goal
+    pub fn with_goal(mut self, goal: impl Into<String>) -> Self {
+        self.goals.push(goal.into());
+        self
+    }
+
+    /// Set the sampling temperature of the character's lines
+    pub fn with_temp(mut self, temp: impl Into<Option<f64>>) -> Self {
+        self.temp = temp.into();
+        self
+    }
+
+    /// Limit the number of tokens in each of the character's lines
+    pub fn with_max_tokens(mut self, max_tokens: impl Into<Option<usize>>) -> Self {
+        self.max_tokens = max_tokens.into();
+        self
+    }
+
+    /// Add a string that ends the character's lines
+    pub fn with_stop(mut self, stop: impl Into<String>) -> Self {
+        self.stop.push(stop.into());
+        self
+    }
+
+    /// Describe the character in a single line, or `None` if there is nothing beyond the name
+    pub fn profile(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if !self.traits.is_empty() {
+            parts.push(format!("{} is {}.", self.name, self.traits.join(", ")));
+        }
+        if let Some(speech_style) = &self.speech_style {
+            parts.push(format!("{} speaks with {}.", self.name, speech_style));
+        }
+        if !self.goals.is_empty() {
+            parts.push(format!("{} wants to {}.", self.name, self.goals.join(" and to ")));
+        }
+
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" "))
+        }
+    }
+}
+
+impl From<&str> for Character {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for Character {
+    fn from(name: String) -> Self {
+        Self::new(name)
+    }
+}
+
+/// Builds a `Scene` from a setting, a cast of characters and default generation parameters
+pub struct SceneBuilder {
+    model: Model,
+    setting: String,
+    characters: Vec<Character>,
+    format: SceneFormat,
+    narration_style: Option<NarrationStyle>,
+    genre: Option<Genre>,
+    pacing: Option<Pacing>,
+    pacing_schedule: Option<usize>,
+    recap_every: Option<usize>,
+    introduce_characters: Option<usize>,
+    max_memory: usize,
+    memory_policy: Arc<dyn MemoryPolicy>,
+    extract_facts: bool,
+    recall_top_k: Option<usize>,
+    state: WorldState,
+    clock: Option<SceneClock>,
+    auto_update_state: bool,
+    relationships: Relationships,
+    track_relationships: bool,
+    emotion_labels: Option<Vec<String>>,
+    constraints: ContentConstraints,
+    turn_weights: TurnWeights,
+    turn_scheduler: Arc<dyn TurnScheduler>,
+    config: GenerationConfig,
+}
+
+impl SceneBuilder {
+    pub fn new(model: Model, setting: impl Into<String>) -> Self {
+        Self {
+            model,
+            setting: setting.into(),
+            characters: Vec::new(),
+            format: SceneFormat::default(),
+            narration_style: None,
+            genre: None,
+            pacing: None,
+            pacing_schedule: None,
+            recap_every: None,
+            introduce_characters: None,
+            max_memory: DEFAULT_MAX_MEMORY,
+            memory_policy: Arc::new(ParaphraseAll),
+            extract_facts: true,
+            recall_top_k: None,
+            state: WorldState::new(),
+            clock: None,
+            auto_update_state: false,
+            relationships: Relationships::new(),
+            track_relationships: false,
+            emotion_labels: None,
+            constraints: ContentConstraints::default(),
+            turn_weights: TurnWeights::default(),
+            turn_scheduler: Arc::new(RoundRobin),
+            config: GenerationConfig::default().with_temp(0.5),
+        }
+    }
+
+    /// Add a character to the scene, either a full `Character` or just a name
+    pub fn character(mut self, character: impl Into<Character>) -> Self {
+        self.characters.push(character.into());
+        self
+    }
+
+    /// Add several characters to the scene
+    pub fn characters(mut self, characters: impl IntoIterator<Item = impl Into<Character>>) -> Self {
+        self.characters.extend(characters.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set how turns are written in the memory, such as `SceneFormat::screenplay()`
+    pub fn format(mut self, format: SceneFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the style narration is written in
+    pub fn narration_style(mut self, narration_style: NarrationStyle) -> Self {
+        self.narration_style = Some(narration_style);
+        self
+    }
+
+    /// Set how many tokens of memory the scene may hold before it is compressed
+    pub fn max_memory(mut self, max_memory: usize) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+
+    /// Set the policy that decides when and how the memory is compressed
+    pub fn memory_policy(mut self, memory_policy: impl MemoryPolicy + 'static) -> Self {
+        self.memory_policy = Arc::new(memory_policy);
+        self
+    }
+
+    /// Set whether important facts are extracted and pinned before memory is compressed
+    pub fn extract_facts(mut self, extract_facts: bool) -> Self {
+        self.extract_facts = extract_facts;
+        self
+    }
+
+    /// Keep compressed turns in a vector memory and recall the `top_k` most relevant ones
+    /// into the prompt before every inference
+    pub fn recall(mut self, top_k: usize) -> Self {
+        self.recall_top_k = Some(top_k);
+        self
+    }
+
+    /// Set a value in the initial world state
+    pub fn state(mut self, key: impl Into<String>, value: impl Into<InferValue>) -> Self {
+        self.state.set(key, value);
+        self
+    }
+
+    /// Track in-world time with a clock, such as `SceneClock::at(18, 30).with_minutes_per_turn(5)`
+    pub fn clock(mut self, clock: SceneClock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Set whether the world state is updated from every inferred turn
+    pub fn auto_update_state(mut self, auto_update_state: bool) -> Self {
+        self.auto_update_state = auto_update_state;
+        self
+    }
+
+    /// Set how `from` initially feels about `to`, with scores from -1 to 1
+    pub fn relationship(
+        mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+        affinity: f32,
+        trust: f32,
+    ) -> Self {
+        self.relationships
+            .set(from, to, Relationship::new(affinity, trust));
+        self
+    }
+
+    /// Set whether the relationships are updated from every inferred turn by a character
+    pub fn track_relationships(mut self, track_relationships: bool) -> Self {
+        self.track_relationships = track_relationships;
+        self
+    }
+
+    /// Tag inferred dialogue with one of the given emotions, such as "angry" or "afraid"
+    pub fn tag_emotions(mut self, labels: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.emotion_labels = Some(labels.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Use a genre such as `Genre::noir()`, describing it in the preamble and taking its sampling parameters.
+    /// Sampling parameters set after this call override the genre's.
+    pub fn genre(mut self, genre: Genre) -> Self {
+        self.config.temp = genre.temp;
+        self.config.top_p = genre.top_p;
+        self.config.repeat_penalty = genre.repeat_penalty;
+        self.config.repeat_last_n = genre.repeat_last_n;
+        self.genre = Some(genre);
+        self
+    }
+
+    /// Set how much tension story turns start with
+    pub fn pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = Some(pacing);
+        self
+    }
+
+    /// Advance the pacing to its next stage every `turns` inferred turns, starting at calm if no pacing was set
+    pub fn pacing_schedule(mut self, turns: usize) -> Self {
+        self.pacing = self.pacing.or(Some(Pacing::Calm));
+        self.pacing_schedule = Some(turns);
+        self
+    }
+
+    /// Insert a recap after every `turns` turns
+    pub fn recap_every(mut self, turns: usize) -> Self {
+        self.recap_every = Some(turns);
+        self
+    }
+
+    /// Generate an introduction of at most `max_tokens` tokens for every character when the scene is built
+    /// and for every character added later
+    pub fn introduce_characters(mut self, max_tokens: usize) -> Self {
+        self.introduce_characters = Some(max_tokens);
+        self
+    }
+
+    /// Set the limits on what inferred turns may contain
+    pub fn constraints(mut self, constraints: ContentConstraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    /// Set how often `infer_any` generates dialogue compared to narration
+    pub fn turn_weights(mut self, dialogue: u64, story: u64) -> Self {
+        self.turn_weights = TurnWeights { dialogue, story };
+        self
+    }
+
+    /// Set what `infer_any` uses to choose which character speaks next, such as `Initiative` for combat
+    pub fn turn_scheduler(mut self, turn_scheduler: impl TurnScheduler + 'static) -> Self {
+        self.turn_scheduler = Arc::new(turn_scheduler);
+        self
+    }
+
+    /// Set how `infer_any` chooses which character speaks next
+    pub fn speaker_selection(self, speaker_selection: SpeakerSelection) -> Self {
+        self.turn_scheduler(speaker_selection)
+    }
+
+    /// Set the default generation parameters. `max_tokens` and `stop` are ignored
+    /// because every kind of turn sets its own.
+    pub fn config(mut self, config: GenerationConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = seed;
+        self
+    }
+
+    pub fn temp(mut self, temp: impl Into<Option<f64>>) -> Self {
+        self.config.temp = temp.into();
+        self
+    }
+
+    pub fn top_p(mut self, top_p: impl Into<Option<f64>>) -> Self {
+        self.config.top_p = top_p.into();
+        self
+    }
+
+    pub fn repeat_penalty(mut self, repeat_penalty: f32, repeat_last_n: usize) -> Self {
+        self.config.repeat_penalty = repeat_penalty;
+        self.config.repeat_last_n = repeat_last_n;
+        self
+    }
+
+    /// Create the scene.
+    /// Returns an error if the memory would not leave room in the context for generation.
+    pub fn build(self) -> Result<Scene> {
+        if self.max_memory >= MAX_TOKENS {
+            anyhow::bail!(
+                "max memory of {} tokens does not fit in the {} token context",
+                self.max_memory,
+                MAX_TOKENS
+            )
+        }
+
+        let mut scene = Scene::new(self.model, self.setting, self.characters, self.config);
+        scene.max_memory = self.max_memory;
+        scene.format = self.format;
+        scene.narration_style = self.narration_style;
+        scene.constraints = self.constraints;
+        scene.genre = self.genre;
+        scene.pacing = self.pacing;
+        scene.pacing_schedule = self.pacing_schedule;
+        scene.recap_every = self.recap_every;
+        scene.introduce_characters = self.introduce_characters;
+        scene.rebuild_long_term_memory();
+        scene.memory_policy = self.memory_policy;
+        scene.extract_facts = self.extract_facts;
+        scene.recall = self.recall_top_k.map(|top_k| Arc::new(Recall::new(top_k)));
+        scene.state = self.state;
+        scene.clock = self.clock;
+        scene.auto_update_state = self.auto_update_state;
+        scene.relationships = self.relationships;
+        scene.track_relationships = self.track_relationships;
+        scene.emotion_labels = self.emotion_labels;
+        scene.turn_weights = self.turn_weights;
+        scene.turn_scheduler = self.turn_scheduler;
+
+        // Introduce the starting cast
+        if let Some(max_tokens) = scene.introduce_characters {
+            let names: Vec<String> = scene
+                .characters
+                .iter()
+                .map(|character| character.name.clone())
+                .collect();
+            for name in names {
+                scene.introduce(name, max_tokens)?;
+            }
+        }
+
+        Ok(scene)
+    }
+}
+
+/// A character who left a scene for good
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RetiredCharacter {
+    pub character: Character,
+    /// Why the character left, such as "died in the fire"
+    pub reason: String,
+}
+
+/// The result of `Scene::infer_toward_goals`
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoalTurn {
+    pub turn: SceneTurn,
+    /// The goal the speaker pursued, or `None` if nobody had a goal to pursue
+    pub goal: Option<String>,
+    /// Whether the model believes the goal was achieved by this turn
+    pub achieved: bool,
+}
+
+/// What happened in a single turn of a scene
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SceneTurnType {
+    /// Narration
+    Story(String),
+    /// A character's name and the line they spoke
+    Dialogue(String, String),
+    /// A character's name and the physical action they performed
+    Action(String, String),
+    /// An action taken by the player
+    Player(String),
+    /// A summary of recent events for players returning to the scene
+    Recap(String),
+}
+
+/// A single turn of a scene
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneTurn {
+    pub turn_type: SceneTurnType,
+    /// The emotion the turn expresses, if emotion tagging is enabled
+    #[serde(default)]
+    pub emotion: Option<String>,
+}
+
+impl SceneTurn {
+    pub(crate) fn new(turn_type: SceneTurnType) -> Self {
+        Self {
+            turn_type,
+            emotion: None,
+        }
+    }
+
+    /// Get the text of the turn without any formatting
+    pub fn text(&self) -> &str {
+        match &self.turn_type {
+            SceneTurnType::Story(text) => text,
+            SceneTurnType::Dialogue(_, text) => text,
+            SceneTurnType::Action(_, text) => text,
+            SceneTurnType::Player(text) => text,
+            SceneTurnType::Recap(text) => text,
+        }
+    }
+
+    /// Get the name of the character who spoke or acted, if this is a dialogue or action turn
+    pub fn character(&self) -> Option<&str> {
+        match &self.turn_type {
+            SceneTurnType::Dialogue(character, _) | SceneTurnType::Action(character, _) => {
+                Some(character)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for SceneTurn {
+    /// Write the turn in the default `SceneFormat`. Use `SceneFormat::render` to write it the way a scene does.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&SceneFormat::default().render(self))
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// Minutes in an in-world day
+pub const MINUTES_PER_DAY: u64 = 24 * 60;
+
+/// A broad part of the day
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeOfDay {
+    Night,
+    Dawn,
+    Morning,
+    Afternoon,
+    Evening,
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeOfDay::Night => write!(f, "night"),
+            TimeOfDay::Dawn => write!(f, "dawn"),
+            TimeOfDay::Morning => write!(f, "morning"),
+            TimeOfDay::Afternoon => write!(f, "afternoon"),
+            TimeOfDay::Evening => write!(f, "evening"),
+        }
+    }
+}
+
+/// In-world time of a scene, shown to the model before every inference.
+/// The clock can advance by a fixed number of minutes with every turn.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneClock {
+    /// Minutes since midnight of the first day
+    pub minutes: u64,
+    /// Minutes the clock advances with every turn, if it advances on its own
+    pub minutes_per_turn: Option<u64>,
+}
+
+impl SceneClock {
+    /// Create a clock at the given time on the first day
+    pub fn at(hour: u64, minute: u64) -> Self {
+        Self {
+            minutes: (hour * 60 + minute) % MINUTES_PER_DAY,
+            minutes_per_turn: None,
+        }
+    }
+
+    /// Advance the clock by `minutes` with every turn
+    pub fn with_minutes_per_turn(mut self, minutes_per_turn: impl Into<Option<u64>>) -> Self {
+        self.minutes_per_turn = minutes_per_turn.into();
+        self
+    }
+
+    /// Move the clock forward
+    pub fn advance(&mut self, minutes: u64) {
+        self.minutes += minutes;
+    }
+
+    /// Get the current day, starting at 1
+    pub fn day(&self) -> u64 {
+        self.minutes / MINUTES_PER_DAY + 1
+    }
+
+    pub fn hour(&self) -> u64 {
+        self.minutes % MINUTES_PER_DAY / 60
+    }
+
+    pub fn minute(&self) -> u64 {
+        self.minutes % 60
+    }
+
+    pub fn time_of_day(&self) -> TimeOfDay {
+        match self.hour() {
+            5 => TimeOfDay::Dawn,
+            6..=11 => TimeOfDay::Morning,
+            12..=16 => TimeOfDay::Afternoon,
+            17..=20 => TimeOfDay::Evening,
+            _ => TimeOfDay::Night,
+        }
+    }
+
+    /// Whether the sun is down
+    pub fn is_night(&self) -> bool {
+        self.time_of_day() == TimeOfDay::Night
+    }
+
+    /// Replace `{day}`, `{time}` and `{time_of_day}` in `text` with the current time
+    pub fn format(&self, text: impl AsRef<str>) -> String {
+        text.as_ref()
+            .replace("{day}", &self.day().to_string())
+            .replace(
+                "{time}",
+                &format!("{:02}:{:02}", self.hour(), self.minute()),
+            )
+            .replace("{time_of_day}", &self.time_of_day().to_string())
+    }
+}
+
+impl Default for SceneClock {
+    fn default() -> Self {
+        Self::at(12, 0)
+    }
+}
+
+impl Display for SceneClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Day {}, {:02}:{:02} ({})",
+            self.day(),
+            self.hour(),
+            self.minute(),
+            self.time_of_day()
+        )
+    }
+}
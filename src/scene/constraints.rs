@@ -0,0 +1,172 @@
+use std::fmt::Display;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::model::Model;
+
+/// The audience a scene's content must be suitable for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rating {
+    /// Suitable for all ages
+    Everyone,
+    /// Suitable for teenagers
+    Teen,
+    /// Suitable for adults only
+    Mature,
+}
+
+impl Display for Rating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rating::Everyone => write!(f, "suitable for all ages"),
+            Rating::Teen => write!(f, "suitable for teenagers"),
+            Rating::Mature => write!(f, "suitable for adults"),
+        }
+    }
+}
+
+/// Limits on what a scene may generate.
+/// Banned words that are single tokens can never be generated, and inferred turns that still break a constraint
+/// are generated again with a new seed, up to `max_attempts` times.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ContentConstraints {
+    /// Topics the scene must never bring up, such as "violence"
+    pub banned_topics: Vec<String>,
+    /// Words that must never appear in a generated turn, matched without regard to case.
+    /// A word that is a single token is also banned during sampling, which blocks longer words
+    /// starting with that token too, such as "bloodhound" when "blood" is banned.
+    pub banned_words: Vec<String>,
+    /// Maximum number of tokens in a generated turn
+    pub max_line_tokens: Option<usize>,
+    /// The audience the content must be suitable for, or `None` for no restriction
+    pub rating: Option<Rating>,
+    /// Number of times a turn is generated before giving up on it
+    pub max_attempts: usize,
+}
+
+impl ContentConstraints {
+    /// Create constraints that don't restrict anything
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_banned_topic(mut self, topic: impl Into<String>) -> Self {
+        self.banned_topics.push(topic.into());
+        self
+    }
+
+    pub fn with_banned_word(mut self, word: impl Into<String>) -> Self {
+        self.banned_words.push(word.into());
+        self
+    }
+
+    pub fn with_max_line_tokens(mut self, max_line_tokens: impl Into<Option<usize>>) -> Self {
+        self.max_line_tokens = max_line_tokens.into();
+        self
+    }
+
+    pub fn with_rating(mut self, rating: impl Into<Option<Rating>>) -> Self {
+        self.rating = rating.into();
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Whether the constraints don't restrict anything
+    pub fn is_empty(&self) -> bool {
+        self.banned_topics.is_empty()
+            && self.banned_words.is_empty()
+            && self.max_line_tokens.is_none()
+            && self.rating.is_none()
+    }
+
+    /// Describe the constraints to the model, or `None` if there is nothing the model needs to be told
+    pub fn describe(&self) -> Option<String> {
+        let mut rules = Vec::new();
+        if let Some(rating) = self.rating {
+            rules.push(format!("Keep everything {}.", rating));
+        }
+        if !self.banned_topics.is_empty() {
+            rules.push(format!(
+                "Never mention {}.",
+                self.banned_topics.iter().join(", ")
+            ));
+        }
+
+        if rules.is_empty() {
+            None
+        } else {
+            Some(rules.join(" "))
+        }
+    }
+
+    /// Get the banned words that appear in `text`
+    pub fn banned_words_in(&self, text: impl AsRef<str>) -> Vec<&str> {
+        let words: Vec<String> = text
+            .as_ref()
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        self.banned_words
+            .iter()
+            .filter(|banned| {
+                // Banned phrases of several words are matched as a whole
+                let banned = banned.to_lowercase();
+                let banned_words: Vec<&str> = banned.split_whitespace().collect();
+                !banned_words.is_empty()
+                    && words
+                        .windows(banned_words.len())
+                        .any(|window| window.iter().zip(&banned_words).all(|(a, b)| a == b))
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Get the tokens that spell a banned word on their own, with or without a leading space and capital letter.
+    /// Sampling can't see what follows a token, so banning one also bans every word that starts with it.
+    pub(crate) fn banned_tokens(&self, model: &Model) -> Vec<u32> {
+        self.banned_words
+            .iter()
+            .flat_map(|word| {
+                let word = word.trim().to_lowercase();
+                let capitalized = word
+                    .chars()
+                    .take(1)
+                    .flat_map(char::to_uppercase)
+                    .chain(word.chars().skip(1))
+                    .collect::<String>();
+                [
+                    word.clone(),
+                    format!(" {}", word),
+                    capitalized.clone(),
+                    format!(" {}", capitalized),
+                ]
+            })
+            .filter_map(|variant| {
+                let tokens = model.tokenize_str(variant);
+                match tokens.as_slice() {
+                    [token] => Some(*token),
+                    _ => None,
+                }
+            })
+            .unique()
+            .collect()
+    }
+}
+
+impl Default for ContentConstraints {
+    fn default() -> Self {
+        Self {
+            banned_topics: Vec::new(),
+            banned_words: Vec::new(),
+            max_line_tokens: None,
+            rating: None,
+            max_attempts: 3,
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// A transient instruction from the director, such as "introduce a sudden storm".
+/// Directives are shown to the model before every inference until they are fulfilled or expire,
+/// and never enter the memory.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Directive {
+    pub instruction: String,
+    /// Number of inferred turns left before the directive expires, or `None` if it lasts until fulfilled
+    pub turns_left: Option<usize>,
+}
+
+impl Directive {
+    pub fn new(instruction: impl Into<String>) -> Self {
+        Self {
+            instruction: instruction.into(),
+            turns_left: None,
+        }
+    }
+
+    /// Expire the directive after `turns` inferred turns even if it wasn't fulfilled
+    pub fn within(mut self, turns: usize) -> Self {
+        self.turns_left = Some(turns);
+        self
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::Result;
+use itertools::Itertools;
+use serde::Serialize;
+
+use super::{Scene, SceneFormat, SceneTurn, SceneTurnType};
+
+/// A format a scene's transcript can be exported to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Every turn written the way the scene writes it in its memory, using the scene's `SceneFormat`
+    Transcript,
+    /// Screenplay-style text written in `SceneFormat::screenplay`, under the setting as a scene heading
+    Screenplay,
+    /// Markdown with italic narration and bold speaker names
+    Markdown,
+    /// Structured JSON with the setting, the characters and every turn
+    Json,
+}
+
+/// The structure of a scene exported as JSON
+#[derive(Serialize)]
+struct ExportedScene<'a> {
+    setting: &'a str,
+    characters: Vec<&'a str>,
+    turns: &'a [SceneTurn],
+}
+
+impl Scene {
+    /// Export the transcript of every turn of the scene in the given format
+    pub fn export(&self, format: ExportFormat) -> Result<String> {
+        match format {
+            ExportFormat::Transcript => Ok(self.export_with(&self.format)),
+            ExportFormat::Screenplay => Ok(format!(
+                "{}\n\n{}",
+                self.setting.to_uppercase(),
+                self.export_with(&SceneFormat::screenplay())
+            )),
+            ExportFormat::Markdown => Ok(self.export_markdown()),
+            ExportFormat::Json => {
+                let exported = ExportedScene {
+                    setting: &self.setting,
+                    characters: self
+                        .characters
+                        .iter()
+                        .map(|character| character.name.as_str())
+                        .collect(),
+                    turns: &self.turns,
+                };
+                Ok(serde_json::to_string_pretty(&exported)?)
+            }
+        }
+    }
+
+    /// Write every turn in a scene format, one after the other
+    fn export_with(&self, format: &SceneFormat) -> String {
+        self.turns.iter().map(|turn| format.render(turn)).collect()
+    }
+
+    fn export_markdown(&self) -> String {
+        // Open with the setting and the cast
+        let mut text = format!(
+            "# {}\n\n**Characters:** {}\n\n",
+            self.setting,
+            self.characters
+                .iter()
+                .map(|character| &character.name)
+                .join(", ")
+        );
+
+        for turn in self.turns.iter() {
+            let block = match &turn.turn_type {
+                SceneTurnType::Story(narration) => format!("*{}*", narration),
+                SceneTurnType::Dialogue(character, line) => {
+                    format!("**{}:** \"{}\"", character, line)
+                }
+                SceneTurnType::Action(character, action) => {
+                    format!("*{} {}*", character, action)
+                }
+                SceneTurnType::Player(action) => format!("> {}", action),
+                SceneTurnType::Recap(recap) => format!("**Previously:** {}", recap),
+            };
+            text.push_str(&block);
+            text.push_str("\n\n");
+        }
+
+        text
+    }
+}
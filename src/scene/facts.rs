@@ -0,0 +1,70 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// A fact about the world of a scene as a (subject, relation, object) triple,
+/// such as ("blacksmith", "owns", "enchanted hammer")
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fact {
+    pub subject: String,
+    pub relation: String,
+    pub object: String,
+    /// Index of the turn the fact was extracted from, if it came from a single turn
+    pub source_turn: Option<usize>,
+}
+
+impl Fact {
+    pub fn new(
+        subject: impl Into<String>,
+        relation: impl Into<String>,
+        object: impl Into<String>,
+    ) -> Self {
+        Self {
+            subject: subject.into(),
+            relation: relation.into(),
+            object: object.into(),
+            source_turn: None,
+        }
+    }
+
+    /// Parse `(subject, relation, object)` lines, ignoring lines that aren't in that form.
+    /// Every fact is attributed to `source_turn`.
+    pub fn parse_lines(lines: impl AsRef<str>, source_turn: Option<usize>) -> Vec<Self> {
+        lines
+            .as_ref()
+            .lines()
+            .filter_map(|line| {
+                // Strip list markers and the parentheses around the triple
+                let line = line.trim().trim_start_matches(['-', '*']).trim();
+                let line = line.strip_prefix('(')?.strip_suffix(')')?;
+
+                // Split the triple into exactly three non-empty parts
+                let parts: Vec<&str> = line
+                    .split(',')
+                    .map(|part| part.trim().trim_matches('"').trim())
+                    .collect();
+                let [subject, relation, object] = parts.as_slice() else {
+                    return None;
+                };
+                if subject.is_empty() || relation.is_empty() || object.is_empty() {
+                    return None;
+                }
+
+                Some(Self {
+                    source_turn,
+                    ..Self::new(
+                        subject.to_lowercase(),
+                        relation.to_lowercase(),
+                        object.to_lowercase(),
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+impl Display for Fact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {}, {})", self.subject, self.relation, self.object)
+    }
+}
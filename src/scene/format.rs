@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+
+use super::{SceneTurn, SceneTurnType};
+
+/// How turns are written in a scene's memory and prompts.
+/// Every turn is written as its opening delimiter, its text, its closing delimiter and a newline.
+/// `{name}` in a delimiter is replaced with the name of the character.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneFormat {
+    /// Explanation of the format given to the model at the start of the memory
+    pub description: String,
+    pub narration_open: String,
+    pub narration_close: String,
+    pub dialogue_open: String,
+    pub dialogue_close: String,
+    pub action_open: String,
+    pub action_close: String,
+    pub player_open: String,
+    pub player_close: String,
+    /// Strings that end every inferred turn in addition to its closing delimiter
+    pub stop: Vec<String>,
+}
+
+impl SceneFormat {
+    /// The screenplay format: speaker names in capitals on their own line and narration in parentheses
+    pub fn screenplay() -> Self {
+        Self {
+            description: "It is written as a screenplay. Narration is written in (parentheses) and dialogue is written as the speaker's name followed by their line.".to_string(),
+            narration_open: "(".to_string(),
+            narration_close: ")".to_string(),
+            dialogue_open: "{NAME}\n    ".to_string(),
+            dialogue_close: String::new(),
+            action_open: "({name} ".to_string(),
+            action_close: ")".to_string(),
+            player_open: "PLAYER\n    (".to_string(),
+            player_close: ")".to_string(),
+            stop: vec!["\n".to_string()],
+        }
+    }
+
+    /// Chat markup in which every turn is a message from the narrator, a character or the player
+    pub fn chat_markup() -> Self {
+        Self {
+            description: "Every turn is a message from the narrator, a character or the player."
+                .to_string(),
+            narration_open: "<|im_start|>narrator\n".to_string(),
+            narration_close: "<|im_end|>".to_string(),
+            dialogue_open: "<|im_start|>{name}\n".to_string(),
+            dialogue_close: "<|im_end|>".to_string(),
+            action_open: "<|im_start|>{name}\n*".to_string(),
+            action_close: "*<|im_end|>".to_string(),
+            player_open: "<|im_start|>player\n".to_string(),
+            player_close: "<|im_end|>".to_string(),
+            stop: vec!["<|im_start|>".to_string()],
+        }
+    }
+
+    /// Replace `{name}` and `{NAME}` in a delimiter with the name of a character
+    fn with_name(delimiter: &str, name: &str) -> String {
+        delimiter
+            .replace("{name}", name)
+            .replace("{NAME}", &name.to_uppercase())
+    }
+
+    /// Get the text a narration turn starts with
+    pub fn narration_prefix(&self) -> String {
+        self.narration_open.clone()
+    }
+
+    /// Get the text a line of dialogue by `name` starts with
+    pub fn dialogue_prefix(&self, name: impl AsRef<str>) -> String {
+        Self::with_name(&self.dialogue_open, name.as_ref())
+    }
+
+    /// Get the text an action by `name` starts with
+    pub fn action_prefix(&self, name: impl AsRef<str>) -> String {
+        Self::with_name(&self.action_open, name.as_ref())
+    }
+
+    /// Get the strings that end a turn with the given closing delimiter
+    pub(crate) fn stops(&self, close: &str) -> Vec<String> {
+        let mut stops = self.stop.clone();
+        if !close.is_empty() {
+            stops.insert(0, close.to_string());
+        }
+        stops
+    }
+
+    /// Write a turn the way it appears in the memory.
+    /// Recaps never enter the memory and are written the same way in every format.
+    pub fn render(&self, turn: &SceneTurn) -> String {
+        let (open, text, close) = match &turn.turn_type {
+            SceneTurnType::Story(text) => (self.narration_prefix(), text, &self.narration_close),
+            SceneTurnType::Dialogue(character, text) => {
+                (self.dialogue_prefix(character), text, &self.dialogue_close)
+            }
+            SceneTurnType::Action(character, text) => {
+                (self.action_prefix(character), text, &self.action_close)
+            }
+            SceneTurnType::Player(text) => (self.player_open.clone(), text, &self.player_close),
+            SceneTurnType::Recap(text) => return format!("(Previously: {})\n", text),
+        };
+        format!("{}{}{}\n", open, text, close)
+    }
+}
+
+impl Default for SceneFormat {
+    /// Narration in [square brackets], dialogue as Name: "line", actions as *Name does something*
+    /// and player actions as > action
+    fn default() -> Self {
+        Self {
+            description: "Narration is written in [square brackets] and dialogue is written as Name: \"line\", physical actions are written as *Name does something*. Actions taken by the player are written as > action.".to_string(),
+            narration_open: "[".to_string(),
+            narration_close: "]".to_string(),
+            dialogue_open: "{name}: \"".to_string(),
+            dialogue_close: "\"".to_string(),
+            action_open: "*{name} ".to_string(),
+            action_close: "*".to_string(),
+            player_open: "> ".to_string(),
+            player_close: String::new(),
+            stop: vec!["\n".to_string()],
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+/// A named style for a scene, bundling a description for the preamble with sampling parameters and stop strings that suit it.
+/// Use one of the built-in presets or create your own.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Genre {
+    pub name: String,
+    /// Told to the model in the preamble, such as "Brooding, rain-soaked crime story"
+    pub description: String,
+    pub temp: Option<f64>,
+    pub top_p: Option<f64>,
+    pub repeat_penalty: f32,
+    pub repeat_last_n: usize,
+    /// Extra strings that end every inferred turn
+    pub stop: Vec<String>,
+}
+
+impl Genre {
+    /// Create a genre with the default sampling parameters of a scene
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            temp: Some(0.5),
+            top_p: None,
+            repeat_penalty: 1.0,
+            repeat_last_n: 0,
+            stop: Vec::new(),
+        }
+    }
+
+    pub fn with_temp(mut self, temp: impl Into<Option<f64>>) -> Self {
+        self.temp = temp.into();
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: impl Into<Option<f64>>) -> Self {
+        self.top_p = top_p.into();
+        self
+    }
+
+    pub fn with_repeat_penalty(mut self, repeat_penalty: f32, repeat_last_n: usize) -> Self {
+        self.repeat_penalty = repeat_penalty;
+        self.repeat_last_n = repeat_last_n;
+        self
+    }
+
+    /// Add a string that ends every inferred turn
+    pub fn with_stop(mut self, stop: impl Into<String>) -> Self {
+        self.stop.push(stop.into());
+        self
+    }
+
+    /// Sweeping tales of magic, quests and ancient kingdoms
+    pub fn high_fantasy() -> Self {
+        Self::new(
+            "high fantasy",
+            "An epic tale of magic, ancient kingdoms, noble quests and mythical creatures, told with grandeur.",
+        )
+        .with_temp(0.6)
+        .with_top_p(0.95)
+        .with_repeat_penalty(1.1, 64)
+    }
+
+    /// Dread of vast, unknowable things beyond human understanding
+    pub fn cosmic_horror() -> Self {
+        Self::new(
+            "cosmic horror",
+            "A slow, creeping tale of dread in which the characters glimpse vast, unknowable horrors and their sanity frays.",
+        )
+        .with_temp(0.7)
+        .with_top_p(0.9)
+        .with_repeat_penalty(1.15, 64)
+    }
+
+    /// Light comedy of misunderstandings between lovable characters
+    pub fn sitcom() -> Self {
+        Self::new(
+            "sitcom",
+            "A light-hearted comedy full of witty banter, awkward misunderstandings and running jokes.",
+        )
+        .with_temp(0.8)
+        .with_top_p(0.95)
+        .with_repeat_penalty(1.1, 32)
+        .with_stop("(laughter)")
+    }
+
+    /// Cynical crime stories in rain-slicked cities
+    pub fn noir() -> Self {
+        Self::new(
+            "noir",
+            "A cynical crime story in a rain-slicked city of shadows, told in clipped, world-weary prose.",
+        )
+        .with_temp(0.5)
+        .with_top_p(0.9)
+        .with_repeat_penalty(1.1, 64)
+    }
+
+    /// Get every built-in preset
+    pub fn presets() -> Vec<Self> {
+        vec![
+            Self::high_fantasy(),
+            Self::cosmic_horror(),
+            Self::sitcom(),
+            Self::noir(),
+        ]
+    }
+
+    /// Find a built-in preset by name, ignoring case, such as "cosmic horror"
+    pub fn preset(name: impl AsRef<str>) -> Option<Self> {
+        let name = name.as_ref().trim();
+        Self::presets()
+            .into_iter()
+            .find(|genre| genre.name.eq_ignore_ascii_case(name))
+    }
+}
@@ -0,0 +1,120 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::VectorMemory;
+use crate::model::Model;
+use crate::token_string::TokenString;
+
+/// Decides when and how a `Scene` compresses its memory
+pub trait MemoryPolicy: Send + Sync {
+    /// Get the number of memory tokens above which the memory is compressed
+    fn threshold(&self, max_memory: usize) -> usize {
+        max_memory
+    }
+
+    /// Get the number of most recent turns that are kept verbatim in the short-term memory
+    /// instead of being compressed
+    fn keep_verbatim(&self) -> usize {
+        0
+    }
+
+    /// Compress older events into at most `max_tokens` tokens
+    fn compress(&self, events: &TokenString, max_tokens: usize, seed: u64) -> TokenString {
+        events.shortened(max_tokens, seed)
+    }
+}
+
+/// Paraphrase the whole memory once it exceeds the scene's maximum memory.
+/// This is the default policy.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ParaphraseAll;
+
+impl MemoryPolicy for ParaphraseAll {}
+
+/// Keep the most recent turns verbatim and only paraphrase older material,
+/// compressing once the memory exceeds `fraction` of the scene's maximum memory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeepRecent {
+    /// Number of most recent turns kept verbatim
+    pub turns: usize,
+    /// Fraction of the maximum memory at which compression starts
+    pub fraction: f32,
+}
+
+impl KeepRecent {
+    /// Keep the last `turns` turns verbatim and compress once the maximum memory is exceeded
+    pub fn new(turns: usize) -> Self {
+        Self {
+            turns,
+            fraction: 1.0,
+        }
+    }
+}
+
+impl MemoryPolicy for KeepRecent {
+    fn threshold(&self, max_memory: usize) -> usize {
+        (max_memory as f32 * self.fraction) as usize
+    }
+
+    fn keep_verbatim(&self) -> usize {
+        self.turns
+    }
+}
+
+/// Compressed turns kept verbatim in a vector memory so the most relevant ones
+/// can be brought back into the prompt
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recall {
+    pub memory: VectorMemory,
+    /// Number of turns recalled for every inference
+    pub top_k: usize,
+}
+
+impl Recall {
+    pub fn new(top_k: usize) -> Self {
+        Self {
+            memory: VectorMemory::new(),
+            top_k,
+        }
+    }
+
+    /// Get the `top_k` turns most relevant to the recent events that fit in `max_tokens` tokens
+    /// together, in the order they happened. Less relevant turns are left out first.
+    pub fn recall(
+        &self,
+        model: &Model,
+        recent: &TokenString,
+        max_tokens: usize,
+    ) -> Result<Vec<String>> {
+        if self.memory.is_empty() || recent.is_empty() || self.top_k == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Find the most relevant turns, skipping those that no longer fit
+        let query = model.embed(recent)?;
+        let mut tokens = 0;
+        let mut recalled: Vec<usize> = Vec::new();
+        for (entry, _) in self.memory.search_embedded(&query, self.top_k) {
+            let length = model.tokenize_str(format!("{}\n", entry.text)).len();
+            if tokens + length > max_tokens {
+                continue;
+            }
+            if let Some(index) = self
+                .memory
+                .entries()
+                .iter()
+                .position(|other| std::ptr::eq(entry, other))
+            {
+                tokens += length;
+                recalled.push(index);
+            }
+        }
+
+        // Put them back in the order they happened
+        recalled.sort_unstable();
+        Ok(recalled
+            .into_iter()
+            .map(|index| format!("{}\n", self.memory.entries()[index].text))
+            .collect())
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// How one character feels about another.
+/// Both scores range from -1 (hostile, distrustful) to 1 (fond, trusting).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Relationship {
+    pub affinity: f32,
+    pub trust: f32,
+}
+
+impl Relationship {
+    /// Create a relationship, clamping both scores to -1..=1
+    pub fn new(affinity: f32, trust: f32) -> Self {
+        Self {
+            affinity: affinity.clamp(-1.0, 1.0),
+            trust: trust.clamp(-1.0, 1.0),
+        }
+    }
+
+    /// Describe the relationship with verbs such as "dislikes and distrusts",
+    /// or `None` if it is neutral
+    pub fn describe(&self) -> Option<String> {
+        let affinity = match self.affinity {
+            affinity if affinity >= 0.6 => Some("adores"),
+            affinity if affinity >= 0.2 => Some("likes"),
+            affinity if affinity <= -0.6 => Some("hates"),
+            affinity if affinity <= -0.2 => Some("dislikes"),
+            _ => None,
+        };
+        let trust = match self.trust {
+            trust if trust >= 0.2 => Some("trusts"),
+            trust if trust <= -0.2 => Some("distrusts"),
+            _ => None,
+        };
+
+        match (affinity, trust) {
+            (Some(affinity), Some(trust)) => Some(format!("{} and {}", affinity, trust)),
+            (Some(verb), None) | (None, Some(verb)) => Some(verb.to_string()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// How every character of a scene feels about every other character.
+/// Relationships are directed, so Mira may trust Aldo while Aldo distrusts Mira.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Relationships {
+    pairs: BTreeMap<String, BTreeMap<String, Relationship>>,
+}
+
+impl Relationships {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get how `from` feels about `to`, which is neutral if it was never set
+    pub fn get(&self, from: impl AsRef<str>, to: impl AsRef<str>) -> Relationship {
+        self.pairs
+            .get(from.as_ref())
+            .and_then(|others| others.get(to.as_ref()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Set how `from` feels about `to`
+    pub fn set(
+        &mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+        relationship: Relationship,
+    ) {
+        self.pairs
+            .entry(from.into())
+            .or_default()
+            .insert(to.into(), relationship);
+    }
+
+    /// Change how `from` feels about `to` by the given amounts, returning the new relationship
+    pub fn adjust(
+        &mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+        affinity: f32,
+        trust: f32,
+    ) -> Relationship {
+        let (from, to) = (from.into(), to.into());
+        let current = self.get(&from, &to);
+        let adjusted = Relationship::new(current.affinity + affinity, current.trust + trust);
+        self.set(from, to, adjusted);
+        adjusted
+    }
+
+    /// Get an iterator over every relationship as `(from, to, relationship)`, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &Relationship)> {
+        self.pairs.iter().flat_map(|(from, others)| {
+            others
+                .iter()
+                .map(move |(to, relationship)| (from.as_str(), to.as_str(), relationship))
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Describe every relationship that isn't neutral, one line such as "Mira distrusts Aldo" per pair
+    pub fn to_lines(&self) -> String {
+        self.iter()
+            .filter_map(|(from, to, relationship)| {
+                relationship
+                    .describe()
+                    .map(|description| format!("{} {} {}\n", from, description, to))
+            })
+            .collect()
+    }
+
+    /// Apply `From -> To: affinity 0.2, trust -0.1` lines to the relationships as changes,
+    /// ignoring lines that aren't in that form or name a character that isn't in `characters`.
+    /// Returns the pairs whose relationship changed.
+    pub fn apply_lines(
+        &mut self,
+        lines: impl AsRef<str>,
+        characters: &[impl AsRef<str>],
+    ) -> Vec<(String, String)> {
+        let is_character = |name: &str| {
+            characters
+                .iter()
+                .any(|character| character.as_ref() == name)
+        };
+
+        let mut changed = Vec::new();
+        for line in lines.as_ref().lines() {
+            // Split the line into the pair and the changes
+            let Some((pair, changes)) = line.split_once(':') else {
+                continue;
+            };
+            let Some((from, to)) = pair.split_once("->") else {
+                continue;
+            };
+            let from = from.trim().trim_start_matches(['-', '*']).trim();
+            let to = to.trim();
+            if from == to || !is_character(from) || !is_character(to) {
+                continue;
+            }
+
+            // Read each score change as a name followed by a number
+            let (mut affinity, mut trust) = (0.0, 0.0);
+            for change in changes.split(',') {
+                let Some((score, amount)) = change.trim().split_once(char::is_whitespace) else {
+                    continue;
+                };
+                // Skip amounts like "NaN" or "inf", which would stick to the score forever
+                let Some(amount) = amount
+                    .trim()
+                    .trim_start_matches('+')
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|amount| amount.is_finite())
+                else {
+                    continue;
+                };
+                match score.to_lowercase().as_str() {
+                    "affinity" => affinity = amount,
+                    "trust" => trust = amount,
+                    _ => {}
+                }
+            }
+
+            if affinity != 0.0 || trust != 0.0 {
+                self.adjust(from, to, affinity, trust);
+                changed.push((from.to_string(), to.to_string()));
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+
+use super::Scene;
+
+/// Decides which character speaks next when `Scene::infer_any` generates dialogue
+pub trait TurnScheduler: Send + Sync {
+    /// Choose the name of the next speaker, or `None` if nobody should speak.
+    /// `seed` changes every turn and may be used for random choices.
+    fn next_speaker(&self, scene: &Scene, seed: u64) -> Option<String>;
+}
+
+/// Get the name after the last speaker in `order`, wrapping around, or `None` if that would be the last speaker again
+fn next_in_order<'a>(order: &[&'a str], last_speaker: Option<&str>) -> Option<&'a str> {
+    let next = match last_speaker.and_then(|last| order.iter().position(|name| *name == last)) {
+        Some(position) => order.get((position + 1) % order.len()).copied(),
+        None => order.first().copied(),
+    };
+    next.filter(|name| Some(*name) != last_speaker)
+}
+
+/// The speaker selection modes scenes had before turn schedulers, kept as schedulers of their own.
+/// The last speaker never speaks twice in a row.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpeakerSelection {
+    /// Pick a character based on the scene seed
+    #[default]
+    Seeded,
+    /// Pick the character who has gone the longest without speaking
+    LeastRecent,
+}
+
+impl TurnScheduler for SpeakerSelection {
+    fn next_speaker(&self, scene: &Scene, seed: u64) -> Option<String> {
+        match self {
+            Self::Seeded => {
+                let candidates: Vec<&str> = scene
+                    .characters()
+                    .iter()
+                    .map(|character| character.name.as_str())
+                    .filter(|name| Some(*name) != scene.last_speaker())
+                    .collect();
+                if candidates.is_empty() {
+                    return None;
+                }
+                Some(candidates[(seed % candidates.len() as u64) as usize].to_string())
+            }
+            Self::LeastRecent => LeastRecent.next_speaker(scene, seed),
+        }
+    }
+}
+
+/// Count how often `name` appears in `text` as a whole word, ignoring case
+pub(crate) fn count_mentions(text: &str, name: &str) -> u64 {
+    let (text, name) = (text.to_lowercase(), name.to_lowercase());
+    if name.is_empty() {
+        return 0;
+    }
+    text.match_indices(&name)
+        .filter(|(start, _)| {
+            let before = text[..*start].chars().next_back();
+            let after = text[start + name.len()..].chars().next();
+            !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+        })
+        .count() as u64
+}
+
+/// Characters take turns in the order they were added to the scene.
+/// This is the default scheduler.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RoundRobin;
+
+impl TurnScheduler for RoundRobin {
+    fn next_speaker(&self, scene: &Scene, _seed: u64) -> Option<String> {
+        let order: Vec<&str> = scene
+            .characters()
+            .iter()
+            .map(|character| character.name.as_str())
+            .collect();
+        next_in_order(&order, scene.last_speaker()).map(str::to_string)
+    }
+}
+
+/// Characters take turns in a fixed initiative order, such as in combat.
+/// Characters missing from the order or from the scene never speak.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Initiative {
+    pub order: Vec<String>,
+}
+
+impl Initiative {
+    pub fn new(order: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            order: order.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TurnScheduler for Initiative {
+    fn next_speaker(&self, scene: &Scene, _seed: u64) -> Option<String> {
+        let order: Vec<&str> = self
+            .order
+            .iter()
+            .map(String::as_str)
+            .filter(|name| scene.character(name).is_some())
+            .collect();
+        next_in_order(&order, scene.last_speaker()).map(str::to_string)
+    }
+}
+
+/// The character who has gone the longest without speaking or acting speaks next
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LeastRecent;
+
+impl TurnScheduler for LeastRecent {
+    fn next_speaker(&self, scene: &Scene, _seed: u64) -> Option<String> {
+        scene
+            .characters()
+            .iter()
+            .filter(|character| Some(character.name.as_str()) != scene.last_speaker())
+            .min_by_key(|character| {
+                scene
+                    .turns()
+                    .iter()
+                    .rposition(|turn| turn.character() == Some(character.name.as_str()))
+                    .map_or(0, |position| position + 1)
+            })
+            .map(|character| character.name.clone())
+    }
+}
+
+/// Characters are picked at random, weighted by how often they were mentioned by name by others in the last `window` turns,
+/// so conversation flows naturally toward whoever was just addressed. Names only count as whole words,
+/// so "Al" isn't mentioned by "Alice".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeightedByMentions {
+    pub window: usize,
+}
+
+impl WeightedByMentions {
+    pub fn new(window: usize) -> Self {
+        Self { window }
+    }
+}
+
+impl Default for WeightedByMentions {
+    fn default() -> Self {
+        Self { window: 4 }
+    }
+}
+
+impl TurnScheduler for WeightedByMentions {
+    fn next_speaker(&self, scene: &Scene, seed: u64) -> Option<String> {
+        // Every candidate gets one chance plus one per mention by someone else
+        let recent = scene.last_n(self.window);
+        let weighted: Vec<(&str, u64)> = scene
+            .characters()
+            .iter()
+            .map(|character| character.name.as_str())
+            .filter(|name| Some(*name) != scene.last_speaker())
+            .map(|name| {
+                let mentions = recent
+                    .iter()
+                    .filter(|turn| turn.character() != Some(name))
+                    .map(|turn| count_mentions(turn.text(), name))
+                    .sum::<u64>();
+                (name, 1 + mentions)
+            })
+            .collect();
+
+        // Pick a candidate in proportion to their weight
+        let total: u64 = weighted.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = seed % total;
+        for (name, weight) in weighted {
+            if roll < weight {
+                return Some(name.to_string());
+            }
+            roll -= weight;
+        }
+        None
+    }
+}
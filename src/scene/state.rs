@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::InferValue;
+
+/// Typed facts about the world of a scene, such as whether a door is locked.
+/// The state is shown to the model before every inference and can be updated from generated turns.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldState {
+    values: BTreeMap<String, InferValue>,
+}
+
+impl WorldState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the value of a key, returning the previous value if there was one
+    pub fn set(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<InferValue>,
+    ) -> Option<InferValue> {
+        self.values.insert(key.into(), value.into())
+    }
+
+    pub fn get(&self, key: impl AsRef<str>) -> Option<&InferValue> {
+        self.values.get(key.as_ref())
+    }
+
+    /// Remove a key, returning its value if there was one
+    pub fn remove(&mut self, key: impl AsRef<str>) -> Option<InferValue> {
+        self.values.remove(key.as_ref())
+    }
+
+    pub fn contains(&self, key: impl AsRef<str>) -> bool {
+        self.values.contains_key(key.as_ref())
+    }
+
+    /// Get an iterator over the keys and values, sorted by key
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &InferValue)> {
+        self.values.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Format the state as one `key: value` line per key
+    pub fn to_lines(&self) -> String {
+        self.values
+            .iter()
+            .map(|(key, value)| format!("{}: {}\n", key, value))
+            .collect()
+    }
+
+    /// Apply `key: value` lines to the state, ignoring lines that aren't in that form.
+    /// When `existing_only` is set, keys that aren't already in the state are ignored.
+    /// Keys that are already in the state keep their type, so values that don't parse as that type are ignored.
+    /// Returns the keys whose values changed.
+    pub fn apply_lines(&mut self, lines: impl AsRef<str>, existing_only: bool) -> Vec<String> {
+        let mut changed = Vec::new();
+        for line in lines.as_ref().lines() {
+            // Split the line into a key and a value
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().trim_start_matches(['-', '*']).trim();
+            if key.is_empty() || (existing_only && !self.contains(key)) {
+                continue;
+            }
+            let Ok(parsed) = value.parse::<InferValue>() else {
+                continue;
+            };
+            let value = match self.get(key) {
+                Some(existing) => match same_type(existing, value, parsed) {
+                    Some(value) => value,
+                    None => continue,
+                },
+                None => parsed,
+            };
+
+            // A NaN is never equal to itself, so it would count as a change every time
+            if matches!(value, InferValue::Float(f) if !f.is_finite()) {
+                continue;
+            }
+
+            // Only record keys whose value actually changed
+            if self.get(key) != Some(&value) {
+                self.set(key, value);
+                changed.push(key.to_string());
+            }
+        }
+        changed
+    }
+}
+
+/// Convert a value parsed from `raw` to the type of an existing value, or `None` if it isn't of that type.
+/// Any value can replace null, and integers are accepted where a float is expected.
+fn same_type(existing: &InferValue, raw: &str, parsed: InferValue) -> Option<InferValue> {
+    match (existing, parsed) {
+        (InferValue::Null, parsed) => Some(parsed),
+        (InferValue::String(_), InferValue::String(s)) => Some(InferValue::String(s)),
+        (InferValue::String(_), _) => Some(InferValue::String(raw.trim().to_string())),
+        (InferValue::Float(_), InferValue::Int(i)) => Some(InferValue::Float(i as f64)),
+        (existing, parsed)
+            if std::mem::discriminant(existing) == std::mem::discriminant(&parsed) =>
+        {
+            Some(parsed)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// The tense narration is written in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tense {
+    #[default]
+    Past,
+    Present,
+}
+
+/// Who narration is written from the perspective of
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PointOfView {
+    /// Told by a character as "I", naming the character
+    FirstPerson(String),
+    /// Told to the reader as "you"
+    SecondPerson,
+    #[default]
+    ThirdPerson,
+}
+
+/// How much detail narration goes into
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Verbosity {
+    Terse,
+    #[default]
+    Normal,
+    Verbose,
+}
+
+/// How a scene's narration is written
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NarrationStyle {
+    pub tense: Tense,
+    pub point_of_view: PointOfView,
+    /// The mood of the narration, such as "bleak" or "whimsical"
+    pub tone: Option<String>,
+    pub verbosity: Verbosity,
+}
+
+impl NarrationStyle {
+    pub fn with_tense(mut self, tense: Tense) -> Self {
+        self.tense = tense;
+        self
+    }
+
+    pub fn with_point_of_view(mut self, point_of_view: PointOfView) -> Self {
+        self.point_of_view = point_of_view;
+        self
+    }
+
+    pub fn with_tone(mut self, tone: impl Into<String>) -> Self {
+        self.tone = Some(tone.into());
+        self
+    }
+
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+}
+
+impl Display for NarrationStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Point of view and tense
+        match &self.point_of_view {
+            PointOfView::FirstPerson(narrator) => {
+                write!(f, "first person from {}'s perspective", narrator)?
+            }
+            PointOfView::SecondPerson => {
+                write!(f, "second person, addressing the reader as \"you\"")?
+            }
+            PointOfView::ThirdPerson => write!(f, "third person")?,
+        }
+        match self.tense {
+            Tense::Past => write!(f, ", past tense")?,
+            Tense::Present => write!(f, ", present tense")?,
+        }
+
+        // Tone and verbosity
+        if let Some(tone) = &self.tone {
+            write!(f, ", with a {} tone", tone)?;
+        }
+        match self.verbosity {
+            Verbosity::Terse => write!(f, ", in short terse sentences"),
+            Verbosity::Normal => Ok(()),
+            Verbosity::Verbose => write!(f, ", in rich descriptive detail"),
+        }
+    }
+}
+
+/// How much tension the story has, which steers narration toward an arc
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pacing {
+    #[default]
+    Calm,
+    Rising,
+    Climax,
+}
+
+impl Pacing {
+    /// Get the stage that follows this one, starting a new arc after the climax
+    pub fn next(self) -> Self {
+        match self {
+            Pacing::Calm => Pacing::Rising,
+            Pacing::Rising => Pacing::Climax,
+            Pacing::Climax => Pacing::Calm,
+        }
+    }
+}
+
+impl Display for Pacing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pacing::Calm => write!(
+                f,
+                "the pace is calm, so linger on quiet details and let the characters breathe"
+            ),
+            Pacing::Rising => write!(
+                f,
+                "tension is rising, so introduce complications and raise the stakes"
+            ),
+            Pacing::Climax => write!(
+                f,
+                "this is the climax, so bring the conflict to a head with decisive, dramatic events"
+            ),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// The overall feeling of a text
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Sentiment {
+    Positive,
+    Neutral,
+    Negative,
+}
+
+/// How likely the model finds each sentiment for a text. The probabilities sum to 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SentimentScore {
+    pub positive: f32,
+    pub neutral: f32,
+    pub negative: f32,
+}
+
+impl SentimentScore {
+    /// How positive the text is, from -1.0 (entirely negative) to 1.0 (entirely positive)
+    pub fn polarity(&self) -> f32 {
+        self.positive - self.negative
+    }
+
+    /// The most likely sentiment
+    pub fn sentiment(&self) -> Sentiment {
+        if self.positive >= self.neutral && self.positive >= self.negative {
+            Sentiment::Positive
+        } else if self.negative >= self.neutral {
+            Sentiment::Negative
+        } else {
+            Sentiment::Neutral
+        }
+    }
+}
+
+impl Model {
+    /// Score how positive, neutral or negative the text is, using `classify_scored`
+    pub fn sentiment(&self, text: impl AsRef<str>) -> SentimentScore {
+        let mut score = SentimentScore::default();
+        for (label, probability) in self.classify_scored(
+            text,
+            ["positive", "neutral", "negative"],
+            &GenerationConfig::default(),
+        ) {
+            match label {
+                "positive" => score.positive = probability,
+                "neutral" => score.neutral = probability,
+                _ => score.negative = probability,
+            }
+        }
+        score
+    }
+
+    /// Get the probability that the text is toxic (offensive, hateful or abusive), from 0.0 to 1.0,
+    /// using `classify_scored`. Returns 0.0 if the text can't be scored.
+    pub fn toxicity(&self, text: impl AsRef<str>) -> f32 {
+        self.classify_scored(text, ["toxic", "harmless"], &GenerationConfig::default())
+            .into_iter()
+            .find(|(label, _)| *label == "toxic")
+            .map_or(0.0, |(_, probability)| probability)
+    }
+}
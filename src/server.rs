@@ -0,0 +1,447 @@
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::generation::{context_room, Completion, FinishReason, GenerationConfig};
+use crate::model::{Model, MODEL_REPO};
+
+/// Default maximum number of tokens generated when a request doesn't say
+pub const DEFAULT_SERVER_TOKENS: usize = 256;
+/// Number of streamed chunks buffered before generation waits for the client
+const STREAM_BUFFER: usize = 32;
+
+/// Options for the OpenAI-compatible server
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Name of the model reported to clients
+    pub model_name: String,
+    /// Maximum number of completions generated at once; further requests wait for a free slot
+    pub max_concurrent: usize,
+    /// Maximum number of tokens generated when a request doesn't set `max_tokens`
+    pub default_max_tokens: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            model_name: MODEL_REPO.to_string(),
+            max_concurrent: 1,
+            default_max_tokens: DEFAULT_SERVER_TOKENS,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_model_name(mut self, model_name: impl Into<String>) -> Self {
+        self.model_name = model_name.into();
+        self
+    }
+
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = max_concurrent.max(1);
+        self
+    }
+
+    pub fn with_default_max_tokens(mut self, default_max_tokens: usize) -> Self {
+        self.default_max_tokens = default_max_tokens;
+        self
+    }
+}
+
+/// A single string or a list of strings, as OpenAI accepts for prompts and stop sequences
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(text) => vec![text],
+            OneOrMany::Many(texts) => texts,
+        }
+    }
+}
+
+/// Sampling options shared by completion and chat completion requests.
+/// Options phi-rs doesn't support, such as `frequency_penalty`, are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct SamplingOptions {
+    #[serde(alias = "max_completion_tokens")]
+    pub max_tokens: Option<usize>,
+    /// Sampling temperature, where 0 samples greedily. Defaults to 1 like OpenAI.
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    /// Seed to sample with; requests without one get a seed of their own
+    pub seed: Option<u64>,
+    pub stop: Option<OneOrMany>,
+    /// Send the completion as server-sent events while it is generated
+    #[serde(default)]
+    pub stream: bool,
+}
+
+/// Body of a request to `/v1/completions`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct CompletionRequest {
+    pub model: Option<String>,
+    pub prompt: OneOrMany,
+    #[serde(flatten)]
+    pub options: SamplingOptions,
+}
+
+/// A message in a chat completion request
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatCompletionMessage {
+    /// "system", "user" or "assistant"
+    pub role: String,
+    pub content: String,
+}
+
+/// Body of a request to `/v1/chat/completions`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub model: Option<String>,
+    pub messages: Vec<ChatCompletionMessage>,
+    #[serde(flatten)]
+    pub options: SamplingOptions,
+}
+
+/// Count the tokens used by the completions
+fn usage(completions: &[Completion]) -> Value {
+    let prompt_tokens: usize = completions.iter().map(|c| c.prompt_tokens).sum();
+    let completion_tokens: usize = completions.iter().map(|c| c.completion_tokens).sum();
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+/// An error returned to the client in OpenAI's error format
+struct ApiError(StatusCode, String);
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        ApiError(StatusCode::BAD_REQUEST, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let kind = if self.0.is_server_error() {
+            "server_error"
+        } else {
+            "invalid_request_error"
+        };
+        let body = json!({ "error": { "message": self.1, "type": kind } });
+        (self.0, Json(body)).into_response()
+    }
+}
+
+#[derive(Clone)]
+struct ServerState {
+    model: Model,
+    config: Arc<ServerConfig>,
+    /// Limits how many completions are generated at once
+    permits: Arc<Semaphore>,
+    /// Number of requests so far, used for ids and default seeds
+    requests: Arc<AtomicU64>,
+}
+
+impl ServerState {
+    /// Start handling a request, returning its number
+    fn next_request(&self) -> u64 {
+        self.requests.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Convert the request's sampling options into a generation config for the prompt.
+    /// `max_tokens` is lowered to the room the prompt leaves in the context window, so the completion
+    /// finishes with "length" there. Returns an error if the prompt doesn't fit in the context window.
+    fn generation_config(
+        &self,
+        options: &SamplingOptions,
+        request: u64,
+        prompt: &str,
+    ) -> Result<GenerationConfig, ApiError> {
+        let room = context_room(self.model.tokenize_str(prompt).len())?;
+        let max_tokens = options
+            .max_tokens
+            .unwrap_or(self.config.default_max_tokens)
+            .min(room);
+        let temp = options.temperature.unwrap_or(1.0);
+        let mut config = GenerationConfig::new(options.seed.unwrap_or(request))
+            .with_temp((temp > 0.0).then_some(temp))
+            .with_top_p(options.top_p.filter(|top_p| *top_p < 1.0))
+            .with_max_tokens(max_tokens);
+        for stop in options
+            .stop
+            .clone()
+            .map(OneOrMany::into_vec)
+            .unwrap_or_default()
+        {
+            config = config.with_stop(stop);
+        }
+        Ok(config)
+    }
+
+    /// Generate a completion of the prompt on a blocking thread, once a slot is free
+    async fn complete(
+        &self,
+        prompt: String,
+        config: GenerationConfig,
+    ) -> Result<Completion, ApiError> {
+        let permit = self.permits.clone().acquire_owned().await.unwrap();
+        let model = self.model.clone();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            model.generate_streamed(prompt, &config, |_| true)
+        })
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(ApiError::from)
+    }
+
+    /// Stream a completion of the prompt as server-sent events. `chunk` turns the text generated since
+    /// the last event, or the reason the completion finished, into the JSON of an event.
+    async fn stream(
+        &self,
+        prompt: String,
+        config: GenerationConfig,
+        chunk: impl Fn(Option<&str>, Option<FinishReason>) -> Value + Send + 'static,
+    ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let permit = self.permits.clone().acquire_owned().await.unwrap();
+        let model = self.model.clone();
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+
+            // Stop generating if the client disconnects
+            let result = model.generate_streamed(prompt, &config, |text| {
+                sender
+                    .blocking_send(chunk(Some(text), None).to_string())
+                    .is_ok()
+            });
+            let last = match result {
+                Ok(completion) => chunk(None, Some(completion.finish_reason)),
+                Err(e) => {
+                    json!({ "error": { "message": e.to_string(), "type": "invalid_request_error" } })
+                }
+            };
+            let _ = sender.blocking_send(last.to_string());
+            let _ = sender.blocking_send("[DONE]".to_string());
+        });
+
+        let events = stream::unfold(receiver, |mut receiver| async move {
+            let data = receiver.recv().await?;
+            Some((Ok(Event::default().data(data)), receiver))
+        });
+        Sse::new(events).keep_alive(KeepAlive::default())
+    }
+}
+
+/// Create a router serving the OpenAI-compatible `/v1/completions`, `/v1/chat/completions`
+/// and `/v1/models` endpoints with the model
+pub fn router(model: Model, config: ServerConfig) -> Router {
+    let state = ServerState {
+        model,
+        permits: Arc::new(Semaphore::new(config.max_concurrent.max(1))),
+        config: Arc::new(config),
+        requests: Arc::new(AtomicU64::new(0)),
+    };
+    Router::new()
+        .route("/v1/models", get(models))
+        .route("/v1/completions", post(completions))
+        .route("/v1/chat/completions", post(chat_completions))
+        .with_state(state)
+}
+
+/// Serve the OpenAI-compatible endpoints on the address, such as "127.0.0.1:8080", until the server fails
+pub async fn serve(model: Model, config: ServerConfig, address: impl ToSocketAddrs) -> Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    axum::serve(listener, router(model, config)).await?;
+    Ok(())
+}
+
+async fn models(State(state): State<ServerState>) -> Json<Value> {
+    Json(json!({
+        "object": "list",
+        "data": [{
+            "id": state.config.model_name,
+            "object": "model",
+            "created": 0,
+            "owned_by": "phi-rs",
+        }],
+    }))
+}
+
+async fn completions(
+    State(state): State<ServerState>,
+    Json(request): Json<CompletionRequest>,
+) -> Result<Response, ApiError> {
+    let number = state.next_request();
+    let id = format!("cmpl-{}", number);
+    let created = unix_time();
+    let model_name = request
+        .model
+        .unwrap_or_else(|| state.config.model_name.clone());
+    let prompts = request.prompt.into_vec();
+
+    if request.options.stream {
+        let [prompt] = <[String; 1]>::try_from(prompts).map_err(|_| {
+            ApiError(
+                StatusCode::BAD_REQUEST,
+                "streaming supports a single prompt".to_string(),
+            )
+        })?;
+        let config = state.generation_config(&request.options, number, &prompt)?;
+        let sse = state
+            .stream(prompt, config, move |text, finish_reason| {
+                json!({
+                    "id": id,
+                    "object": "text_completion",
+                    "created": created,
+                    "model": model_name,
+                    "choices": [{
+                        "index": 0,
+                        "text": text.unwrap_or_default(),
+                        "logprobs": null,
+                        "finish_reason": finish_reason,
+                    }],
+                })
+            })
+            .await;
+        return Ok(sse.into_response());
+    }
+
+    // Complete each prompt in turn
+    let mut completions = Vec::with_capacity(prompts.len());
+    for prompt in prompts {
+        let config = state.generation_config(&request.options, number, &prompt)?;
+        completions.push(state.complete(prompt, config).await?);
+    }
+    let choices: Vec<Value> = completions
+        .iter()
+        .enumerate()
+        .map(|(index, completion)| {
+            json!({
+                "index": index,
+                "text": completion.text,
+                "logprobs": null,
+                "finish_reason": completion.finish_reason,
+            })
+        })
+        .collect();
+    Ok(Json(json!({
+        "id": id,
+        "object": "text_completion",
+        "created": created,
+        "model": model_name,
+        "choices": choices,
+        "usage": usage(&completions),
+    }))
+    .into_response())
+}
+
+async fn chat_completions(
+    State(state): State<ServerState>,
+    Json(request): Json<ChatCompletionRequest>,
+) -> Result<Response, ApiError> {
+    let number = state.next_request();
+    let id = format!("chatcmpl-{}", number);
+    let created = unix_time();
+    let model_name = request
+        .model
+        .unwrap_or_else(|| state.config.model_name.clone());
+    let prompt = chat_prompt(&request.messages)?;
+
+    // Stop before the model writes the next message itself
+    let config = state
+        .generation_config(&request.options, number, &prompt)?
+        .with_stop("###");
+
+    if request.options.stream {
+        let sse = state
+            .stream(prompt, config, move |text, finish_reason| {
+                let delta = match text {
+                    Some(text) => json!({ "role": "assistant", "content": text }),
+                    None => json!({}),
+                };
+                json!({
+                    "id": id,
+                    "object": "chat.completion.chunk",
+                    "created": created,
+                    "model": model_name,
+                    "choices": [{
+                        "index": 0,
+                        "delta": delta,
+                        "finish_reason": finish_reason,
+                    }],
+                })
+            })
+            .await;
+        return Ok(sse.into_response());
+    }
+
+    let completion = state.complete(prompt, config).await?;
+    Ok(Json(json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": created,
+        "model": model_name,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": completion.text.trim() },
+            "finish_reason": completion.finish_reason,
+        }],
+        "usage": usage(std::slice::from_ref(&completion)),
+    }))
+    .into_response())
+}
+
+/// Write the chat messages in the model's instruction format, ending where the assistant's reply starts.
+/// Returns an error if there are no messages or a message has an unknown role.
+pub fn chat_prompt(messages: &[ChatCompletionMessage]) -> Result<String> {
+    if messages.is_empty() {
+        bail!("a chat completion needs at least one message")
+    }
+
+    let mut prompt = String::new();
+    for message in messages {
+        let section = match message.role.as_str() {
+            "system" | "developer" => "System",
+            "user" => "Instruction",
+            "assistant" => "Response",
+            role => bail!("unknown message role {:?}", role),
+        };
+        prompt.push_str(&format!("### {}:\n{}\n", section, message.content.trim()));
+    }
+    prompt.push_str("### Response:\n");
+
+    Ok(prompt)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
@@ -0,0 +1,158 @@
+use anyhow::Result;
+use itertools::Itertools;
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+use crate::scene::{Character, Scene};
+
+/// Maximum number of tokens in the summary of a finished scene
+pub const MAX_SCENE_SUMMARY_TOKENS: usize = 128;
+/// Maximum number of tokens of context carried from earlier scenes into a new one
+pub const MAX_CARRIED_CONTEXT_TOKENS: usize = 256;
+
+/// A narrative made of several scenes sharing a cast and a synopsis.
+/// When a new scene starts, the previous one is summarized and the story so far is carried into the new scene.
+pub struct Story {
+    model: Model,
+    synopsis: String,
+    cast: Vec<Character>,
+    scenes: Vec<Scene>,
+    /// Summary of each finished scene, in order
+    scene_summaries: Vec<String>,
+    config: GenerationConfig,
+}
+
+impl Story {
+    pub fn new(model: Model, synopsis: impl Into<String>) -> Self {
+        Self {
+            model,
+            synopsis: synopsis.into(),
+            cast: Vec::new(),
+            scenes: Vec::new(),
+            scene_summaries: Vec::new(),
+            config: GenerationConfig::default().with_temp(0.5),
+        }
+    }
+
+    /// Set the default generation parameters of new scenes
+    pub fn with_config(mut self, config: GenerationConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Add a character to the cast, replacing any character with the same name
+    pub fn add_character(&mut self, character: impl Into<Character>) {
+        let character = character.into();
+        match self
+            .cast
+            .iter_mut()
+            .find(|other| other.name == character.name)
+        {
+            Some(existing) => *existing = character,
+            None => self.cast.push(character),
+        }
+    }
+
+    pub fn synopsis(&self) -> &str {
+        &self.synopsis
+    }
+
+    pub fn cast(&self) -> &[Character] {
+        &self.cast
+    }
+
+    /// Get every scene of the story, oldest first
+    pub fn scenes(&self) -> &[Scene] {
+        &self.scenes
+    }
+
+    /// Get the summaries of the finished scenes, oldest first
+    pub fn scene_summaries(&self) -> &[String] {
+        &self.scene_summaries
+    }
+
+    /// Get the scene currently being played
+    pub fn current_scene(&self) -> Option<&Scene> {
+        self.scenes.last()
+    }
+
+    pub fn current_scene_mut(&mut self) -> Option<&mut Scene> {
+        self.scenes.last_mut()
+    }
+
+    /// Finish the current scene, if any, and start a new one with the named characters.
+    /// Characters that aren't in the cast yet are added to it.
+    /// The new scene starts with the synopsis, a summary of the earlier scenes and the facts pinned in the previous scene.
+    pub fn new_scene(
+        &mut self,
+        setting: impl Into<String>,
+        characters: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<&mut Scene> {
+        // Summarize the scene that is ending
+        let mut facts = Vec::new();
+        if let Some(previous) = self.scenes.last_mut() {
+            let summary = previous.summarize(MAX_SCENE_SUMMARY_TOKENS);
+            if !summary.is_empty() {
+                self.scene_summaries.push(summary);
+            }
+            facts = previous.facts().to_vec();
+        }
+
+        // Look the characters up in the cast
+        let characters: Vec<Character> = characters
+            .into_iter()
+            .map(|name| {
+                let name = name.as_ref();
+                if self.cast.iter().all(|character| character.name != name) {
+                    self.cast.push(Character::new(name));
+                }
+                self.cast
+                    .iter()
+                    .find(|character| character.name == name)
+                    .unwrap()
+                    .clone()
+            })
+            .collect();
+
+        // Create the scene, continuing the seed from the previous scene
+        let seed = self
+            .scenes
+            .last()
+            .map_or(self.config.seed, |previous| previous.config().seed);
+        let mut scene = Scene::builder(self.model.clone(), setting)
+            .characters(characters)
+            .config(self.config.clone().with_seed(seed))
+            .build()?;
+
+        // Carry the story so far into the new scene
+        let context = self.context(seed);
+        scene.set_summary(context);
+        for fact in facts {
+            scene.pin_fact(fact);
+        }
+
+        self.scenes.push(scene);
+        Ok(self.scenes.last_mut().unwrap())
+    }
+
+    /// Describe the story so far, shortened to fit the carried context budget
+    fn context(&self, seed: u64) -> String {
+        let mut context = format!("Synopsis: {}", self.synopsis);
+        if !self.scene_summaries.is_empty() {
+            context.push_str(&format!(
+                "\nThe story so far: {}",
+                self.scene_summaries.iter().join(" ")
+            ));
+        }
+
+        // Shorten the context if it has grown too long
+        let tokens = self.model.tokenize(&context);
+        if tokens.len() > MAX_CARRIED_CONTEXT_TOKENS {
+            tokens
+                .shortened(MAX_CARRIED_CONTEXT_TOKENS, seed)
+                .to_string()
+        } else {
+            context
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Maximum number of tokens of text summarized in one prompt.
+/// Longer texts are split into chunks that are summarized separately and then merged.
+pub const SUMMARY_CHUNK_TOKENS: usize = 1024;
+/// Maximum number of tokens in the summary of each chunk of a long text
+pub const CHUNK_SUMMARY_TOKENS: usize = 256;
+
+/// How long a summary should be
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SummaryLength {
+    /// A single sentence
+    Sentence,
+    /// A few sentences
+    #[default]
+    Short,
+    /// A full paragraph
+    Paragraph,
+    /// At most this many tokens
+    Tokens(usize),
+}
+
+impl SummaryLength {
+    /// Maximum number of tokens in a summary of this length
+    pub fn max_tokens(&self) -> usize {
+        match self {
+            SummaryLength::Sentence => 48,
+            SummaryLength::Short => 128,
+            SummaryLength::Paragraph => 256,
+            SummaryLength::Tokens(max_tokens) => *max_tokens,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            SummaryLength::Sentence => " in a single sentence",
+            SummaryLength::Short => " in a few sentences",
+            SummaryLength::Paragraph => " in a paragraph",
+            SummaryLength::Tokens(_) => "",
+        }
+    }
+}
+
+/// How a summary should be written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SummaryStyle {
+    /// Plain sentences
+    #[default]
+    Prose,
+    /// A list of points, each on its own line starting with "- "
+    Bullets,
+    /// A short title-like line
+    Headline,
+}
+
+/// Options for `Model::summarize`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SummaryOptions {
+    pub length: SummaryLength,
+    pub style: SummaryStyle,
+    /// What the summary should concentrate on, such as "the characters' motives"
+    pub focus: Option<String>,
+    /// Seed of every summary, including the partial summaries of long texts
+    pub seed: u64,
+}
+
+impl SummaryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_length(mut self, length: SummaryLength) -> Self {
+        self.length = length;
+        self
+    }
+
+    pub fn with_style(mut self, style: SummaryStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn with_focus(mut self, focus: impl Into<Option<String>>) -> Self {
+        self.focus = focus.into();
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+impl Model {
+    /// Summarize the text. Texts longer than `SUMMARY_CHUNK_TOKENS` are split into chunks at line breaks
+    /// where possible, each chunk is summarized, and the partial summaries are merged into the final summary.
+    /// Blank text has nothing to summarize and is an error.
+    pub fn summarize(&self, text: impl AsRef<str>, options: SummaryOptions) -> Result<String> {
+        let text = text.as_ref().trim();
+        if text.is_empty() {
+            bail!("cannot summarize empty text")
+        }
+
+        // Summarize each chunk separately until everything fits in one prompt
+        let chunk_options = SummaryOptions {
+            length: SummaryLength::Tokens(CHUNK_SUMMARY_TOKENS),
+            style: SummaryStyle::Prose,
+            ..options.clone()
+        };
+        let mut text = text.to_string();
+        while self.tokenize_str(&text).len() > SUMMARY_CHUNK_TOKENS {
+            let chunks = self.split_into_chunks(&text);
+            let mut partial_summaries = Vec::with_capacity(chunks.len());
+            for chunk in chunks {
+                partial_summaries.push(self.summarize_chunk(&chunk, &chunk_options)?);
+            }
+            text = partial_summaries.join("\n");
+        }
+
+        self.summarize_chunk(&text, &options)
+    }
+
+    /// Summarize text that fits in one prompt
+    fn summarize_chunk(&self, text: &str, options: &SummaryOptions) -> Result<String> {
+        // Describe the summary wanted
+        let mut instruction = match options.style {
+            SummaryStyle::Prose => "Summarize the text".to_string(),
+            SummaryStyle::Bullets => "Summarize the text as a list of key points".to_string(),
+            SummaryStyle::Headline => "Write a short headline for the text".to_string(),
+        };
+        if options.style != SummaryStyle::Headline {
+            instruction.push_str(options.length.describe());
+        }
+        if let Some(focus) = &options.focus {
+            instruction.push_str(&format!(", focusing on {}", focus));
+        }
+        instruction.push('.');
+
+        // Give the model the text, starting the list off for bullet points
+        let mut extra = HashMap::new();
+        extra.insert("Text", text);
+        if options.style == SummaryStyle::Bullets {
+            extra.insert("Response", "- ");
+        }
+
+        // Summarize until the next section or the token limit
+        let mut config = GenerationConfig::new(options.seed)
+            .with_temp(0.3)
+            .with_max_tokens(options.length.max_tokens())
+            .with_stop("###");
+        if options.style == SummaryStyle::Headline {
+            config = config.with_stop("\n");
+        }
+        let summary = self.instruct_with(instruction, Some(&extra), &config)?;
+
+        if options.style == SummaryStyle::Bullets {
+            Ok(format!("- {}", summary.trim()))
+        } else {
+            Ok(summary.trim().to_string())
+        }
+    }
+
+    /// Split the text into chunks of at most `SUMMARY_CHUNK_TOKENS` tokens, breaking at lines where possible
+    pub(crate) fn split_into_chunks(&self, text: &str) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let mut chunk = String::new();
+        let mut chunk_tokens = 0;
+        for line in text.lines() {
+            let line_tokens = self.tokenize_str(line);
+
+            // Lines too long for a chunk of their own are split between tokens
+            if line_tokens.len() > SUMMARY_CHUNK_TOKENS {
+                if !chunk.is_empty() {
+                    chunks.push(std::mem::take(&mut chunk));
+                    chunk_tokens = 0;
+                }
+                for tokens in line_tokens.as_slice().chunks(SUMMARY_CHUNK_TOKENS) {
+                    chunks.push(self.detokenize(tokens));
+                }
+                continue;
+            }
+
+            // Start a new chunk when the line doesn't fit
+            if chunk_tokens + line_tokens.len() > SUMMARY_CHUNK_TOKENS {
+                chunks.push(std::mem::take(&mut chunk));
+                chunk_tokens = 0;
+            }
+            chunk.push_str(line);
+            chunk.push('\n');
+            chunk_tokens += line_tokens.len() + 1;
+        }
+        if !chunk.trim().is_empty() {
+            chunks.push(chunk);
+        }
+
+        chunks
+    }
+}
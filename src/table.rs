@@ -0,0 +1,308 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::character::{check_fields, CharacterField, FieldValue};
+use crate::generation::GenerationConfig;
+use crate::model::{Model, MAX_TOKENS};
+
+/// Maximum number of tokens in one row of a generated table
+pub const MAX_ROW_TOKENS: usize = 96;
+/// Largest weight a generated row can have
+pub const MAX_ROW_WEIGHT: u32 = 5;
+/// How many times more rows than requested `Model::generate_table` may try before giving up
+pub const ROW_ATTEMPTS_PER_ROW: usize = 3;
+
+/// A row of a random table and how likely it is to be rolled
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableRow {
+    pub weight: u32,
+    /// One value for every column of the table
+    pub values: Vec<String>,
+}
+
+/// A weighted random table, such as "d20 swamp encounters"
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Table {
+    pub theme: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<TableRow>,
+}
+
+impl Table {
+    /// The sum of the weights of every row, which is the size of the die the table is rolled with
+    pub fn total_weight(&self) -> u32 {
+        self.rows.iter().map(|row| row.weight.max(1)).sum()
+    }
+
+    /// The range of die results each row covers, starting at 1
+    pub fn die_ranges(&self) -> Vec<(u32, u32)> {
+        let mut start = 1;
+        self.rows
+            .iter()
+            .map(|row| {
+                let range = (start, start + row.weight.max(1) - 1);
+                start += row.weight.max(1);
+                range
+            })
+            .collect()
+    }
+
+    /// Get the row covering a die result from 1 to `total_weight`
+    pub fn row_for(&self, die_result: u32) -> Option<&TableRow> {
+        self.die_ranges()
+            .into_iter()
+            .position(|(start, end)| (start..=end).contains(&die_result))
+            .map(|index| &self.rows[index])
+    }
+
+    /// Roll on the table, with rows chosen in proportion to their weights
+    pub fn roll(&self, seed: u64) -> Option<&TableRow> {
+        let total_weight = self.total_weight();
+        if total_weight == 0 {
+            return None;
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.row_for(rng.gen_range(1..=total_weight))
+    }
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total_weight = self.total_weight();
+        writeln!(f, "d{} | {}", total_weight, self.columns.join(" | "))?;
+        for ((start, end), row) in self.die_ranges().into_iter().zip(&self.rows) {
+            if start == end {
+                write!(f, "{}", start)?;
+            } else {
+                write!(f, "{}-{}", start, end)?;
+            }
+            writeln!(f, " | {}", row.values.join(" | "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A column of the rows made by `Model::generate_rows`.
+/// Columns hold the same kinds of values as the fields of a character sheet.
+pub type Column = CharacterField;
+
+/// A row made by `Model::generate_rows`, with a value for every column in order
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Row {
+    pub values: Vec<(String, FieldValue)>,
+}
+
+impl Row {
+    /// Get the value of a column by name, ignoring case
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&FieldValue> {
+        let name = name.as_ref();
+        self.values
+            .iter()
+            .find(|(column, _)| column.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+}
+
+impl Model {
+    /// Generate a weighted random table with `n_rows` rows and the given columns, such as
+    /// `generate_table("swamp encounters", 10, ["Encounter", "Description"])`.
+    /// Returns an error if there are no rows or columns, or if no valid rows could be generated.
+    pub fn generate_table(
+        &self,
+        theme: impl AsRef<str>,
+        n_rows: usize,
+        columns: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<Table> {
+        self.generate_table_with(theme, n_rows, columns, &GenerationConfig::new(0))
+    }
+
+    /// Generate a weighted random table like `generate_table`, sampling with the config.
+    /// The temperature defaults to 0.8 so that rows don't repeat.
+    pub fn generate_table_with(
+        &self,
+        theme: impl AsRef<str>,
+        n_rows: usize,
+        columns: impl IntoIterator<Item = impl AsRef<str>>,
+        config: &GenerationConfig,
+    ) -> Result<Table> {
+        let theme = theme.as_ref().trim().to_string();
+        let columns = columns
+            .into_iter()
+            .map(|column| column.as_ref().trim().to_string())
+            .collect_vec();
+        if n_rows == 0 {
+            bail!("a table needs at least one row")
+        }
+        if columns.is_empty() {
+            bail!("a table needs at least one column")
+        }
+
+        // Describe the table and start it with its header
+        let instruction = format!(
+            "Write a random table of {} for a tabletop role-playing game, one row per line. \
+            Start each row with a weight from 1 to {}, where common rows have higher weights.",
+            theme, MAX_ROW_WEIGHT
+        );
+        let header = format!("Weight | {}\n", columns.join(" | "));
+
+        // Generate one row at a time so each can be checked
+        let mut rows: Vec<TableRow> = Vec::with_capacity(n_rows);
+        let mut seen = HashSet::new();
+        let mut seed = config.seed;
+        let mut hidden = 0;
+        for _ in 0..n_rows.saturating_mul(ROW_ATTEMPTS_PER_ROW) {
+            if rows.len() >= n_rows {
+                break;
+            }
+
+            // Show the rows so far, leaving out the oldest ones if the prompt would leave no room for another row
+            let mut extra = HashMap::new();
+            loop {
+                let response = rows[hidden..]
+                    .iter()
+                    .fold(header.clone(), |mut response, row| {
+                        response.push_str(&format!(
+                            "{} | {}\n",
+                            row.weight,
+                            row.values.join(" | ")
+                        ));
+                        response
+                    });
+                extra.insert("Response", response);
+                let prompt_tokens = self
+                    .create_instruct_prompt(&instruction, Some(&extra))
+                    .len();
+                if hidden == rows.len() || prompt_tokens + MAX_ROW_TOKENS <= MAX_TOKENS {
+                    break;
+                }
+                hidden += 1;
+            }
+            let row_config = GenerationConfig {
+                seed,
+                temp: config.temp.or(Some(0.8)),
+                max_tokens: Some(MAX_ROW_TOKENS),
+                stop: vec!["\n".to_string()],
+                ..config.clone()
+            };
+            seed = seed.wrapping_add(1);
+            let line = self.instruct_with(&instruction, Some(&extra), &row_config)?;
+
+            // Keep well-formed rows that aren't repeats
+            let Some(row) = parse_row(&line, columns.len()) else {
+                continue;
+            };
+            if seen.insert(row.values[0].to_lowercase()) {
+                rows.push(row);
+            }
+        }
+
+        if rows.is_empty() {
+            bail!("could not generate any rows for the table of {}", theme)
+        }
+        Ok(Table {
+            theme,
+            columns,
+            rows,
+        })
+    }
+}
+
+impl Model {
+    /// Generate `n` rows with a value for every column, such as (name, CR, terrain, treasure).
+    /// Rows whose first value repeats an earlier row are generated again.
+    /// Returns an error if a column has an empty range or no options, or the model doesn't answer a column
+    /// with a number in its range or one of its options.
+    pub fn generate_rows(
+        &self,
+        schema: &[Column],
+        n: usize,
+        config: &GenerationConfig,
+    ) -> Result<Vec<Row>> {
+        self.generate_rows_about("", schema, n, config)
+    }
+
+    /// Generate rows like `generate_rows` for a table about the topic, such as "forest encounters"
+    pub fn generate_rows_about(
+        &self,
+        topic: impl AsRef<str>,
+        schema: &[Column],
+        n: usize,
+        config: &GenerationConfig,
+    ) -> Result<Vec<Row>> {
+        let topic = topic.as_ref().trim();
+        check_fields(schema)?;
+        let mut rows: Vec<Row> = Vec::with_capacity(n);
+        if schema.is_empty() {
+            return Ok(rows);
+        }
+
+        let mut seen = HashSet::new();
+        let mut seed = config.seed;
+        for _ in 0..n.saturating_mul(ROW_ATTEMPTS_PER_ROW) {
+            if rows.len() >= n {
+                break;
+            }
+
+            // Show the earlier rows so the new one is different
+            let mut known = String::new();
+            if !topic.is_empty() {
+                known.push_str(&format!("Table: {}\n", topic));
+            }
+            for row in &rows {
+                let values = row.values.iter().map(|(_, value)| value).join(" | ");
+                known.push_str(&format!("Earlier entry: {}\n", values));
+            }
+
+            // Fill the columns in order, each knowing the ones before it
+            let mut row = Row::default();
+            for column in schema {
+                let mut entry = known.clone();
+                for (name, value) in &row.values {
+                    entry.push_str(&format!("{}: {}\n", name, value));
+                }
+                let value = self.fill_field("entry", &entry, column, seed, config)?;
+                seed = seed.wrapping_add(1);
+                row.values.push((column.name.clone(), value));
+            }
+
+            if seen.insert(row.values[0].1.to_string().to_lowercase()) {
+                rows.push(row);
+            }
+        }
+
+        Ok(rows)
+    }
+}
+
+/// Parse a generated line like "3 | goblin ambush | ..." into a row with the given number of values.
+/// Missing weights default to 1 and weights are clamped to `MAX_ROW_WEIGHT`.
+pub(crate) fn parse_row(line: &str, n_columns: usize) -> Option<TableRow> {
+    let mut cells = line
+        .trim()
+        .trim_matches('|')
+        .split('|')
+        .map(|cell| cell.trim().to_string())
+        .collect_vec();
+
+    // Read the weight, if the line has one
+    let weight = match cells.first().and_then(|cell| cell.parse::<u32>().ok()) {
+        Some(weight) => {
+            cells.remove(0);
+            weight.clamp(1, MAX_ROW_WEIGHT)
+        }
+        None => 1,
+    };
+
+    if cells.len() != n_columns || cells.iter().any(|cell| cell.is_empty()) {
+        return None;
+    }
+    Some(TableRow {
+        weight,
+        values: cells,
+    })
+}
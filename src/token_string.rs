@@ -1,5 +1,6 @@
-use std::{fmt::Display, slice::SliceIndex};
+use std::{collections::HashMap, fmt::Display, slice::SliceIndex};
 
+use crate::generation::GenerationConfig;
 use crate::model::Model;
 
 /// A string of tokens representing a sequence of text
@@ -80,6 +81,26 @@ impl TokenString {
         self.tokens
     }
 
+    /// Paraphrase the text into a new, shorter token string of at most `max_tokens` tokens
+    pub fn shortened(&self, max_tokens: usize, seed: u64) -> TokenString {
+        // Give the model the text to paraphrase
+        let mut extra = HashMap::new();
+        extra.insert("Text", self.to_string());
+
+        // Paraphrase until the next section or the token limit
+        let config = GenerationConfig::new(seed)
+            .with_temp(0.3)
+            .with_max_tokens(max_tokens)
+            .with_stop("###");
+        let text = self.model.instruct_with(
+            "Paraphrase the text more concisely while keeping every important detail.",
+            Some(&extra),
+            &config,
+        ).unwrap();
+
+        self.model.tokenize(text.trim())
+    }
+
     /// Decode the tokens into a new `String`
     pub fn to_string(&self) -> String {
         self.model.detokenize(&self.tokens)
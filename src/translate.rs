@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+
+/// Default maximum number of tokens in a translation, used when the config doesn't set one
+pub const MAX_TRANSLATION_TOKENS: usize = 256;
+/// Lowest probability that a round-trip translation means the same as the original for it to be consistent
+pub const MIN_ROUND_TRIP_AGREEMENT: f32 = 0.5;
+
+/// The result of `Model::translate_checked`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Translation {
+    pub text: String,
+    /// The translation translated back into the source language
+    pub round_trip: String,
+    /// Probability that the round trip means the same as the original text
+    pub agreement: f32,
+}
+
+impl Translation {
+    /// Whether the round trip means the same as the original, suggesting the translation is sound
+    pub fn is_consistent(&self) -> bool {
+        self.agreement >= MIN_ROUND_TRIP_AGREEMENT
+    }
+}
+
+impl Model {
+    /// Translate the text into the target language, such as "French".
+    /// The response is tagged with the language, like "[French] ...", to keep the model in that language.
+    /// Fails if there is no text to translate.
+    pub fn translate(
+        &self,
+        text: impl AsRef<str>,
+        target_lang: impl AsRef<str>,
+        config: &GenerationConfig,
+    ) -> Result<String> {
+        self.translate_from(text, None, target_lang.as_ref(), config)
+    }
+
+    /// Translate the text from the source language into the target language, then translate the result back
+    /// and ask the model whether the round trip means the same as the original, as a sanity check.
+    /// Fails like `translate` if either direction does.
+    pub fn translate_checked(
+        &self,
+        text: impl AsRef<str>,
+        source_lang: impl AsRef<str>,
+        target_lang: impl AsRef<str>,
+        config: &GenerationConfig,
+    ) -> Result<Translation> {
+        let (text, source_lang, target_lang) =
+            (text.as_ref(), source_lang.as_ref(), target_lang.as_ref());
+
+        // Translate there and back again
+        let translation = self.translate_from(text, Some(source_lang), target_lang, config)?;
+        let round_trip =
+            self.translate_from(&translation, Some(target_lang), source_lang, config)?;
+
+        // Compare the round trip with the original
+        let comparison = format!("Original: {}\nRound trip: {}", text, round_trip);
+        let agreement =
+            self.yes_probability(comparison, "Do the two texts mean the same thing?")?;
+
+        Ok(Translation {
+            text: translation,
+            round_trip,
+            agreement,
+        })
+    }
+
+    /// Translate the text, tagging it with the source language if known
+    fn translate_from(
+        &self,
+        text: impl AsRef<str>,
+        source_lang: Option<&str>,
+        target_lang: &str,
+        config: &GenerationConfig,
+    ) -> Result<String> {
+        let text = text.as_ref().trim();
+        if text.is_empty() {
+            bail!("cannot translate empty text")
+        }
+
+        // Tag the text and the response with their languages
+        let tagged_text = match source_lang {
+            Some(source_lang) => format!("[{}] {}", source_lang, text),
+            None => text.to_string(),
+        };
+        let response = format!("[{}]", target_lang);
+        let mut extra = HashMap::new();
+        extra.insert("Text", tagged_text.as_str());
+        extra.insert("Response", response.as_str());
+
+        // Translate until the end of the line unless the text has several lines
+        let mut config = config.clone();
+        config.max_tokens = Some(config.max_tokens.unwrap_or(MAX_TRANSLATION_TOKENS));
+        config.stop.push("###".to_string());
+        if !text.contains('\n') {
+            config.stop.push("\n".to_string());
+        }
+        let translation = self.instruct_with(
+            format!("Translate the text into {}.", target_lang),
+            Some(&extra),
+            &config,
+        )?;
+
+        Ok(translation.trim().to_string())
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt::Display;
+use std::ops::{Range, RangeInclusive};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::generation::GenerationConfig;
+use crate::model::Model;
+use crate::token_string::IntoTokenString;
+
+/// Placeholder in a tuning prompt template that is replaced by each input
+pub const INPUT_PLACEHOLDER: &str = "{input}";
+
+/// The parameter values a search is allowed to try
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterSpace {
+    /// Every parameter set is evaluated once per seed in this range
+    pub seeds: Range<u64>,
+    pub temps: RangeInclusive<f64>,
+    pub top_ps: RangeInclusive<f64>,
+}
+
+impl ParameterSpace {
+    /// Make sure every range has at least one value to try.
+    /// Returns an error if a range is reversed or empty, or a bound is NaN.
+    pub fn validate(&self) -> Result<()> {
+        if self.seeds.is_empty() {
+            bail!("the seed range {:?} is empty", self.seeds)
+        }
+        if self.temps.is_empty() {
+            bail!("the temperature range {:?} is empty", self.temps)
+        }
+        if self.top_ps.is_empty() {
+            bail!("the top_p range {:?} is empty", self.top_ps)
+        }
+        Ok(())
+    }
+}
+
+/// How parameter sets are picked from a `ParameterSpace`
+#[derive(Clone, Debug, PartialEq)]
+pub enum SearchStrategy {
+    /// Try every combination of `steps` evenly spaced temperatures and top_p values
+    Grid { steps: usize },
+    /// Try `samples` uniformly random combinations, using `seed` to pick them
+    Random { samples: usize, seed: u64 },
+}
+
+/// How well a single parameter set did
+#[derive(Clone, Debug, PartialEq)]
+pub struct TuningResult {
+    /// The config that was evaluated, with the seed set to the first seed of the space
+    pub config: GenerationConfig,
+    /// How many generations passed the validator
+    pub passed: usize,
+    /// How many generations were run
+    pub trials: usize,
+}
+
+impl TuningResult {
+    /// Get the fraction of generations that passed the validator
+    pub fn pass_rate(&self) -> f64 {
+        if self.trials == 0 {
+            0.0
+        } else {
+            self.passed as f64 / self.trials as f64
+        }
+    }
+}
+
+/// The results of a parameter search, best first
+#[derive(Clone, Debug, PartialEq)]
+pub struct TuningReport {
+    pub results: Vec<TuningResult>,
+}
+
+impl TuningReport {
+    /// Get the parameter set with the highest pass rate
+    pub fn best(&self) -> Option<&TuningResult> {
+        self.results.first()
+    }
+}
+
+impl Display for TuningReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:>8} {:>8} {:>10}", "temp", "top_p", "pass rate")?;
+        for result in &self.results {
+            writeln!(
+                f,
+                "{:>8.3} {:>8.3} {:>9.1}% ({}/{})",
+                result.config.temp.unwrap_or(0.0),
+                result.config.top_p.unwrap_or(1.0),
+                result.pass_rate() * 100.0,
+                result.passed,
+                result.trials,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The output of one config in a `Model::compare` run
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComparisonRow {
+    /// The config that was run, with the shared seed applied
+    pub config: GenerationConfig,
+    pub output: String,
+    /// Number of tokens the model generated for the output, including any that ended in a stop string
+    pub tokens: usize,
+    /// Time spent generating the output
+    pub elapsed: Duration,
+}
+
+impl ComparisonRow {
+    /// Get the generation speed in tokens per second
+    pub fn tokens_per_second(&self) -> f64 {
+        self.tokens as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl Display for ComparisonRow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "temp={:?} top_p={:?} repeat_penalty={} ({} tokens, {:.1} tokens/s): {:?}",
+            self.config.temp,
+            self.config.top_p,
+            self.config.repeat_penalty,
+            self.tokens,
+            self.tokens_per_second(),
+            self.output,
+        )
+    }
+}
+
+impl Model {
+    /// Run the same prompt with each of the configs and return the outputs side by side.
+    /// Every config is run with the seed of the first config so only the other parameters differ.
+    pub fn compare(
+        &self,
+        prompt: impl IntoTokenString,
+        configs: &[GenerationConfig],
+    ) -> Result<Vec<ComparisonRow>> {
+        let prompt = self.tokenize(prompt);
+        let seed = configs.first().map_or(0, |config| config.seed);
+
+        configs
+            .iter()
+            .map(|config| {
+                let config = config.clone().with_seed(seed);
+
+                // Generate and time the output
+                let start = Instant::now();
+                let completion = self.generate_streamed(&prompt, &config, |_| true)?;
+                let elapsed = start.elapsed();
+
+                Ok(ComparisonRow {
+                    tokens: completion.completion_tokens,
+                    config,
+                    output: completion.text,
+                    elapsed,
+                })
+            })
+            .collect()
+    }
+
+    /// Search for the sampling parameters that make the most generations pass `validator`.
+    /// Every `{input}` in `template` is replaced by each of the `inputs` to build the prompts,
+    /// and the validator is given the input and the generated text.
+    /// Parameters not covered by `space` are taken from `base`.
+    /// Returns an error if a range in `space` is empty.
+    pub fn tune(
+        &self,
+        template: impl AsRef<str>,
+        inputs: &[impl AsRef<str>],
+        validator: impl Fn(&str, &str) -> bool,
+        space: &ParameterSpace,
+        strategy: &SearchStrategy,
+        base: &GenerationConfig,
+    ) -> Result<TuningReport> {
+        space.validate()?;
+
+        // Build the prompts, using the template as-is if there are no inputs
+        let prompts: Vec<(&str, String)> = if inputs.is_empty() {
+            vec![("", template.as_ref().to_string())]
+        } else {
+            inputs
+                .iter()
+                .map(|input| {
+                    let input = input.as_ref();
+                    (input, template.as_ref().replace(INPUT_PLACEHOLDER, input))
+                })
+                .collect()
+        };
+
+        // Pick the temperature and top_p combinations to try
+        let candidates: Vec<(f64, f64)> = match strategy {
+            SearchStrategy::Grid { steps } => {
+                let temps = grid_values(&space.temps, *steps);
+                let top_ps = grid_values(&space.top_ps, *steps);
+                temps
+                    .iter()
+                    .flat_map(|temp| top_ps.iter().map(move |top_p| (*temp, *top_p)))
+                    .collect()
+            }
+            SearchStrategy::Random { samples, seed } => {
+                let mut rng = StdRng::seed_from_u64(*seed);
+                (0..*samples)
+                    .map(|_| {
+                        (
+                            rng.gen_range(space.temps.clone()),
+                            rng.gen_range(space.top_ps.clone()),
+                        )
+                    })
+                    .collect()
+            }
+        };
+
+        // Evaluate every candidate on every prompt and seed
+        let mut results = Vec::with_capacity(candidates.len());
+        for (temp, top_p) in candidates {
+            let config = base
+                .clone()
+                .with_seed(space.seeds.start)
+                .with_temp(temp)
+                .with_top_p(top_p);
+
+            let mut passed = 0;
+            let mut trials = 0;
+            for seed in space.seeds.clone() {
+                let config = config.clone().with_seed(seed);
+                for (input, prompt) in &prompts {
+                    let output = self.generate(prompt, &config)?;
+                    if validator(input, &output) {
+                        passed += 1;
+                    }
+                    trials += 1;
+                }
+            }
+
+            results.push(TuningResult {
+                config,
+                passed,
+                trials,
+            });
+        }
+
+        // Sort the results so the best parameter sets come first
+        results.sort_by(|a, b| b.pass_rate().total_cmp(&a.pass_rate()));
+
+        Ok(TuningReport { results })
+    }
+}
+
+/// Get `steps` evenly spaced values covering the range
+fn grid_values(range: &RangeInclusive<f64>, steps: usize) -> Vec<f64> {
+    match steps {
+        0 => Vec::new(),
+        1 => vec![*range.start()],
+        _ => (0..steps)
+            .map(|i| range.start() + (range.end() - range.start()) * i as f64 / (steps - 1) as f64)
+            .collect(),
+    }
+}
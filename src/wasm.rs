@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::crafter::{Crafter, Theme};
+use crate::generation::GenerationConfig;
+use crate::model::{InferIter, Model, TokenDecoder};
+
+fn js_error(error: anyhow::Error) -> JsError {
+    JsError::new(&error.to_string())
+}
+
+/// `GenerationConfig` for JavaScript, built with the same `with*` methods
+#[wasm_bindgen(js_name = GenerationConfig)]
+#[derive(Clone, Default)]
+pub struct JsGenerationConfig(GenerationConfig);
+
+#[wasm_bindgen(js_class = GenerationConfig)]
+impl JsGenerationConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u64) -> Self {
+        Self(GenerationConfig::new(seed))
+    }
+
+    #[wasm_bindgen(js_name = withTemp)]
+    pub fn with_temp(self, temp: Option<f64>) -> Self {
+        Self(self.0.with_temp(temp))
+    }
+
+    #[wasm_bindgen(js_name = withTopP)]
+    pub fn with_top_p(self, top_p: Option<f64>) -> Self {
+        Self(self.0.with_top_p(top_p))
+    }
+
+    #[wasm_bindgen(js_name = withRepeatPenalty)]
+    pub fn with_repeat_penalty(self, repeat_penalty: f32, repeat_last_n: usize) -> Self {
+        Self(self.0.with_repeat_penalty(repeat_penalty, repeat_last_n))
+    }
+
+    #[wasm_bindgen(js_name = withMaxTokens)]
+    pub fn with_max_tokens(self, max_tokens: Option<usize>) -> Self {
+        Self(self.0.with_max_tokens(max_tokens))
+    }
+
+    #[wasm_bindgen(js_name = withStop)]
+    pub fn with_stop(self, stop: String) -> Self {
+        Self(self.0.with_stop(stop))
+    }
+
+    #[wasm_bindgen(js_name = withBannedTokens)]
+    pub fn with_banned_tokens(self, tokens: Vec<u32>) -> Self {
+        Self(self.0.with_banned_tokens(tokens))
+    }
+}
+
+/// `Model` for JavaScript. It runs on the CPU and is loaded from the bytes of the model and
+/// tokenizer files, which the page fetches itself.
+#[wasm_bindgen(js_name = Model)]
+pub struct JsModel(Model);
+
+#[wasm_bindgen(js_class = Model)]
+impl JsModel {
+    /// Load the model from the contents of `MODEL_FILE` and `TOKENIZER_FILE`
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        model_bytes: Vec<u8>,
+        tokenizer_bytes: Vec<u8>,
+        seed: u64,
+    ) -> Result<JsModel, JsError> {
+        Model::from_bytes(model_bytes, tokenizer_bytes, seed)
+            .map(Self)
+            .map_err(js_error)
+    }
+
+    pub fn tokenize(&self, text: &str) -> Vec<u32> {
+        self.0.tokenize_str(text).into_vec()
+    }
+
+    pub fn detokenize(&self, tokens: Vec<u32>) -> String {
+        self.0.detokenize(tokens)
+    }
+
+    /// Get an iterator over the tokens generated after the prompt
+    pub fn infer(&self, prompt: &str, config: &JsGenerationConfig) -> Result<JsInferIter, JsError> {
+        let iter = self.0.infer_with(prompt, &config.0).map_err(js_error)?;
+        Ok(JsInferIter {
+            model: self.0.clone(),
+            iter,
+            decoder: TokenDecoder::default(),
+            sent: 0,
+        })
+    }
+
+    /// Continue the prompt and return the generated text, respecting `maxTokens` and `stop`
+    pub fn generate(&self, prompt: &str, config: &JsGenerationConfig) -> Result<String, JsError> {
+        self.0.generate(prompt, &config.0).map_err(js_error)
+    }
+
+    /// Follow the instruction and return the response, respecting `maxTokens` and `stop`
+    pub fn instruct(
+        &self,
+        instruction: &str,
+        config: &JsGenerationConfig,
+    ) -> Result<String, JsError> {
+        self.0
+            .instruct_with(instruction, None::<&HashMap<&str, &str>>, &config.0)
+            .map_err(js_error)
+    }
+
+    /// Craft a new item from the items with a crafter of the theme, such as "alchemy"
+    pub fn craft(&self, items: Vec<String>, theme: &str, seed: u64) -> Result<String, JsError> {
+        let theme = theme.parse::<Theme>().map_err(js_error)?;
+        Crafter::themed(self.0.clone(), theme)
+            .craft_item(&items, seed)
+            .map_err(js_error)
+    }
+}
+
+/// `InferIter` for JavaScript
+#[wasm_bindgen(js_name = InferIter)]
+pub struct JsInferIter {
+    model: Model,
+    iter: InferIter,
+    /// Decodes the generated tokens without decoding all of them again each time
+    decoder: TokenDecoder,
+    /// Length of the decoded text already returned by `nextText`
+    sent: usize,
+}
+
+#[wasm_bindgen(js_class = InferIter)]
+impl JsInferIter {
+    /// Generate the next token, or undefined once the model is finished
+    #[wasm_bindgen(js_name = nextToken)]
+    pub fn next_token(&mut self) -> Option<u32> {
+        let token = self.iter.next()?;
+        self.decoder.push(&self.model, token);
+        Some(token)
+    }
+
+    /// Generate the next token and return the text it completes, which is empty while a character
+    /// is split between tokens. Returns undefined once the model is finished.
+    #[wasm_bindgen(js_name = nextText)]
+    pub fn next_text(&mut self) -> Option<String> {
+        self.next_token()?;
+        let text = self.decoder.text();
+        let new_text = text[self.sent..].to_string();
+        self.sent = text.len();
+        Some(new_text)
+    }
+}